@@ -1,20 +1,87 @@
 use core::fmt;
-use std::rc::Rc;
+use std::{cell::RefCell, rc::Rc};
 
 use crate::fastpass::{
     self, CaptureWhile, Either, ErrorMessage, Infallible, ParseResult, Parser, View,
 };
-use crate::interpreter::Value;
+use crate::interpreter::{List, Symbol, Value};
 
-const SYMBOL_ILLEGALS: &[char] = &[' ', '\r', '\n', '\t', '(', ')', ';'];
+/// the source a datum was read from,
+/// values created while evaluating have no span
+pub type Span = Option<Location>;
+
+/// a whole source which has been read, shared by everything read from it
+struct Source {
+    name: Box<str>,
+    text: Box<str>,
+}
+
+/// the part of a source a datum was read from. it holds on to a copy of the source,
+/// so values can outlive the buffer they were read from
+#[derive(Clone)]
+pub struct Location {
+    source: Rc<Source>,
+    start: usize,
+    end: usize,
+}
+
+impl Location {
+    /// a view of this location within its source
+    pub fn view(&self) -> View<'_> {
+        View::named(&self.source.name, &self.source.text).sub_view(self.start..self.end)
+    }
+}
+
+impl fmt::Debug for Location {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?}", self.view())
+    }
+}
+
+impl ErrorMessage for Location {
+    fn display(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.view().display(f)
+    }
+}
+
+thread_local! {
+    /// the source being read by [sl], so that the spans read from it share one copy of it
+    static READING: RefCell<Option<Rc<Source>>> = const { RefCell::new(None) };
+}
+
+/// the span of a datum read from `view`
+fn span(view: View) -> Span {
+    let source = READING.with_borrow(Option::clone).unwrap_or_else(|| {
+        Rc::new(Source {
+            name: view.name().into(),
+            text: view.source().into(),
+        })
+    });
+    Some(Location {
+        source,
+        start: view.start,
+        end: view.end,
+    })
+}
+
+const SYMBOL_ILLEGALS: &[char] = &[' ', '\r', '\n', '\t', '(', ')', ';', '"', '\'', '`', ','];
+
+/// errors in the input which no other parser could recover from
+pub type Malformed<'buf> = Either<
+    UnclosedSExpr<'buf>,
+    Either<
+        UnclosedString<'buf>,
+        Either<InvalidEscape<'buf>, Either<DanglingQuote<'buf>, InvalidNumber<'buf>>>,
+    >,
+>;
 
 #[inline(always)]
-fn symbol<'buf>(buf: View<'buf>) -> ParseResult<'buf, Value<'buf>, NoSymbol<'buf>> {
-    let Ok((buf, res)) =
+fn symbol<'buf>(buf: View<'buf>) -> ParseResult<'buf, Value, NoSymbol<'buf>> {
+    let Ok((rest, res)) =
         fastpass::CaptureWhile(|_, char| !SYMBOL_ILLEGALS.contains(&char)).parse(buf);
     match res {
-        "" => Err(NoSymbol(buf)),
-        x => Ok((buf, Value::Symbol(x))),
+        "" => Err(NoSymbol(rest)),
+        x => Ok((rest, Value::Symbol(Symbol::new(x), span(buf.up_to(rest))))),
     }
 }
 
@@ -27,11 +94,229 @@ fn symbol_test() {
     let (buf, res) = res.unwrap();
     assert_eq!("", buf.as_str());
     match res {
-        Value::Symbol(res) => assert_eq!("abc", res),
+        Value::Symbol(res, Some(span)) => {
+            assert_eq!("abc", &*res.name());
+            assert_eq!("abc", span.view().as_str());
+        }
         _ => panic!(),
     }
 }
 
+/// numbers are read from the same tokens as symbols, tokens which are not valid numbers
+/// are symbols instead, unless they look like numbers, such as `#b102` or `1.2.3`
+#[inline(always)]
+fn number<'buf>(
+    buf: View<'buf>,
+) -> ParseResult<'buf, Value, Either<NoNumber<'buf>, Malformed<'buf>>> {
+    let Ok((rest, res)) =
+        fastpass::CaptureWhile(|_, char| !SYMBOL_ILLEGALS.contains(&char)).parse(buf);
+    match read_number(res, 10) {
+        Some(number) => Ok((rest, number)),
+        None if numeric(res) => {
+            let (radix, digits) = prefixed(res, 10);
+            let reason = match integer(digits, radix) {
+                true => "integer too large for 64 bits",
+                false => "malformed number",
+            };
+            let invalid = InvalidNumber(buf.up_to(rest), reason);
+            Err(Either::R(Either::R(Either::R(Either::R(Either::R(invalid))))))
+        }
+        None => Err(Either::L(NoNumber(buf))),
+    }
+}
+
+/// whether `token` is meant as a number: it has a radix prefix, or it is made of
+/// digits, `.` and exponents, starting with a digit or a `.` followed by one
+fn numeric(token: &str) -> bool {
+    if prefixed(token, 0).1.len() < token.len() {
+        return true;
+    }
+    let unsigned = token.strip_prefix(['+', '-']).unwrap_or(token);
+    let mut chars = unsigned.chars();
+    let starts = match chars.next() {
+        Some('.') => chars.next().is_some_and(|c| c.is_ascii_digit()),
+        Some(c) => c.is_ascii_digit(),
+        None => false,
+    };
+    let mut previous = ' ';
+    starts
+        && unsigned.chars().all(|c| {
+            let part = match c {
+                '+' | '-' => matches!(previous, 'e' | 'E'),
+                c => c.is_ascii_digit() || matches!(c, '.' | 'e' | 'E'),
+            };
+            previous = c;
+            part
+        })
+}
+
+/// reads `token` as a number in `radix`, unless it has a radix prefix such as `#x`.
+/// integers which do not fit in 64 bits are not numbers
+pub fn read_number(token: &str, radix: u32) -> Option<Value> {
+    let (radix, digits) = prefixed(token, radix);
+    if integer(digits, radix) {
+        return i64::from_str_radix(digits, radix).ok().map(Value::Int);
+    }
+    if radix != 10 {
+        return None;
+    }
+    match digits {
+        // as the printer writes them
+        "+inf.0" => Some(Value::Float(f64::INFINITY)),
+        "-inf.0" => Some(Value::Float(f64::NEG_INFINITY)),
+        "+nan.0" | "-nan.0" => Some(Value::Float(f64::NAN)),
+        _ if decimal(digits.strip_prefix(['+', '-']).unwrap_or(digits)) => digits.parse().ok().map(Value::Float),
+        _ => None,
+    }
+}
+
+/// the radix of `token` and its digits, without its radix prefix such as `#x`,
+/// or `radix` and `token` itself when it has none
+fn prefixed(token: &str, radix: u32) -> (u32, &str) {
+    match token.get(..2) {
+        Some("#x" | "#X") => (16, &token[2..]),
+        Some("#b" | "#B") => (2, &token[2..]),
+        Some("#o" | "#O") => (8, &token[2..]),
+        Some("#d" | "#D") => (10, &token[2..]),
+        _ => (radix, token),
+    }
+}
+
+/// digits in `radix` with an optional sign
+fn integer(digits: &str, radix: u32) -> bool {
+    let unsigned = digits.strip_prefix(['+', '-']).unwrap_or(digits);
+    !unsigned.is_empty() && unsigned.chars().all(|c| c.is_digit(radix))
+}
+
+/// digits with an optional fractional part and exponent,
+/// such as `1.5`, `.5`, `1.` or `15e-1`
+fn decimal(unsigned: &str) -> bool {
+    let (mantissa, exponent) = match unsigned.split_once(['e', 'E']) {
+        Some((mantissa, exponent)) => (mantissa, Some(exponent)),
+        None => (unsigned, None),
+    };
+    let digits = |str: &str| str.chars().all(|c| c.is_ascii_digit());
+    let mantissa = match mantissa.split_once('.') {
+        Some((int, frac)) => digits(int) && digits(frac) && !(int.is_empty() && frac.is_empty()),
+        None => !mantissa.is_empty() && digits(mantissa),
+    };
+    let exponent = match exponent {
+        Some(exponent) => {
+            let exponent = exponent.strip_prefix(['+', '-']).unwrap_or(exponent);
+            !exponent.is_empty() && digits(exponent)
+        }
+        None => true,
+    };
+    mantissa && exponent
+}
+
+#[test]
+fn number_test() {
+    let read = |str| match number(View::new(str)) {
+        Ok((_, value)) => Some(value),
+        Err(_) => None,
+    };
+    let invalid = |str| matches!(number(View::new(str)), Err(Either::R(_)));
+    assert!(matches!(read("42"), Some(Value::Int(42))));
+    assert!(matches!(read("-7"), Some(Value::Int(-7))));
+    assert!(matches!(read("#xff"), Some(Value::Int(255))));
+    assert!(matches!(read("#b-101"), Some(Value::Int(-5))));
+    assert!(matches!(read("1.5"), Some(Value::Float(1.5))));
+    assert!(matches!(read(".5"), Some(Value::Float(0.5))));
+    assert!(matches!(read("15e-1"), Some(Value::Float(1.5))));
+    assert!(matches!(read("1e3"), Some(Value::Float(1000.0))));
+    assert!(read("+").is_none());
+    assert!(read("1+").is_none());
+    assert!(read("inf").is_none());
+    assert!(read("#xfg").is_none());
+    assert!(read(".").is_none());
+    assert!(matches!(read("+inf.0"), Some(Value::Float(f)) if f == f64::INFINITY));
+    assert!(matches!(read("-inf.0"), Some(Value::Float(f)) if f == f64::NEG_INFINITY));
+    assert!(matches!(read("+nan.0"), Some(Value::Float(f)) if f.is_nan()));
+    assert!(matches!(read("9223372036854775807"), Some(Value::Int(i64::MAX))));
+    assert!(invalid("9223372036854775808"));
+    assert!(invalid("#b102"));
+    assert!(invalid("#x"));
+    assert!(invalid("#xfg"));
+    assert!(invalid("1.2.3"));
+    assert!(invalid("1e"));
+    assert!(!invalid("1+"));
+    assert!(!invalid("..."));
+    assert!(!invalid("-"));
+}
+
+#[inline(always)]
+fn string<'buf>(
+    buf: View<'buf>,
+) -> ParseResult<'buf, Value, Either<NoString<'buf>, Malformed<'buf>>> {
+    let Ok((body, _)) = Parser::parse(&"\"", buf) else {
+        return Err(Either::L(NoString(buf)));
+    };
+    let mut value = String::new();
+    let mut chars = body.as_str().char_indices();
+    while let Some((i, char)) = chars.next() {
+        match char {
+            '"' => return Ok((body.sub_view(i + 1..), Value::String(Rc::from(value)))),
+            '\\' => match escape(&mut chars) {
+                Some(char) => value.push(char),
+                None => {
+                    let invalid = InvalidEscape(body.sub_view(i..));
+                    return Err(Either::R(Either::R(Either::R(Either::L(invalid)))));
+                }
+            },
+            char => value.push(char),
+        }
+    }
+    Err(Either::R(Either::R(Either::L(UnclosedString(buf)))))
+}
+
+/// reads the escape sequence following a `\`, such as `\n` or `\u{3bb}`
+fn escape(chars: &mut std::str::CharIndices) -> Option<char> {
+    match chars.next()?.1 {
+        'n' => Some('\n'),
+        't' => Some('\t'),
+        'r' => Some('\r'),
+        '0' => Some('\0'),
+        '\\' => Some('\\'),
+        '"' => Some('"'),
+        'u' => {
+            if chars.next()?.1 != '{' {
+                return None;
+            }
+            let mut code = String::new();
+            loop {
+                match chars.next()?.1 {
+                    '}' => break,
+                    digit => code.push(digit),
+                }
+            }
+            char::from_u32(u32::from_str_radix(&code, 16).ok()?)
+        }
+        _ => None,
+    }
+}
+
+#[test]
+fn string_test() {
+    let read = |str| match string(View::new(str)) {
+        Ok((rest, Value::String(value))) => Some((value.to_string(), rest.as_str())),
+        _ => None,
+    };
+    assert_eq!(Some(("a b".to_string(), " c")), read("\"a b\" c"));
+    assert_eq!(
+        Some(("\"\t\n\\λ".to_string(), "")),
+        read(r#""\"\t\n\\\u{3bb}""#)
+    );
+    assert!(matches!(
+        string(View::new("\"abc")),
+        Err(Either::R(Either::R(Either::L(_))))
+    ));
+    assert!(matches!(
+        string(View::new(r#""\q""#)),
+        Err(Either::R(Either::R(Either::R(Either::L(_)))))
+    ));
+}
+
 #[inline(always)]
 fn comment<'buf>(buf: View<'buf>) -> ParseResult<'buf, (), Infallible> {
     match ";".then(CaptureWhile(|_, c| c != '\n')).parse(buf) {
@@ -73,7 +358,7 @@ fn swallow<'buf>(mut buf: View<'buf>) -> ParseResult<'buf, (), Infallible> {
 }
 
 #[inline(always)]
-fn bool<'buf>(buf: View<'buf>) -> ParseResult<'buf, Value<'buf>, NoBool<'buf>> {
+fn bool<'buf>(buf: View<'buf>) -> ParseResult<'buf, Value, NoBool<'buf>> {
     match "#t".or("#f").parse(buf) {
         Ok((buf, Either::L(_))) => Ok((buf, Value::Bool(true))),
         Ok((buf, Either::R(_))) => Ok((buf, Value::Bool(false))),
@@ -84,52 +369,134 @@ fn bool<'buf>(buf: View<'buf>) -> ParseResult<'buf, Value<'buf>, NoBool<'buf>> {
 #[inline(always)]
 fn sexpr<'buf>(
     buf: View<'buf>,
-) -> ParseResult<'buf, Value<'buf>, Either<NoSExpr<'buf>, UnclosedSExpr<'buf>>> {
+) -> ParseResult<'buf, Value, Either<NoSExpr<'buf>, Malformed<'buf>>> {
     let open = "(".map_err(|(buf, _, _)| Err(Either::L(NoSExpr(buf))));
-    let close = ")".map_err(|(buf, _, _)| Err(Either::R(UnclosedSExpr(buf))));
+    let close = ")".map_err(|(buf, _, _)| Err(Either::R(Either::L(UnclosedSExpr(buf)))));
 
+    let start = buf;
     let (buf, _) = open.parse(buf)?;
 
     let Ok((buf, (exprs, Either::L(err)))) = expr.then_left(swallow).greedy().parse(buf);
 
-    let exprs = match err {
-        unclosed_sexpr @ Either::R(_) => return Err(unclosed_sexpr),
-        _ => Value::List(Rc::from(exprs)),
-    };
+    if let malformed @ Either::R(_) = err {
+        return Err(malformed);
+    }
 
     let (buf, _) = close.parse(buf)?;
-    let Ok((buf, _)) = swallow.parse(buf);
-    Ok((buf, exprs))
+    Ok((buf, Value::List(List::from(exprs), span(start.up_to(buf)))))
 }
 
+/// `'x`, `` `x ``, `,x` and `,@x` read as
+/// `(quote x)`, `(quasiquote x)`, `(unquote x)` and `(unquote-splicing x)`
 #[inline(always)]
-fn expr<'buf>(
+fn quoted<'buf>(
+    buf: View<'buf>,
+) -> ParseResult<'buf, Value, Either<NoQuote<'buf>, Malformed<'buf>>> {
+    let (name, len) = match buf.as_str() {
+        str if str.starts_with(",@") => ("unquote-splicing", 2),
+        str if str.starts_with(',') => ("unquote", 1),
+        str if str.starts_with('\'') => ("quote", 1),
+        str if str.starts_with('`') => ("quasiquote", 1),
+        _ => return Err(Either::L(NoQuote(buf))),
+    };
+    let prefix = buf.sub_view(..len);
+    let Ok((rest, _)) = swallow.parse(buf.sub_view(len..));
+    match datum(rest) {
+        Ok((rest, value)) => {
+            let symbol = Value::Symbol(Symbol::new(name), span(prefix));
+            let list = Value::List(List::from([symbol, value]), span(buf.up_to(rest)));
+            Ok((rest, list))
+        }
+        Err(Either::R(malformed)) => Err(Either::R(malformed)),
+        Err(Either::L(_)) => {
+            let dangling = DanglingQuote(prefix);
+            Err(Either::R(Either::R(Either::R(Either::R(Either::L(dangling))))))
+        }
+    }
+}
+
+#[test]
+fn quoted_test() {
+    let read = |str| match quoted(View::new(str)) {
+        Ok((rest, value)) => Some((value.to_string(), rest.as_str())),
+        _ => None,
+    };
+    assert_eq!(Some(("'x".to_string(), " y")), read("'x y"));
+    assert_eq!(Some(("`(a ,b ,@c)".to_string(), "")), read("`(a ,b ,@c)"));
+    assert_eq!(Some(("''x".to_string(), "")), read("' 'x"));
+    match quoted(View::new("'(a b) c")) {
+        Ok((_, Value::List(exprs, Some(span)))) => {
+            assert_eq!("'(a b)", span.view().as_str());
+            assert!(matches!(exprs[0], Value::Symbol(quote, _) if &*quote.name() == "quote"));
+        }
+        _ => panic!(),
+    }
+    assert!(matches!(
+        quoted(View::new("')")),
+        Err(Either::R(Either::R(Either::R(Either::R(Either::L(_))))))
+    ));
+}
+
+/// a single datum, without the whitespace and comments after it
+#[inline(always)]
+fn datum<'buf>(
     buf: View<'buf>,
-) -> ParseResult<'buf, Value<'buf>, Either<NoSExpr<'buf>, UnclosedSExpr<'buf>>> {
-    match bool.then_left(swallow).parse(buf) {
+) -> ParseResult<'buf, Value, Either<NoSExpr<'buf>, Malformed<'buf>>> {
+    match string.parse(buf) {
         Ok(res) => return Ok(res),
-        _ => (),
+        Err(Either::R(malformed)) => return Err(Either::R(malformed)),
+        Err(_) => (),
     };
-    match symbol.then_left(swallow).parse(buf) {
+    match quoted.parse(buf) {
         Ok(res) => return Ok(res),
-        _ => (),
+        Err(Either::R(malformed)) => return Err(Either::R(malformed)),
+        Err(_) => (),
     };
-    match sexpr.then_left(swallow).parse(buf) {
+    if let Ok(res) = bool.parse(buf) {
+        return Ok(res);
+    };
+    match number.parse(buf) {
+        Ok(res) => return Ok(res),
+        Err(Either::R(malformed)) => return Err(Either::R(malformed)),
+        Err(_) => (),
+    };
+    if let Ok(res) = symbol.parse(buf) {
+        return Ok(res);
+    };
+    sexpr.parse(buf)
+}
+
+#[inline(always)]
+fn expr<'buf>(
+    buf: View<'buf>,
+) -> ParseResult<'buf, Value, Either<NoSExpr<'buf>, Malformed<'buf>>> {
+    match datum.then_left(swallow).parse(buf) {
         Ok(res) => Ok(res),
         Err(Either::L(err)) => Err(err),
     }
 }
 
 #[inline(always)]
-pub fn sl<'buf>(
-    buf: View<'buf>,
-) -> Result<Value<'buf>, Either<UnclosedSExpr<'buf>, UnexpectedToken<'buf>>> {
+pub fn sl<'buf>(buf: View<'buf>) -> Result<Value, Either<Malformed<'buf>, UnexpectedToken<'buf>>> {
+    let source = Source {
+        name: buf.name().into(),
+        text: buf.source().into(),
+    };
+    READING.set(Some(Rc::new(source)));
+    let program = program(buf);
+    READING.set(None);
+    program
+}
+
+/// every datum in `buf`, as a list
+fn program<'buf>(buf: View<'buf>) -> Result<Value, Either<Malformed<'buf>, UnexpectedToken<'buf>>> {
     let Ok((buf, _)) = swallow.parse(buf);
 
+    let start = buf;
     let Ok((buf, (exprs, err))) = expr.greedy().parse(buf);
     let exprs = match err {
         Either::R(err) => return Err(Either::L(err)),
-        _ => Value::List(Rc::from(exprs)),
+        _ => Value::List(List::from(exprs), span(start.up_to(buf))),
     };
 
     match buf.as_str() {
@@ -156,6 +523,15 @@ impl<'buf> ErrorMessage for NoBool<'buf> {
     }
 }
 
+#[derive(Debug)]
+pub struct NoNumber<'buf>(View<'buf>);
+impl<'buf> ErrorMessage for NoNumber<'buf> {
+    fn display(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.display(f)?;
+        write!(f, "expected number")
+    }
+}
+
 #[derive(Debug)]
 pub struct NoSymbol<'buf>(View<'buf>);
 impl<'buf> ErrorMessage for NoSymbol<'buf> {
@@ -183,6 +559,60 @@ impl<'buf> ErrorMessage for UnclosedSExpr<'buf> {
     }
 }
 
+#[derive(Debug)]
+pub struct NoString<'buf>(View<'buf>);
+impl<'buf> ErrorMessage for NoString<'buf> {
+    fn display(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.display(f)?;
+        write!(f, "expected '\"'")
+    }
+}
+
+#[derive(Debug)]
+pub struct UnclosedString<'buf>(View<'buf>);
+impl<'buf> ErrorMessage for UnclosedString<'buf> {
+    fn display(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.display(f)?;
+        write!(f, "missing '\"', unclosed string")
+    }
+}
+
+#[derive(Debug)]
+pub struct InvalidEscape<'buf>(View<'buf>);
+impl<'buf> ErrorMessage for InvalidEscape<'buf> {
+    fn display(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.display(f)?;
+        write!(f, "invalid escape sequence in string")
+    }
+}
+
+#[derive(Debug)]
+pub struct NoQuote<'buf>(View<'buf>);
+impl<'buf> ErrorMessage for NoQuote<'buf> {
+    fn display(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.display(f)?;
+        write!(f, "expected one of ', `, , or ,@")
+    }
+}
+
+#[derive(Debug)]
+pub struct DanglingQuote<'buf>(View<'buf>);
+impl<'buf> ErrorMessage for DanglingQuote<'buf> {
+    fn display(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.display(f)?;
+        write!(f, "expected an expression to quote")
+    }
+}
+
+#[derive(Debug)]
+pub struct InvalidNumber<'buf>(View<'buf>, &'static str);
+impl<'buf> ErrorMessage for InvalidNumber<'buf> {
+    fn display(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.display(f)?;
+        write!(f, "{}", self.1)
+    }
+}
+
 #[derive(Debug)]
 pub struct UnexpectedToken<'buf>(View<'buf>);
 impl<'buf> ErrorMessage for UnexpectedToken<'buf> {
@@ -191,3 +621,16 @@ impl<'buf> ErrorMessage for UnexpectedToken<'buf> {
         write!(f, "unexpected token")
     }
 }
+
+#[test]
+fn sexpr_span_test() {
+    let buf = View::new("(a (b c))  ; comment");
+    let (_, res) = sexpr(buf).ok().unwrap();
+    match res {
+        Value::List(exprs, Some(span)) => {
+            assert_eq!("(a (b c))", span.view().as_str());
+            assert!(matches!(&exprs[1], Value::List(_, Some(span)) if span.view().as_str() == "(b c)"));
+        }
+        _ => panic!(),
+    }
+}
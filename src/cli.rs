@@ -0,0 +1,274 @@
+use core::str::FromStr;
+
+use crate::interpreter::{Capabilities, Capability, Engine, Limits};
+
+pub const USAGE: &str = "usage:
+    sl [OPTION...]                  start the repl
+    sl [run] [OPTION...] INPUT... [-- ARG...]
+                                    run each input in turn, in one top level
+    sl expand [OPTION...] INPUT...  print each input with its macros expanded
+
+inputs:
+    FILE                            a script to read
+    -                               read a script from stdin
+    -e EXPR, --eval EXPR            evaluate EXPR
+
+options:
+    --print-all                     print the value of every top level form, for run
+    --quiet                         print no values, only errors, for run
+    --engine=ENGINE                 evaluate with ENGINE, tree (the default) or vm
+    --fuel=N                        stop with an error after N calls
+    --max-depth=N                   stop with an error when calls nest more than N deep
+    --max-cells=N                   stop with an error after allocating N list cells,
+                                    where strings and new symbols count by their size
+    --allow=CAP,...                 grant the script only core and each CAP
+    --deny=CAP,...                  take each CAP away from the script
+    -h, --help                      print this message
+
+ARG... is given to the script by (command-line)
+
+capabilities, the groups of builtins a script can be granted, all by default:
+    core                            the language itself, always granted
+    io                              display, write and newline
+    fs                              the filesystem
+    process                         command-line
+    net                             the network
+    eval                            eval
+
+exit codes:
+    0 success, 1 evaluation error, 2 usage error, 3 syntax error, 4 unreadable input";
+
+/// where to read a script from
+#[derive(Debug, PartialEq)]
+pub enum Input {
+    File(String),
+    Stdin,
+    Expr(String),
+}
+
+/// which values of the top level forms are printed
+#[derive(Debug, PartialEq)]
+pub enum Print {
+    Last,
+    All,
+    Nothing,
+}
+
+/// how the session the inputs are evaluated in is set up
+#[derive(Debug, Default, PartialEq)]
+pub struct Options {
+    pub engine: Engine,
+    pub limits: Limits,
+    pub capabilities: Capabilities,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Command {
+    Repl(Options),
+    Help,
+    Run {
+        inputs: Vec<Input>,
+        args: Vec<String>,
+        print: Print,
+        options: Options,
+    },
+    /// expands the macros in each input, without running it
+    Expand {
+        inputs: Vec<Input>,
+        options: Options,
+    },
+}
+
+/// parses the arguments following the program name
+pub fn parse(args: impl IntoIterator<Item = String>) -> Result<Command, String> {
+    let mut args = args.into_iter().peekable();
+    let command = match args.peek().map(String::as_str) {
+        Some(command @ ("run" | "expand")) => {
+            let command = Some(command.to_string());
+            args.next();
+            command
+        }
+        _ => None,
+    };
+
+    let mut inputs = Vec::new();
+    // the option which set print, which only applies to run
+    let mut print = (Print::Last, None);
+    let mut options = Options::default();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-h" | "--help" => return Ok(Command::Help),
+            "--print-all" => print = (Print::All, Some(arg)),
+            "--quiet" => print = (Print::Nothing, Some(arg)),
+            "--engine=tree" => options.engine = Engine::Tree,
+            "--engine=vm" => options.engine = Engine::Vm,
+            option if option.starts_with("--fuel=") => {
+                options.limits.fuel = Some(limit(option)?)
+            }
+            option if option.starts_with("--max-depth=") => {
+                options.limits.depth = Some(limit(option)?)
+            }
+            option if option.starts_with("--max-cells=") => {
+                options.limits.cells = Some(limit(option)?)
+            }
+            option if option.starts_with("--allow=") => {
+                options.capabilities = Capabilities::core();
+                for capability in capability_list(option)? {
+                    options.capabilities.grant(capability);
+                }
+            }
+            option if option.starts_with("--deny=") => {
+                for capability in capability_list(option)? {
+                    if capability == Capability::Core {
+                        return Err("the core capability cannot be denied".to_string());
+                    }
+                    options.capabilities.deny(capability);
+                }
+            }
+            "-e" | "--eval" => match args.next() {
+                Some(expr) => inputs.push(Input::Expr(expr)),
+                None => return Err(format!("{arg} expects an expression")),
+            },
+            "-" => inputs.push(Input::Stdin),
+            "--" => break,
+            option if option.starts_with('-') => return Err(format!("unknown option {option}")),
+            _ => inputs.push(Input::File(arg)),
+        }
+    }
+    let args: Vec<_> = args.collect();
+    match (command.as_deref(), print) {
+        (None, (_, None)) if inputs.is_empty() && args.is_empty() => Ok(Command::Repl(options)),
+        _ if inputs.is_empty() => {
+            Err("nothing to run, expected a file, - or -e EXPR".to_string())
+        }
+        (Some("expand"), (_, Some(option))) => Err(format!("{option} only applies to run")),
+        (Some("expand"), _) => Ok(Command::Expand { inputs, options }),
+        (_, (print, _)) => Ok(Command::Run {
+            inputs,
+            args,
+            print,
+            options,
+        }),
+    }
+}
+
+/// the number an `--option=N` is set to
+fn limit<T: FromStr>(option: &str) -> Result<T, String> {
+    let (name, value) = option
+        .split_once('=')
+        .expect("options with limits have a value");
+    value
+        .parse()
+        .map_err(|_| format!("{name} expects a whole number, got {value}"))
+}
+
+/// the capabilities an `--option=CAP,...` lists
+fn capability_list(option: &str) -> Result<Vec<Capability>, String> {
+    let (_, list) = option
+        .split_once('=')
+        .expect("options with capabilities have a value");
+    list.split(',').map(str::parse).collect()
+}
+
+#[test]
+fn parse_test() {
+    let parse = |args: &[&str]| parse(args.iter().map(|arg| arg.to_string()));
+    assert_eq!(Ok(Command::Repl(Options::default())), parse(&[]));
+    assert_eq!(Ok(Command::Help), parse(&["run", "a.sl", "--help"]));
+    assert_eq!(
+        Ok(Command::Run {
+            inputs: vec![Input::File("a.sl".to_string()), Input::Stdin],
+            args: vec!["x".to_string(), "--quiet".to_string()],
+            print: Print::All,
+            options: Options::default(),
+        }),
+        parse(&["run", "--print-all", "a.sl", "-", "--", "x", "--quiet"])
+    );
+    assert_eq!(
+        Ok(Command::Run {
+            inputs: vec![Input::Expr("(+ 1 2)".to_string())],
+            args: vec![],
+            print: Print::Nothing,
+            options: Options {
+                engine: Engine::Vm,
+                ..Options::default()
+            },
+        }),
+        parse(&["-e", "(+ 1 2)", "--quiet", "--engine=vm"])
+    );
+    assert_eq!(
+        Ok(Command::Run {
+            inputs: vec![Input::File("a.sl".to_string())],
+            args: vec![],
+            print: Print::Last,
+            options: Options {
+                limits: Limits {
+                    fuel: Some(1000),
+                    depth: Some(50),
+                    cells: Some(10),
+                },
+                ..Options::default()
+            },
+        }),
+        parse(&["--fuel=1000", "--max-depth=50", "--max-cells=10", "a.sl"])
+    );
+    let mut capabilities = Capabilities::core();
+    capabilities.grant(Capability::Io);
+    capabilities.grant(Capability::Process);
+    assert_eq!(
+        Ok(Command::Run {
+            inputs: vec![Input::File("a.sl".to_string())],
+            args: vec![],
+            print: Print::Last,
+            options: Options {
+                capabilities,
+                ..Options::default()
+            },
+        }),
+        parse(&["--allow=io,process,eval", "--deny=eval", "a.sl"])
+    );
+    assert_eq!(
+        Ok(Command::Expand {
+            inputs: vec![Input::File("a.sl".to_string())],
+            options: Options::default(),
+        }),
+        parse(&["expand", "a.sl"])
+    );
+    let mut capabilities = Capabilities::default();
+    capabilities.deny(Capability::Io);
+    assert_eq!(
+        Ok(Command::Expand {
+            inputs: vec![Input::File("a.sl".to_string())],
+            options: Options {
+                limits: Limits {
+                    fuel: Some(5),
+                    ..Limits::default()
+                },
+                capabilities,
+                ..Options::default()
+            },
+        }),
+        parse(&["expand", "--deny=io", "--fuel=5", "a.sl"])
+    );
+    // options without inputs start the repl with them
+    assert_eq!(
+        Ok(Command::Repl(Options {
+            engine: Engine::Vm,
+            capabilities,
+            ..Options::default()
+        })),
+        parse(&["--engine=vm", "--deny=io"])
+    );
+    assert!(parse(&["expand"]).is_err());
+    assert!(parse(&["expand", "--quiet", "a.sl"]).is_err());
+    assert!(parse(&["--print-all"]).is_err());
+    assert!(parse(&["--", "x"]).is_err());
+    assert!(parse(&["-e"]).is_err());
+    assert!(parse(&["run"]).is_err());
+    assert!(parse(&["--verbose", "a.sl"]).is_err());
+    assert!(parse(&["--engine=jit", "a.sl"]).is_err());
+    assert!(parse(&["--fuel=lots", "a.sl"]).is_err());
+    assert!(parse(&["--max-depth=-1", "a.sl"]).is_err());
+    assert!(parse(&["--allow=io,disk", "a.sl"]).is_err());
+    assert!(parse(&["--deny=core", "a.sl"]).is_err());
+}
@@ -6,6 +6,7 @@ pub enum Either<L, R> {
 }
 
 impl<T> Either<T, T> {
+	#[allow(dead_code)]
 	pub fn fuse(self) -> T {
 		match self {
 			Either::L(t) => t,
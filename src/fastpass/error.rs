@@ -4,7 +4,7 @@ pub trait ErrorMessage: Sized {
 	fn display(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result;
 }
 
-impl<'a> ErrorMessage for &'a str {
+impl ErrorMessage for &str {
 	fn display(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
 		write!(f, "{}", self)
 	}
@@ -1,9 +1,11 @@
 use super::{ErrorMessage, ParseResult, Parser, View};
 
+#[allow(dead_code)]
 pub struct Expect<F> {
     test: F
 }
 
+#[allow(dead_code)]
 pub fn expect<'buf, E: ErrorMessage, F: Fn(View<'buf>) -> Option<E>>(test: F) -> Expect<F> {
     Expect { test }
 }
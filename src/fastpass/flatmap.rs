@@ -1,10 +1,12 @@
 use super::{ErrorMessage, ParseResult, Parser, View};
 
+#[allow(dead_code)]
 pub struct FlatMap<P, F> {
     parser: P,
     f: F,
 }
 
+#[allow(dead_code)]
 pub fn flatmap<P, F>(parser: P, f: F) -> FlatMap<P, F> {
     FlatMap { parser, f }
 }
@@ -28,11 +30,13 @@ where
     }
 }
 
+#[allow(dead_code)]
 pub struct FlatMapOk<P, F> {
     parser: P,
     f: F,
 }
 
+#[allow(dead_code)]
 pub fn flatmap_ok<P, F>(parser: P, f: F) -> FlatMapOk<P, F> {
     FlatMapOk { parser, f }
 }
@@ -53,11 +57,13 @@ where
     }
 }
 
+#[allow(dead_code)]
 pub struct FlatMapErr<P, F> {
     parser: P,
     f: F,
 }
 
+#[allow(dead_code)]
 pub fn flatmap_err<P, F>(parser: P, f: F) -> FlatMapErr<P, F> {
     FlatMapErr { parser, f }
 }
@@ -74,7 +80,7 @@ where
     type Error = P2::Error;
 
     fn parse(&self, buf: View<'buf>) -> ParseResult<'buf, Self::Output, Self::Error> {
-        match self.parser.parse(buf.clone()) {
+        match self.parser.parse(buf) {
             Ok(ok) => Ok(ok),
             Err(err) => (self.f)(err).parse(buf),
         }
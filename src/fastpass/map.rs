@@ -1,10 +1,12 @@
 use super::{ErrorMessage, ParseResult, Parser, View};
 
+#[allow(dead_code)]
 pub struct Map<P, F> {
     parser: P,
     f: F,
 }
 
+#[allow(dead_code)]
 pub fn map<P, F>(parser: P, f: F) -> Map<P, F> {
     Map { parser, f }
 }
@@ -20,7 +22,7 @@ where
     type Error = E2;
 
     fn parse(&self, buf: View<'buf>) -> ParseResult<'buf, Self::Output, Self::Error> {
-        match self.parser.parse(buf.clone()) {
+        match self.parser.parse(buf) {
             Ok((buf, res)) => (self.f)(Ok(res)).map(|res| (buf, res)),
             Err(err) => (self.f)(Err(err)).map(|res| (buf, res)),
         }
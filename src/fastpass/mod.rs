@@ -38,6 +38,7 @@ pub trait Parser<'buf>: Sized {
 	type Error: ErrorMessage;
 
 	fn parse(&self, buf: View<'buf>) -> ParseResult<'buf, Self::Output, Self::Error>;
+	#[allow(dead_code)]
 	fn flatmap<
 		T2,
 		P: Parser<'buf, Output = T2>,
@@ -48,12 +49,14 @@ pub trait Parser<'buf>: Sized {
 	) -> FlatMap<Self, F> {
 		flatmap::flatmap(self, f)
 	}
+	#[allow(dead_code)]
 	fn flatmap_ok<T2, P: Parser<'buf, Output = T2>, F: Fn(Self::Output) -> P>(
 		self,
 		f: F,
 	) -> FlatMapOk<Self, F> {
 		flatmap::flatmap_ok(self, f)
 	}
+	#[allow(dead_code)]
 	fn flatmap_err<P: Parser<'buf, Output = Self::Output>, F: Fn(Self::Error) -> P>(
 		self,
 		f: F,
@@ -61,6 +64,7 @@ pub trait Parser<'buf>: Sized {
 		flatmap::flatmap_err(self, f)
 	}
 
+	#[allow(dead_code)]
 	fn map<T, E: ErrorMessage, F: Fn(Result<Self::Output, Self::Error>) -> Result<T, E>>(
 		self,
 		f: F,
@@ -77,6 +81,7 @@ pub trait Parser<'buf>: Sized {
 		map::map_err(self, f)
 	}
 
+	#[allow(dead_code)]
 	fn optional(self) -> Optional<Self> {
 		optional::optional(self)
 	}
@@ -88,6 +93,7 @@ pub trait Parser<'buf>: Sized {
 	fn then<P: Parser<'buf>>(self, then: P) -> Then<Self, P> {
 		then::then(self, then)
 	}
+	#[allow(clippy::type_complexity)]
 	fn then_left<P: Parser<'buf>>(
 		self,
 		then: P,
@@ -99,6 +105,8 @@ pub trait Parser<'buf>: Sized {
 	> {
 		then::then(self, then).map_ok(|(l, _)| Ok(l))
 	}
+	#[allow(dead_code)]
+	#[allow(clippy::type_complexity)]
 	fn then_right<P: Parser<'buf>>(
 		self,
 		then: P,
@@ -113,10 +121,12 @@ pub trait Parser<'buf>: Sized {
 		repeat::greedy(self)
 	}
 
+	#[allow(dead_code)]
 	fn repeat_if<F: Fn(&Vec<Self::Output>) -> bool>(self, test: F) -> RepeatIf<Self, F> {
 		repeat::repeat_if(self, test)
 	}
 
+	#[allow(dead_code)]
 	fn expect<E: ErrorMessage, F: Fn(View<'buf>) -> Option<E>>(
 		self,
 		test: F,
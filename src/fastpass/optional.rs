@@ -1,9 +1,11 @@
 use super::{Infallible, ParseResult, Parser};
 
+#[allow(dead_code)]
 pub struct Optional<P> {
 	parser: P,
 }
 
+#[allow(dead_code)]
 pub fn optional<P>(parser: P) -> Optional<P> {
 	Optional { parser }
 }
@@ -26,11 +26,13 @@ impl<'buf, P: Parser<'buf>> Parser<'buf> for Greedy<P> {
 	}
 }
 
+#[allow(dead_code)]
 pub struct RepeatIf<P, F> {
 	parser: P,
 	test: F,
 }
 
+#[allow(dead_code)]
 pub fn repeat_if<P, F>(parser: P, test: F) -> RepeatIf<P, F> {
 	RepeatIf { parser, test }
 }
@@ -12,9 +12,9 @@ impl<'buf> Parser<'buf> for &'buf str {
 			.zip(buf.as_str().char_indices())
 			.try_fold(0, |found, ((i_l, c_l), (i_r, c_r))| {
 				if c_l == c_r && i_l == i_r {
-					return ControlFlow::Continue(i_l + c_l.len_utf8());
+					ControlFlow::Continue(i_l + c_l.len_utf8())
 				} else {
-					return ControlFlow::Break(found);
+					ControlFlow::Break(found)
 				}
 			});
 
@@ -22,16 +22,16 @@ impl<'buf> Parser<'buf> for &'buf str {
 			ControlFlow::Continue(x) => x,
 			ControlFlow::Break(x) => x,
 		};
-		return if found == self.len() {
+		if found == self.len() {
 			Ok((buf.sub_view(found..), &buf.as_str()[..found]))
 		} else {
 			Err((buf.sub_view(found..), "\nexpected: ", self))
-		};
+		}
 	}
 }
 
 #[test]
-fn exact_match<'buf>() {
+fn exact_match() {
 	let parser = "abc";
 	let buf = View::new("abc");
 	let res = Parser::parse(&parser, buf);
@@ -41,7 +41,7 @@ fn exact_match<'buf>() {
 }
 
 #[test]
-fn totally_different<'buf>() {
+fn totally_different() {
 	let parser = "abc";
 	let buf = View::new("defgh");
 	let res = Parser::parse(&parser, buf);
@@ -49,7 +49,7 @@ fn totally_different<'buf>() {
 }
 
 #[test]
-fn starts_with<'buf>() {
+fn starts_with() {
 	let parser = "abc";
 	let buf = View::new("abcd");
 	let res = Parser::parse(&parser, buf);
@@ -59,7 +59,7 @@ fn starts_with<'buf>() {
 }
 
 #[test]
-fn ends_early<'buf>() {
+fn ends_early() {
 	let parser = "abc";
 	let buf = View::new("ab");
 	let res = Parser::parse(&parser, buf);
@@ -67,7 +67,7 @@ fn ends_early<'buf>() {
 }
 
 #[test]
-fn partial<'buf>() {
+fn partial() {
 	let parser = "abc";
 	let buf = View::new("abd");
 	let res = Parser::parse(&parser, buf);
@@ -100,12 +100,12 @@ impl<'buf, F: Fn(&str, char) -> bool> Parser<'buf> for CaptureWhile<F> {
 				}
 			}
 		}
-		return Ok((buf.sub_view(capture..), &buf.as_str()[..capture]));
+		Ok((buf.sub_view(capture..), &buf.as_str()[..capture]))
 	}
 }
 
 #[test]
-fn capture_all_whitespace<'buf>() {
+fn capture_all_whitespace() {
 	let parser = CaptureWhile(|_, char| char.is_whitespace());
 	let buf = View::new("   ");
 	let res = Parser::parse(&parser, buf);
@@ -115,7 +115,7 @@ fn capture_all_whitespace<'buf>() {
 }
 
 #[test]
-fn capture_nothing<'buf>() {
+fn capture_nothing() {
 	let parser = CaptureWhile(|_, char| !char.is_whitespace());
 	let buf = View::new("   ");
 	let res = Parser::parse(&parser, buf);
@@ -125,7 +125,7 @@ fn capture_nothing<'buf>() {
 }
 
 #[test]
-fn capture_a<'buf>() {
+fn capture_a() {
 	let parser = CaptureWhile(|_, char| char == 'a');
 	let buf = View::new("abc");
 	let res = Parser::parse(&parser, buf);
@@ -6,6 +6,7 @@ use core::{
 use crate::fastpass::ErrorMessage;
 
 pub struct View<'buf> {
+	name: &'buf str,
 	source: &'buf str,
 	pub start: usize,
 	pub end: usize,
@@ -25,21 +26,43 @@ impl<'buf> fmt::Display for View<'buf> {
 
 impl<'buf> Clone for View<'buf> {
 	fn clone(&self) -> Self {
-		Self { ..*self }
+		*self
 	}
 }
 
 impl<'buf> Copy for View<'buf> {}
 
 impl<'a, 'buf: 'a> View<'buf> {
+	#[allow(dead_code)]
 	pub fn new(source: &'buf str) -> Self {
+		Self::named("", source)
+	}
+
+	/// a view of the whole of `source`, which was read from the file `name`
+	pub fn named(name: &'buf str, source: &'buf str) -> Self {
 		Self {
+			name,
 			source,
 			start: 0,
 			end: source.len(),
 		}
 	}
 
+	pub fn name(&self) -> &'buf str {
+		self.name
+	}
+
+	/// the whole of the source this is a view of
+	pub fn source(&self) -> &'buf str {
+		self.source
+	}
+
+	/// the view from the start of this view up to the start of `rest`,
+	/// where `rest` is what remains after parsing from this view
+	pub fn up_to(&self, rest: View<'buf>) -> View<'buf> {
+		self.sub_view(..rest.start - self.start)
+	}
+
 	pub fn sub_view<R: RangeBounds<usize>>(&self, index: R) -> View<'buf> {
 		let start = match index.start_bound() {
 			Bound::Included(x) => self.start + x,
@@ -69,15 +92,15 @@ impl<'a, 'buf: 'a> View<'buf> {
 impl<'buf> ErrorMessage for View<'buf> {
 	fn display(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
 		let preceding = &self.source[..self.start];
-		let row = preceding.lines().count();
-		let col = preceding.lines().last().map_or(0, str::len);
-		let out = self.source.lines().nth(if row == 0 { 0 } else { row - 1 }).unwrap_or("");
+		let row = preceding.split('\n').count();
+		let col = preceding.split('\n').next_back().map_or(0, str::len);
+		let out = self.source.lines().nth(row - 1).unwrap_or("");
 		let start = if col <= Self::ATTENTION {
 			0
 		} else {
 			col - Self::ATTENTION - 1
 		};
-		write!(f, "{out}\n")?;
+		writeln!(f, "{out}")?;
 		for _ in 0..start {
 			write!(f, " ")?;
 		}
@@ -89,8 +112,11 @@ impl<'buf> ErrorMessage for View<'buf> {
 		for _ in 0..attention_count {
 			write!(f, "^")?;
 		}
-		write!(f, "\n")?;
-		write!(f, "row: {row}, col: {col}\n")
+		writeln!(f)?;
+		if !self.name.is_empty() {
+			write!(f, "file: {}, ", self.name)?;
+		}
+		writeln!(f, "row: {row}, col: {col}")
 	}
 }
 
@@ -105,3 +131,10 @@ fn sub_view1() {
     let v = v.sub_view(1..2);
     assert_eq!("3", v.as_str());
 }
+
+#[test]
+fn up_to() {
+    let v = View::new("(a b) c");
+    let rest = v.sub_view(5..);
+    assert_eq!("(a b)", v.up_to(rest).as_str());
+}
@@ -0,0 +1,71 @@
+use std::{cell::RefCell, rc::Rc};
+
+use super::{
+    env,
+    inbuilt::{arity, function},
+    Env, EvalError, EvalResult, List, Value,
+};
+
+fn cell(value: &Value) -> Result<&Rc<RefCell<Value>>, EvalError> {
+    match value {
+        Value::Box(cell) => Ok(cell),
+        _ => Err(EvalError::Type(
+            format!("expected a box, got {value}"),
+            value.clone(),
+        )),
+    }
+}
+
+fn make_box(args: &[Value]) -> EvalResult {
+    match args {
+        [value] => Ok(Value::Box(Rc::new(RefCell::new(value.clone())))),
+        _ => Err(arity("box", "1 argument", args)),
+    }
+}
+
+fn unbox(args: &[Value]) -> EvalResult {
+    match args {
+        [value] => Ok(cell(value)?.borrow().clone()),
+        _ => Err(arity("unbox", "1 argument", args)),
+    }
+}
+
+fn set_box(args: &[Value]) -> EvalResult {
+    match args {
+        [value, contents] => {
+            *cell(value)?.borrow_mut() = contents.clone();
+            Ok(Value::List(List::from([]), None))
+        }
+        _ => Err(arity("set-box!", "2 arguments", args)),
+    }
+}
+
+fn is_box(args: &[Value]) -> EvalResult {
+    match args {
+        [value] => Ok(Value::Bool(matches!(value, Value::Box(_)))),
+        _ => Err(arity("box?", "1 argument", args)),
+    }
+}
+
+pub fn bindings(env: Env) -> Vec<env::Value<&'static str, Value>> {
+    vec![
+        env::Value("box", function(env.clone(), "value", make_box)),
+        env::Value("unbox", function(env.clone(), "box", unbox)),
+        env::Value("set-box!", function(env.clone(), "box value", set_box)),
+        env::Value("box?", function(env, "value", is_box)),
+    ]
+}
+
+#[test]
+fn boxes_test() {
+    use super::test::eval;
+    assert_eq!(Some("(#&1 #t #f)".to_string()), eval("(define b (box 1)) (list b (box? b) (box? 1))"));
+    // boxes which contain themselves are displayed without going round forever
+    assert_eq!(Some("#&#&...".to_string()), eval("(define b (box 1)) (set-box! b b) b"));
+    assert_eq!(
+        Some("#&(1 #&(2 #&...))".to_string()),
+        eval("(define a (box 1)) (define b (box 2)) (set-box! a (list 1 b)) (set-box! b (list 2 a)) a")
+    );
+    // the same box twice, without a cycle, is displayed in full both times
+    assert_eq!(Some("(#&1 #&1)".to_string()), eval("(define b (box 1)) (list b b)"));
+}
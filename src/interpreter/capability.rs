@@ -0,0 +1,168 @@
+//! capabilities, the groups the builtins are sorted into, so that a script which is not
+//! trusted can be given only some of them. the names of builtins in a group which is not
+//! granted are still bound, to procedures which fail with [EvalError::Capability]
+
+use core::{fmt, str::FromStr};
+use std::rc::Rc;
+
+use super::{Env, EvalError, List, Value};
+
+/// a group of builtins
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Capability {
+    /// the language itself, its special forms and the builtins which only compute
+    /// with values. always granted
+    Core,
+    /// printing to stdout
+    Io,
+    /// reading and writing files
+    Fs,
+    /// the process running the script, such as its command line
+    Process,
+    /// the network
+    Net,
+    /// evaluating data as code, with `eval`
+    Eval,
+}
+
+impl Capability {
+    pub const ALL: [Capability; 6] = [
+        Capability::Core,
+        Capability::Io,
+        Capability::Fs,
+        Capability::Process,
+        Capability::Net,
+        Capability::Eval,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Capability::Core => "core",
+            Capability::Io => "io",
+            Capability::Fs => "fs",
+            Capability::Process => "process",
+            Capability::Net => "net",
+            Capability::Eval => "eval",
+        }
+    }
+
+    fn bit(self) -> u8 {
+        1 << self as u8
+    }
+}
+
+impl fmt::Display for Capability {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+impl FromStr for Capability {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        Capability::ALL
+            .into_iter()
+            .find(|capability| capability.name() == name)
+            .ok_or_else(|| {
+                let names: Vec<_> = Capability::ALL.iter().map(|c| c.name()).collect();
+                format!(
+                    "unknown capability {name}, expected one of {}",
+                    names.join(", ")
+                )
+            })
+    }
+}
+
+/// the capabilities granted to a script. every capability is granted by default
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Capabilities(u8);
+
+impl Default for Capabilities {
+    fn default() -> Self {
+        Capabilities::all()
+    }
+}
+
+impl Capabilities {
+    /// every capability
+    pub fn all() -> Self {
+        Capabilities(Capability::ALL.iter().fold(0, |bits, c| bits | c.bit()))
+    }
+
+    /// only [Capability::Core]
+    pub fn core() -> Self {
+        Capabilities(Capability::Core.bit())
+    }
+
+    pub fn grant(&mut self, capability: Capability) {
+        self.0 |= capability.bit();
+    }
+
+    /// takes `capability` away, unless it is [Capability::Core], which cannot be
+    pub fn deny(&mut self, capability: Capability) {
+        if capability != Capability::Core {
+            self.0 &= !capability.bit();
+        }
+    }
+
+    pub fn granted(self, capability: Capability) -> bool {
+        self.0 & capability.bit() != 0
+    }
+}
+
+/// what the builtin `name` is bound to when `capability` is not granted.
+/// its arguments are not evaluated
+pub fn denied(env: Env, name: &'static str, capability: Capability) -> Value {
+    Value::Procedure(
+        env,
+        Rc::new(move |_, _, exprs| {
+            Err(EvalError::Capability(
+                format!("{name} needs the {capability} capability"),
+                Value::List(List::from(exprs), None),
+            ))
+        }),
+        Rc::new("..."),
+    )
+}
+
+#[test]
+fn expand_test() {
+    use super::{test::read, Session};
+    let mut capabilities = Capabilities::default();
+    capabilities.deny(Capability::Io);
+    // expanding evaluates definitions, which are kept to the capabilities of the session
+    let mut session = Session::new(capabilities);
+    let expanded = session.expand(read("(define x (display \"hi\"))"), |_| ());
+    assert!(matches!(expanded, Err(EvalError::Capability(_, _))));
+}
+
+#[test]
+fn capabilities_test() {
+    use super::{test::read, Engine, Session};
+    let mut sandbox = Capabilities::core();
+    sandbox.grant(Capability::Io);
+    for engine in [Engine::Tree, Engine::Vm] {
+        let run = |source: &str| {
+            let mut session = Session::new(sandbox);
+            session.engine(engine);
+            session.command_line(&["script.sl".to_string()]);
+            session.run(read(source), |_| ()).map(|last| last.unwrap().to_string())
+        };
+        let denied = |source: &str| matches!(run(source), Err(EvalError::Capability(_, _)));
+        assert!(denied("(eval '(+ 1 2))"));
+        assert!(denied("(command-line)"));
+        assert!(denied("(define (f x) (eval x)) (f 1)"));
+        // the arguments of a denied builtin are not evaluated
+        assert!(denied("(eval (car '()))"));
+        assert_eq!(Some("()".to_string()), run("(newline)").ok());
+        assert_eq!(Some("3".to_string()), run("(+ 1 2)").ok());
+        assert_eq!(
+            Some("capability-not-granted".to_string()),
+            run("(guard (e (#t (error-object-kind e))) (eval 1))").ok()
+        );
+    }
+    let mut session = Session::default();
+    session.command_line(&["script.sl".to_string()]);
+    assert!(session.run(read("(list (eval 1) (command-line))"), |_| ()).is_ok());
+}
@@ -0,0 +1,167 @@
+//! the cells lists are kept in. a list is the end of a buffer of cells, so the cdr of a list
+//! shares its buffer. a buffer has free cells before the ones in use, so consing onto the list
+//! which starts at the front of its buffer fills the cell before it rather than copying the list
+
+use core::{
+    cell::{Cell, UnsafeCell},
+    mem::MaybeUninit,
+    ops::Deref,
+};
+use std::rc::{Rc, Weak};
+
+use super::{limits, EvalError, Value};
+
+struct Buffer {
+    /// the cells from `front` on hold values, which are never changed once written,
+    /// and the cells before it are free
+    cells: Box<[UnsafeCell<MaybeUninit<Value>>]>,
+    front: Cell<usize>,
+}
+
+impl Buffer {
+    /// a buffer of `values`, with `free` free cells before them
+    fn new(free: usize, values: impl Iterator<Item = Value>) -> Rc<Buffer> {
+        let mut cells = Vec::with_capacity(free + values.size_hint().0);
+        cells.extend((0..free).map(|_| UnsafeCell::new(MaybeUninit::uninit())));
+        cells.extend(values.map(|value| UnsafeCell::new(MaybeUninit::new(value))));
+        Rc::new(Buffer {
+            cells: cells.into_boxed_slice(),
+            front: Cell::new(free),
+        })
+    }
+}
+
+impl Drop for Buffer {
+    fn drop(&mut self) {
+        for cell in &mut self.cells[self.front.get()..] {
+            // SAFETY: the cells from the front on were written, and are dropped only here
+            unsafe { cell.get_mut().assume_init_drop() };
+        }
+    }
+}
+
+/// the values of a list, which derefs to a slice of them
+#[derive(Clone)]
+pub struct List {
+    buffer: Rc<Buffer>,
+    /// the cell of the first value
+    start: usize,
+}
+
+impl List {
+    fn new(values: impl Iterator<Item = Value>) -> List {
+        List {
+            buffer: Buffer::new(0, values),
+            start: 0,
+        }
+    }
+
+    /// the list of `head` followed by the values of `tail`, allocating a cell for `head`.
+    /// `tail` is copied when a list has already been consed onto it, and the copy is charged
+    pub fn cons(head: Value, tail: &List) -> Result<List, EvalError> {
+        let buffer = &tail.buffer;
+        if tail.start != buffer.front.get() {
+            limits::allocate(tail.len() + 1)?;
+        } else if tail.start > 0 {
+            limits::allocate(1)?;
+            let start = tail.start - 1;
+            // SAFETY: the cell is before the front, so it is free and no list includes it
+            unsafe { (*buffer.cells[start].get()).write(head) };
+            buffer.front.set(start);
+            return Ok(List {
+                buffer: buffer.clone(),
+                start,
+            });
+        } else {
+            // the buffer is full, and the list is moved to one with as many free cells as it has
+            limits::allocate(1)?;
+        }
+        let len = tail.len() + 1;
+        Ok(List {
+            buffer: Buffer::new(len, core::iter::once(head).chain(tail.iter().cloned())),
+            start: len,
+        })
+    }
+
+    /// a reference to the cells of this list, which does not keep their values alive
+    pub fn downgrade(&self) -> WeakList {
+        WeakList(Rc::downgrade(&self.buffer))
+    }
+
+    /// the list of the values after the first `count`, sharing the cells of this one
+    pub fn skip(&self, count: usize) -> List {
+        List {
+            buffer: self.buffer.clone(),
+            start: (self.start + count).min(self.buffer.cells.len()),
+        }
+    }
+}
+
+/// a list which has been [List::downgrade]d
+pub struct WeakList(Weak<Buffer>);
+
+impl WeakList {
+    pub fn strong_count(&self) -> usize {
+        self.0.strong_count()
+    }
+}
+
+impl Deref for List {
+    type Target = [Value];
+
+    fn deref(&self) -> &[Value] {
+        let cells = &self.buffer.cells[self.start..];
+        // SAFETY: the cells of a list are from the front of its buffer on, so they hold values
+        // which are not changed while the list is, and UnsafeCell and MaybeUninit have the
+        // layout of the value they hold
+        unsafe { core::slice::from_raw_parts(cells.as_ptr().cast::<Value>(), cells.len()) }
+    }
+}
+
+impl AsRef<[Value]> for List {
+    fn as_ref(&self) -> &[Value] {
+        self
+    }
+}
+
+impl From<Vec<Value>> for List {
+    fn from(values: Vec<Value>) -> List {
+        List::new(values.into_iter())
+    }
+}
+
+impl<const N: usize> From<[Value; N]> for List {
+    fn from(values: [Value; N]) -> List {
+        List::new(values.into_iter())
+    }
+}
+
+impl From<&[Value]> for List {
+    fn from(values: &[Value]) -> List {
+        List::new(values.iter().cloned())
+    }
+}
+
+impl FromIterator<Value> for List {
+    fn from_iter<I: IntoIterator<Item = Value>>(values: I) -> List {
+        List::new(values.into_iter())
+    }
+}
+
+#[test]
+fn sharing_test() {
+    let list = List::from([Value::Int(2), Value::Int(3)]);
+    let one = List::cons(Value::Int(1), &list).unwrap();
+    let zero = List::cons(Value::Int(0), &one).unwrap();
+    // the cdr of a list and the list it was consed onto share their cells,
+    // unless there was no room before it, as for `list`
+    assert_eq!(one.as_ptr(), zero.skip(1).as_ptr());
+    assert_ne!(list.as_ptr(), one.skip(1).as_ptr());
+    // consing onto a list which has been consed onto already copies it
+    let other = List::cons(Value::Int(-1), &one).unwrap();
+    assert_ne!(one.as_ptr(), other.skip(1).as_ptr());
+    let values = |list: &List| list.iter().map(|v| v.to_string()).collect::<Vec<_>>();
+    assert_eq!(vec!["0", "1", "2", "3"], values(&zero));
+    assert_eq!(vec!["-1", "1", "2", "3"], values(&other));
+    assert!(zero.skip(4).is_empty() && zero.skip(5).is_empty());
+}
@@ -0,0 +1,788 @@
+//! evaluation in continuation passing style.
+//! rather than evaluating the expressions they depend on by calling [super::eval],
+//! procedures hand back a [Tail::Then] with the rest of their work as a [Frame].
+//! frames are never mutated once made, so the rest of a computation can be captured
+//! as a copy of the stack of frames, and resumed any number of times.
+//!
+//! builtins which call procedures themselves, such as `map`, take their calls as steps
+//! of the run they were called in, see [iterate], as does the vm. top level forms, and the
+//! expansions of macros, are evaluated with a [run] of their own. a continuation can escape
+//! out of any number of runs, but it can only be resumed while the run it was captured in
+//! is running
+
+use core::fmt;
+use std::{
+    cell::{Cell, RefCell},
+    rc::Rc,
+};
+
+use super::{
+    env,
+    inbuilt::{apply, arity},
+    invoke, limits, params, Env, EvalError, EvalResult, List, Tail, TailResult, Value,
+};
+
+/// the rest of the work of a procedure, once the expression it is waiting on has a value
+pub type Frame = Rc<dyn Fn(Value) -> TailResult + 'static>;
+
+/// what is done with an error raised while evaluating the expression of a [Tail::Catch]
+pub type Handler = Rc<dyn Fn(EvalError) -> TailResult + 'static>;
+
+/// the clauses of a `handle`, which effects performed while evaluating the expression
+/// of a [Tail::Handle] are handed to
+pub struct Effects {
+    /// the effect each clause handles
+    pub names: Vec<Value>,
+    /// evaluates the clause at an index, given the value performed
+    /// and a procedure which resumes the computation from where it was performed
+    pub clause: Box<dyn Fn(usize, Value, Value) -> TailResult>,
+}
+
+/// what a frame hands errors or effects to, until it has its value
+#[derive(Clone)]
+enum Handle {
+    Errors(Handler),
+    Effects(Rc<Effects>),
+}
+
+/// a frame waiting for a value
+#[derive(Clone)]
+struct Entry {
+    frame: Frame,
+    /// the call it belongs to, for errors
+    within: Option<Value>,
+    /// what the frame handles, and the winds it was installed in
+    handle: Option<(Handle, Winds)>,
+}
+
+/// the frames waiting for a value, innermost last
+type Stack = Vec<Entry>;
+
+/// a part of the dynamic extent of an evaluation which has been entered and not yet left,
+/// such as a `dynamic-wind`
+struct Wind {
+    dynamic: Dynamic,
+    depth: usize,
+    outer: Winds,
+}
+
+enum Dynamic {
+    /// a `dynamic-wind`, with its before and after thunks
+    Wind(Value, Value),
+    /// a `with-exception-handler`, with its handler
+    Handler(Value),
+    /// a `guard`, which what is raised within it is handed to as an error
+    Guard,
+    /// a handler being called, with the winds around the wind which installed it,
+    /// so that what it raises is handled further out
+    Handling(Winds),
+}
+
+/// the winds around the current evaluation, innermost first
+type Winds = Option<Rc<Wind>>;
+
+thread_local! {
+    static WINDS: RefCell<Winds> = const { RefCell::new(None) };
+    /// the runs which have not yet returned, innermost last
+    static RUNS: RefCell<Vec<u64>> = const { RefCell::new(Vec::new()) };
+    static NEXT_RUN: Cell<u64> = const { Cell::new(0) };
+}
+
+fn winds() -> Winds {
+    WINDS.with_borrow(Clone::clone)
+}
+
+fn depth(winds: &Winds) -> usize {
+    winds.as_ref().map_or(0, |wind| wind.depth)
+}
+
+/// enters a wind within the current winds, giving the winds it was entered from
+fn enter(dynamic: Dynamic) -> Winds {
+    let outer = winds();
+    WINDS.set(Some(Rc::new(Wind {
+        dynamic,
+        depth: depth(&outer) + 1,
+        outer: outer.clone(),
+    })));
+    outer
+}
+
+/// the innermost exception handler and the wind which installed it, or `None` when there
+/// is none or a `guard` is further in
+fn handler() -> Option<(Value, Rc<Wind>)> {
+    let mut winds = winds();
+    while let Some(wind) = winds {
+        winds = match &wind.dynamic {
+            Dynamic::Handler(handler) => return Some((handler.clone(), wind)),
+            Dynamic::Guard => return None,
+            Dynamic::Handling(outer) => outer.clone(),
+            Dynamic::Wind(_, _) => wind.outer.clone(),
+        };
+    }
+    None
+}
+
+/// calls `handler`, installed by `installed`, with `condition`,
+/// with the handlers around `installed` in place of it
+fn call_handler(handler: &Value, installed: &Wind, condition: Value) -> TailResult {
+    enter(Dynamic::Handling(installed.outer.clone()));
+    apply(handler, &[condition])
+}
+
+/// calls `thunk` with `handler` installed, so that it is called with what is raised
+/// while thunk is evaluated, where it is raised
+pub fn with_exception_handler(handler: Value, thunk: Value) -> TailResult {
+    // the handler is installed by the run which evaluates thunk, so that it is called
+    // by that run, and taken away again if an error leaves it
+    let outer = winds();
+    Ok(Tail::Then(
+        Env::global(),
+        deferred(move || {
+            enter(Dynamic::Handler(handler.clone()));
+            apply(&thunk, &[])
+        }),
+        frame(move |value| {
+            WINDS.set(outer.clone());
+            Ok(Tail::Value(value))
+        }),
+    ))
+}
+
+/// raises `value` to the innermost exception handler, continuing with what the handler
+/// gives back. without a handler it is raised as an error, to be caught by a `guard`
+pub fn raise_continuable(value: Value, form: Value) -> TailResult {
+    let outer = winds();
+    Ok(Tail::Then(
+        Env::global(),
+        deferred(move || match handler() {
+            Some((handler, installed)) => call_handler(&handler, &installed, value.clone()),
+            None => Err(EvalError::Raise(value.clone(), form.clone())),
+        }),
+        frame(move |value| {
+            WINDS.set(outer.clone());
+            Ok(Tail::Value(value))
+        }),
+    ))
+}
+
+fn same(a: &Winds, b: &Winds) -> bool {
+    match (a, b) {
+        (Some(a), Some(b)) => Rc::ptr_eq(a, b),
+        (None, None) => true,
+        _ => false,
+    }
+}
+
+/// leaves the current winds for `to`, calling the after thunk of each wind which is left,
+/// innermost first, then the before thunk of each wind which is entered, outermost first
+fn rewind(to: &Winds) -> Result<(), EvalError> {
+    let (mut from, mut common) = (winds(), to.clone());
+    while depth(&from) > depth(&common) {
+        from = from.and_then(|wind| wind.outer.clone());
+    }
+    while depth(&common) > depth(&from) {
+        common = common.and_then(|wind| wind.outer.clone());
+    }
+    while !same(&from, &common) {
+        from = from.and_then(|wind| wind.outer.clone());
+        common = common.and_then(|wind| wind.outer.clone());
+    }
+    loop {
+        let from = winds();
+        if same(&from, &common) {
+            break;
+        }
+        let wind = from.expect("common is within the current winds");
+        WINDS.set(wind.outer.clone());
+        if let Dynamic::Wind(_, after) = &wind.dynamic {
+            run(apply(after, &[])?)?;
+        }
+    }
+    let mut entered = Vec::new();
+    let mut wind = to.clone();
+    while !same(&wind, &common) {
+        let inner = wind.expect("common is within to");
+        wind = inner.outer.clone();
+        entered.push(inner);
+    }
+    for wind in entered.into_iter().rev() {
+        if let Dynamic::Wind(before, _) = &wind.dynamic {
+            run(apply(before, &[])?)?;
+        }
+        WINDS.set(Some(wind));
+    }
+    Ok(())
+}
+
+/// the rest of a computation, from where `call/cc` was called
+pub struct Continuation {
+    /// the run it was captured in
+    run: u64,
+    stack: Stack,
+    winds: Winds,
+}
+
+impl fmt::Debug for Continuation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "continuation")
+    }
+}
+
+/// a procedure which resumes `continuation` with its argument, or `()` without one
+fn resume(continuation: Rc<Continuation>) -> Value {
+    Value::Procedure(
+        Env::global(),
+        Rc::new(move |_, env, exprs| {
+            let continuation = continuation.clone();
+            evaluate_all(&env, exprs, move |mut values| match values.len() {
+                0 => Err(EvalError::Unwind(
+                    continuation.clone(),
+                    Value::List(List::from([]), None),
+                )),
+                1 => Err(EvalError::Unwind(continuation.clone(), values.remove(0))),
+                _ => Err(arity("continuation", "0 or 1 arguments", &values)),
+            })
+        }),
+        Rc::new("[value]"),
+    )
+}
+
+/// a frame from a closure
+pub fn frame(f: impl Fn(Value) -> TailResult + 'static) -> Frame {
+    Rc::new(f)
+}
+
+/// continues with `f` once `tail` has a value
+pub fn then(tail: Tail, f: impl Fn(Value) -> TailResult + 'static) -> TailResult {
+    match tail {
+        Tail::Value(value) => f(value),
+        tail => then_frame(tail, frame(f)),
+    }
+}
+
+fn then_frame(tail: Tail, f: Frame) -> TailResult {
+    match tail {
+        Tail::Value(value) => f(value),
+        Tail::Eval(env, expr) => Ok(Tail::Then(env, expr, f)),
+        Tail::Then(env, expr, inner) => Ok(Tail::Then(
+            env,
+            expr,
+            frame(move |value| then_frame(inner(value)?, f.clone())),
+        )),
+        // the other tails work on the stack of the machine,
+        // so they are continued as calls of procedures which give them again
+        tail => Ok(Tail::Then(
+            Env::global(),
+            deferred(move || Ok(tail.clone())),
+            f,
+        )),
+    }
+}
+
+/// what a builtin which calls procedures, such as `map`, does next, see [iterate]
+pub enum Step<S> {
+    /// calls a procedure with arguments, then continues from the state with its value
+    Call(Value, Vec<Value>, S),
+    Done(Value),
+}
+
+/// takes the steps of a builtin which calls procedures from `first`, continuing with `next`
+/// once each call has a value. the calls are evaluated by the run the builtin was called in,
+/// so the continuations of what they evaluate can be captured and resumed
+pub fn iterate<S: Clone + 'static>(
+    first: Step<S>,
+    next: impl Fn(S, Value) -> Result<Step<S>, EvalError> + 'static,
+) -> TailResult {
+    iterate_from(first, Rc::new(next))
+}
+
+fn iterate_from<S: Clone + 'static>(
+    mut step: Step<S>,
+    next: Rc<dyn Fn(S, Value) -> Result<Step<S>, EvalError>>,
+) -> TailResult {
+    loop {
+        let (procedure, args, state) = match step {
+            Step::Done(value) => return Ok(Tail::Value(value)),
+            Step::Call(procedure, args, state) => (procedure, args, state),
+        };
+        match apply(&procedure, &args)? {
+            // calls which have a value straight away are continued here,
+            // rather than with a frame each
+            Tail::Value(value) => step = next(state, value)?,
+            tail => {
+                let next = next.clone();
+                return then(tail, move |value| {
+                    iterate_from(next(state.clone(), value)?, next.clone())
+                });
+            }
+        }
+    }
+}
+
+/// a call of a procedure with no arguments which gives `tail`
+pub fn deferred(tail: impl Fn() -> TailResult + 'static) -> Value {
+    let procedure = Value::Procedure(
+        Env::global(),
+        Rc::new(move |_, _, _| tail()),
+        Rc::new("deferred"),
+    );
+    Value::List(List::from([procedure]), None)
+}
+
+/// the value of an expression which is not a call, so needs no frame to evaluate,
+/// or `None` for a call
+pub fn atom(env: &Env, expr: &Value) -> Result<Option<Value>, EvalError> {
+    match expr {
+        Value::List(_, _) => Ok(None),
+        Value::Symbol(_, _) if params::keyword(expr).is_some() => Ok(Some(expr.clone())),
+        Value::Symbol(name, _) => Value::from_env(env.clone(), *name)
+            .map(Some)
+            .map_err(|err| err.within(expr)),
+        Value::Resolved(name, address, span) => match env.lookup_at(name, *address) {
+            Some(value) => Ok(Some(value)),
+            None => Err(EvalError::Unbound(
+                Value::Symbol(*name, span.clone()),
+                env.clone(),
+            )),
+        },
+        _ => Ok(Some(expr.clone())),
+    }
+}
+
+/// evaluates `expr` in `env`, then continues with its value
+pub fn evaluate(
+    env: &Env,
+    expr: &Value,
+    then: impl Fn(Value) -> TailResult + 'static,
+) -> TailResult {
+    match atom(env, expr)? {
+        Some(value) => then(value),
+        None => Ok(Tail::Then(env.clone(), expr.clone(), frame(then))),
+    }
+}
+
+/// evaluates `exprs` in `env` from left to right, then continues with their values
+pub fn evaluate_all(
+    env: &Env,
+    exprs: &[Value],
+    then: impl Fn(Vec<Value>) -> TailResult + Clone + 'static,
+) -> TailResult {
+    evaluate_from(env, exprs, Vec::with_capacity(exprs.len()), then)
+}
+
+fn evaluate_from(
+    env: &Env,
+    exprs: &[Value],
+    mut values: Vec<Value>,
+    then: impl Fn(Vec<Value>) -> TailResult + Clone + 'static,
+) -> TailResult {
+    for (i, expr) in exprs.iter().enumerate() {
+        match atom(env, expr)? {
+            Some(value) => values.push(value),
+            None => {
+                let (env, rest): (_, Rc<[Value]>) = (env.clone(), Rc::from(&exprs[i + 1..]));
+                return Ok(Tail::Then(
+                    env.clone(),
+                    expr.clone(),
+                    frame(move |value| {
+                        let mut values = values.clone();
+                        values.push(value);
+                        evaluate_from(&env, &rest, values, then.clone())
+                    }),
+                ));
+            }
+        }
+    }
+    then(values)
+}
+
+/// a stack of frames, run until the value they are waiting on has been handed to each
+struct Machine {
+    id: u64,
+    stack: Stack,
+    /// the call being evaluated, which frames pushed now belong to
+    within: Option<Value>,
+    /// the winds when the run started, which an error leaves for
+    base: Winds,
+    /// the frames waiting in the runs further out, and the most there can be in all
+    outer: usize,
+    max_depth: usize,
+}
+
+impl Drop for Machine {
+    fn drop(&mut self) {
+        RUNS.with_borrow_mut(|runs| runs.pop());
+        limits::set_depth(self.outer);
+    }
+}
+
+/// evaluates expressions until one produces a value
+pub fn run(tail: Tail) -> EvalResult {
+    let id = NEXT_RUN.replace(NEXT_RUN.get() + 1);
+    RUNS.with_borrow_mut(|runs| runs.push(id));
+    Machine {
+        id,
+        stack: Vec::new(),
+        within: None,
+        base: winds(),
+        outer: limits::depth(),
+        max_depth: limits::max_depth(),
+    }
+    .run(tail)
+}
+
+impl Machine {
+    fn run(&mut self, mut tail: Tail) -> EvalResult {
+        loop {
+            // runs started from here are nested within the frames waiting in this one
+            limits::set_depth(self.outer + self.stack.len());
+            let next = match tail {
+                Tail::Value(value) => match self.stack.pop() {
+                    None => return Ok(value),
+                    Some(Entry { frame, within, .. }) => {
+                        let next = frame(value).map_err(|err| match &within {
+                            Some(call) => err.within(call),
+                            None => err,
+                        });
+                        self.within = within;
+                        next
+                    }
+                },
+                Tail::Eval(env, expr) => match &expr {
+                    Value::List(call, _) => {
+                        let next = invoke(env, call).map_err(|err| err.within(&expr));
+                        self.within = Some(expr);
+                        next
+                    }
+                    _ => atom(&env, &expr).map(|value| Tail::Value(value.expect("not a call"))),
+                },
+                Tail::Then(env, expr, frame) => {
+                    self.stack.push(Entry {
+                        frame,
+                        within: self.within.clone(),
+                        handle: None,
+                    });
+                    self.check_depth(&expr).map(|()| Tail::Eval(env, expr))
+                }
+                Tail::Catch(env, expr, handler) => {
+                    self.install(Handle::Errors(handler));
+                    // what is raised within the guard is not handed to the exception
+                    // handlers around it, until the guard has a value
+                    let outer = enter(Dynamic::Guard);
+                    self.stack.push(Entry {
+                        frame: frame(move |value| {
+                            WINDS.set(outer.clone());
+                            Ok(Tail::Value(value))
+                        }),
+                        within: self.within.clone(),
+                        handle: None,
+                    });
+                    self.check_depth(&expr).map(|()| Tail::Eval(env, expr))
+                }
+                Tail::Handle(env, expr, effects) => {
+                    self.install(Handle::Effects(effects));
+                    self.check_depth(&expr).map(|()| Tail::Eval(env, expr))
+                }
+                Tail::Perform(effect, value) => match self.perform(&effect, value.clone(), true) {
+                    Some(next) => next,
+                    None => Err(EvalError::Perform(
+                        Rc::new((effect, value)),
+                        self.within
+                            .clone()
+                            .unwrap_or(Value::List(List::from([]), None)),
+                    )),
+                },
+                Tail::Resume(resumption, value) => {
+                    let resumed = Err("the continuation of an effect can only be resumed once");
+                    match resumption.stack.replace(resumed) {
+                        Ok(stack) => rewind(&resumption.winds).and_then(|()| {
+                            self.stack.extend(stack);
+                            self.check_depth(&value).map(|()| Tail::Value(value))
+                        }),
+                        Err(reason) => Err(EvalError::Resume(
+                            reason.to_string(),
+                            self.within.clone().unwrap_or(value),
+                        )),
+                    }
+                }
+                Tail::Capture(procedure) => {
+                    let continuation = Rc::new(Continuation {
+                        run: self.id,
+                        stack: self.stack.clone(),
+                        winds: winds(),
+                    });
+                    apply(&procedure, &[resume(continuation)])
+                }
+            };
+            tail = self.handle(next)?;
+        }
+    }
+
+    /// the tail to continue with after `next`, resuming a continuation captured in this run,
+    /// or handing an error to the innermost handler on the stack
+    fn handle(&mut self, mut next: TailResult) -> TailResult {
+        loop {
+            match next {
+                Ok(tail) => return Ok(tail),
+                Err(EvalError::Unwind(continuation, value)) if continuation.run == self.id => {
+                    rewind(&continuation.winds)?;
+                    self.stack = continuation.stack.clone();
+                    self.within = None;
+                    return Ok(Tail::Value(value));
+                }
+                // unwinding to a run further out
+                Err(err @ EvalError::Unwind(_, _)) if err.resumable() => return Err(err),
+                // a limit ends evaluation, rather than being handled
+                Err(err @ EvalError::Limit(_, _)) => {
+                    let _ = rewind(&self.base);
+                    return Err(err);
+                }
+                // an effect performed in a run further in, which has returned,
+                // so it can be handled but not resumed
+                Err(EvalError::Perform(performed, form)) => {
+                    let (effect, value) = performed.as_ref();
+                    match self.perform(effect, value.clone(), false) {
+                        Some(handled) => next = handled,
+                        None => {
+                            let _ = rewind(&self.base);
+                            return Err(EvalError::Perform(performed, form));
+                        }
+                    }
+                }
+                // an exception handler installed within this run is called where the error
+                // was raised, and it is an error for it to return, as the error cannot be continued
+                Err(err) if handler().is_some_and(|(_, wind)| wind.depth > depth(&self.base)) => {
+                    let (handler, installed) = handler().expect("there is a handler");
+                    let form = err.form().clone();
+                    let condition = err.condition();
+                    let returned = condition.clone();
+                    next = Ok(Tail::Then(
+                        Env::global(),
+                        deferred(move || call_handler(&handler, &installed, condition.clone())),
+                        frame(move |_| {
+                            Err(EvalError::User(
+                                "an exception handler returned from a non-continuable raise"
+                                    .to_string(),
+                                Rc::from([returned.clone()]),
+                                form.clone(),
+                            ))
+                        }),
+                    ));
+                }
+                Err(err) => {
+                    let caught = self
+                        .stack
+                        .iter()
+                        .rposition(|entry| matches!(entry.handle, Some((Handle::Errors(_), _))));
+                    let Some(index) = caught else {
+                        // the error leaves every wind entered during the run
+                        let _ = rewind(&self.base);
+                        return Err(err);
+                    };
+                    let entry = self
+                        .stack
+                        .drain(index..)
+                        .next()
+                        .expect("index is in the stack");
+                    let Some((Handle::Errors(handler), winds)) = entry.handle else {
+                        unreachable!("the entry handles errors")
+                    };
+                    self.within = entry.within;
+                    next = rewind(&winds).and_then(|()| handler(err));
+                }
+            }
+        }
+    }
+
+    /// the error for more frames waiting than the limit on the depth of calls,
+    /// having pushed one for `expr`
+    fn check_depth(&self, expr: &Value) -> Result<(), EvalError> {
+        match self.outer + self.stack.len() > self.max_depth {
+            true => Err(limits::too_deep(expr.clone())),
+            false => Ok(()),
+        }
+    }
+
+    /// pushes a frame which gives the value it is waiting on back unchanged,
+    /// and handles what is raised or performed until then
+    fn install(&mut self, handle: Handle) {
+        self.stack.push(Entry {
+            frame: frame(|value| Ok(Tail::Value(value))),
+            within: self.within.clone(),
+            handle: Some((handle, winds())),
+        });
+    }
+
+    /// hands an effect to the clause for it of the innermost handle on the stack,
+    /// with the frames from the handle up taken off the stack, to be resumed by the clause.
+    /// `None` when there is no handle for it
+    fn perform(&mut self, effect: &Value, value: Value, resumable: bool) -> Option<TailResult> {
+        let (index, clause) =
+            self.stack
+                .iter()
+                .enumerate()
+                .rev()
+                .find_map(|(i, entry)| match &entry.handle {
+                    Some((Handle::Effects(effects), _)) => effects
+                        .names
+                        .iter()
+                        .position(|name| name == effect)
+                        .map(|clause| (i, clause)),
+                    _ => None,
+                })?;
+        // the handle is resumed with the frames above it, so it handles the effects
+        // performed after the computation is resumed too
+        let stack: Stack = self.stack.drain(index..).collect();
+        let Some((Handle::Effects(effects), winds)) = stack[0].handle.clone() else {
+            unreachable!("the entry handles effects")
+        };
+        self.within = stack[0].within.clone();
+        let resumption = Rc::new(Resumption {
+            stack: RefCell::new(match resumable {
+                true => Ok(stack),
+                false => Err(
+                    "the effect was performed within an evaluation which has returned, \
+                    such as the expansion of a macro, so it cannot be resumed",
+                ),
+            }),
+            winds: self::winds(),
+        });
+        Some(rewind(&winds).and_then(|()| (effects.clause)(clause, value, resumption.procedure())))
+    }
+}
+
+/// the frames between a `handle` and an effect performed within it, taken off the stack
+/// until they are resumed, which can only happen once
+pub struct Resumption {
+    /// the frames, or why they cannot be resumed
+    stack: RefCell<Result<Stack, &'static str>>,
+    winds: Winds,
+}
+
+impl Resumption {
+    /// a procedure which resumes the frames with its argument, or `()` without one
+    fn procedure(self: Rc<Self>) -> Value {
+        Value::Procedure(
+            Env::global(),
+            Rc::new(move |_, env, exprs| {
+                let resumption = self.clone();
+                evaluate_all(&env, exprs, move |mut values| match values.len() {
+                    0 => Ok(Tail::Resume(
+                        resumption.clone(),
+                        Value::List(List::from([]), None),
+                    )),
+                    1 => Ok(Tail::Resume(resumption.clone(), values.remove(0))),
+                    _ => Err(arity("resume", "0 or 1 arguments", &values)),
+                })
+            }),
+            Rc::new("[value]"),
+        )
+    }
+}
+
+impl Continuation {
+    /// whether the run it was captured in is still running
+    pub fn resumable(&self) -> bool {
+        RUNS.with_borrow(|runs| runs.contains(&self.run))
+    }
+}
+
+/// (call/cc procedure)
+/// calls procedure with the continuation of the call
+fn call_cc(env: Env) -> Value {
+    Value::Procedure(
+        env,
+        Rc::new(|_, env, exprs| match exprs {
+            [_] => evaluate_all(&env, exprs, |mut values| {
+                Ok(Tail::Capture(values.remove(0)))
+            }),
+            _ => Err(arity("call/cc", "1 argument", exprs)),
+        }),
+        Rc::new("procedure"),
+    )
+}
+
+/// (dynamic-wind before thunk after)
+/// calls before, thunk then after, giving the value of thunk.
+/// before is called again whenever a continuation enters thunk,
+/// and after whenever a continuation leaves it
+fn dynamic_wind(env: Env) -> Value {
+    Value::Procedure(
+        env,
+        Rc::new(|_, env, exprs| match exprs {
+            [_, _, _] => evaluate_all(&env, exprs, |values| {
+                let [before, thunk, after] = <[Value; 3]>::try_from(values).expect("3 arguments");
+                then(apply(&before, &[])?, move |_| {
+                    let outer = enter(Dynamic::Wind(before.clone(), after.clone()));
+                    let after = after.clone();
+                    then(apply(&thunk, &[])?, move |value| {
+                        WINDS.set(outer.clone());
+                        then(apply(&after, &[])?, move |_| Ok(Tail::Value(value.clone())))
+                    })
+                })
+            }),
+            _ => Err(arity("dynamic-wind", "3 arguments", exprs)),
+        }),
+        Rc::new("before thunk after"),
+    )
+}
+
+pub fn bindings(env: Env) -> Vec<env::Value<&'static str, Value>> {
+    vec![
+        env::Value("call/cc", call_cc(env.clone())),
+        env::Value("call-with-current-continuation", call_cc(env.clone())),
+        env::Value("dynamic-wind", dynamic_wind(env)),
+    ]
+}
+
+#[test]
+fn continuations_test() {
+    use super::test::{eval, interpret_str};
+    assert_eq!(
+        Some("6".to_string()),
+        eval("(+ 1 (call/cc (lambda (k) (+ 10 (k 5)))))")
+    );
+    // escaping from within a procedure called by a builtin
+    assert_eq!(
+        Some("2".to_string()),
+        eval("(call/cc (lambda (k) (map (lambda (x) (if? (= x 2) (k x) x)) '(1 2 3))))")
+    );
+    // resuming a continuation again, after the call/cc it was captured by returned
+    assert_eq!(
+        Some("(3 2 1 0)".to_string()),
+        eval(
+            "(let ((k (box #f)) (seen (box '())))
+                (begin
+                  (define n (call/cc (lambda (c) (begin (define _ (set-box! k c)) 0))))
+                  (define _ (set-box! seen (cons n (unbox seen))))
+                  (if? (< n 3) ((unbox k) (+ n 1)) (unbox seen))))"
+        )
+    );
+    // and resuming one captured within a procedure called by a builtin, which continues
+    // the builtin with what it had collected then
+    assert_eq!(
+        Some("(1 20 3)".to_string()),
+        eval("(let ((k (box #f)) (n (box 0)))
+                (let ((r (map (lambda (x) (call/cc (lambda (c) (if? (= x 2) (let ((_ (set-box! k c))) x) x)))) '(1 2 3))))
+                  (let ((_ (set-box! n (+ (unbox n) 1))))
+                    (if? (< (unbox n) 3) ((unbox k) (* 10 (unbox n))) r))))")
+    );
+    assert_eq!(
+        Some("(escaped (out in))".to_string()),
+        eval("(let ((log (box '())))
+                (begin
+                  (define (note x) (set-box! log (cons x (unbox log))))
+                  (define r (call/cc (lambda (k)
+                    (dynamic-wind (lambda () (note 'in)) (lambda () (k 'escaped)) (lambda () (note 'out))))))
+                  (list r (unbox log))))")
+    );
+    assert_eq!(
+        Some("(1 2 3 4 (nested 5))".to_string()),
+        eval("`(1 ,(+ 1 1) ,@(list 3 4) (nested ,(call/cc (lambda (k) (k 5)))))")
+    );
+    // a continuation cannot be resumed once the top level form it was captured in has returned
+    assert!(matches!(
+        interpret_str("(define k #f) (list 1 (call/cc (lambda (c) (set! k c)))) (k 1)"),
+        Err(EvalError::Unwind(_, _))
+    ));
+}
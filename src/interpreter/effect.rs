@@ -0,0 +1,134 @@
+//! effect handlers. `perform` hands a value to the innermost `handle` with a clause for
+//! the effect, along with a procedure which resumes the computation from the perform,
+//! so that control flow such as generators can be written in sl itself
+
+use std::rc::Rc;
+
+use super::{
+    cps::{evaluate_all, Effects},
+    env,
+    inbuilt::{arity, no_match},
+    Env, EvalError, List, Tail, Value,
+};
+
+/// (perform effect [value])
+fn perform(env: Env) -> Value {
+    Value::Procedure(
+        env,
+        Rc::new(|_, env, exprs| match exprs {
+            [_] | [_, _] => evaluate_all(&env, exprs, |mut values| {
+                let value = match values.len() {
+                    2 => values.remove(1),
+                    _ => Value::List(List::from([]), None),
+                };
+                Ok(Tail::Perform(values.remove(0), value))
+            }),
+            _ => Err(arity("perform", "1 or 2 arguments", exprs)),
+        }),
+        Rc::new("effect [value]"),
+    )
+}
+
+/// (handle body (effect (value resume) clause)...)
+/// evaluates body, and when it performs an effect with a clause, evaluates the clause
+/// in place of the handle, with the value performed and a procedure to resume body with
+fn handle(env: Env) -> Value {
+    Value::Procedure(
+        env,
+        Rc::new(|_, env, exprs| {
+            let [body, clauses @ ..] = exprs else {
+                return Err(no_match("handle", exprs));
+            };
+            let mut names = Vec::with_capacity(clauses.len());
+            let mut params = Vec::with_capacity(clauses.len());
+            let mut bodies = Vec::with_capacity(clauses.len());
+            for clause in clauses {
+                let Value::List(parts, _) = clause else {
+                    return Err(clause_error(clause));
+                };
+                let [name @ Value::Symbol(_, _), Value::List(bindings, _), body] = parts.as_ref()
+                else {
+                    return Err(clause_error(clause));
+                };
+                let [Value::Symbol(value, _), Value::Symbol(resume, _)] = bindings.as_ref() else {
+                    return Err(clause_error(clause));
+                };
+                names.push(name.clone());
+                params.push((*value, *resume));
+                bodies.push(body.clone());
+            }
+            let caller = env.clone();
+            let clause = Box::new(move |index: usize, value, resume| {
+                let bound = [
+                    env::Value(params[index].0, value),
+                    env::Value(params[index].1, resume),
+                ];
+                let env = caller.bind(env::Values::new(bound));
+                Ok(Tail::Eval(env, bodies[index].clone()))
+            });
+            let effects = Rc::new(Effects { names, clause });
+            Ok(Tail::Handle(env, body.clone(), effects))
+        }),
+        Rc::new("body (effect (value resume) clause)..."),
+    )
+}
+
+fn clause_error(clause: &Value) -> EvalError {
+    EvalError::BadForm(
+        "did not match the (effect (value resume) clause) form of macro procedure \"handle\"'s clauses".to_string(),
+        clause.clone(),
+    )
+}
+
+pub fn bindings(env: Env) -> Vec<env::Value<&'static str, Value>> {
+    vec![
+        env::Value("perform", perform(env.clone())),
+        env::Value("handle", handle(env)),
+    ]
+}
+
+#[test]
+fn effects_test() {
+    use super::test::{eval, interpret_str};
+    // a generator, resumed once for each value it yields
+    assert_eq!(
+        Some("(1 2 3)".to_string()),
+        eval("(let ((out (box '())))
+                (let ((_ (handle (let ((_ (perform 'yield 1)) (_ (perform 'yield 2))) (perform 'yield 3))
+                           (yield (x resume) (resume (set-box! out (cons x (unbox out))))))))
+                  (reverse (unbox out))))")
+    );
+    // the value of a clause which does not resume is the value of the handle
+    assert_eq!(
+        Some("(failed oops)".to_string()),
+        eval("(handle (+ 1 (perform 'fail 'oops)) (fail (why resume) (list 'failed why)))")
+    );
+    // effects pass through handles without a clause for them, and through guards
+    assert_eq!(
+        Some("(inner 6)".to_string()),
+        eval("(handle (handle (guard (e (#t 'caught)) (list (perform 'a) (perform 'b 3))) (a (_ resume) (resume 'inner)))
+                (b (x resume) (resume (* x 2))))")
+    );
+    // effects performed within the procedures a builtin calls are resumed within the builtin
+    assert_eq!(
+        Some("(10 20 30)".to_string()),
+        eval("(handle (map (lambda (x) (perform 'yield x)) '(1 2 3)) (yield (x k) (k (* x 10))))")
+    );
+    assert_eq!(
+        Some("((2 4) 14 (1 2 3 4 5))".to_string()),
+        eval("(handle (list (filter (lambda (x) (perform 'even? x)) '(1 2 3 4))
+                            (fold-left (lambda (acc x) (+ acc (perform 'square x))) 0 '(1 2 3))
+                            (sort '(3 1 2 5 4) (lambda (a b) (perform 'less? (list a b)))))
+                (even? (x k) (k (even? x)))
+                (square (x k) (k (* x x)))
+                (less? (pair k) (k (< (car pair) (car (cdr pair))))))")
+    );
+    assert!(matches!(
+        interpret_str("(handle (perform 'twice) (twice (_ resume) (list (resume 1) (resume 2))))"),
+        Err(EvalError::Resume(_, _))
+    ));
+    assert!(matches!(
+        interpret_str("(perform 'nobody 1)"),
+        Err(EvalError::Perform(_, _))
+    ));
+}
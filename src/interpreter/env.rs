@@ -1,119 +1,429 @@
+//! environments, the frames of bindings which names are looked up in with [Lookup].
+//! names are resolved before they are evaluated: the compiler of the [super::vm] and the
+//! resolution pass of the tree-walker, [super::resolve], give each local the depth and
+//! index of its slot in a frame, and send every other name to the [Global] frame.
+//! names are only looked up by name, from the innermost frame out, in what is evaluated
+//! with `eval`, in the expansions of macros called while evaluating, and by the debug dump
+
 use core::fmt;
-use std::{marker::PhantomData, rc::Rc};
+use std::{cell::RefCell, collections::HashMap, hash::Hash, rc::Rc};
+
+use super::Symbol;
 
 pub trait Lookup<K: PartialEq, V> {
-    fn lookup<'a>(&'a self, id: &K) -> Option<&'a V>;
+    fn lookup(&self, id: &K) -> Option<V>;
+    /// replaces the value bound to `id`, giving the value back if `id` is not bound
+    fn set(&self, id: &K, value: V) -> Result<(), V>;
+    /// binds `id` in the [Global] frame, replacing any value it had,
+    /// giving the value back if there is no global frame
+    fn define(&self, _id: &K, value: V) -> Result<(), V> {
+        Err(value)
+    }
     fn debug(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result;
+    /// the value in slot `index` of the frame `depth` frames out from this one,
+    /// for a name resolved to its slot. frames without slots have no value in any
+    fn slot(&self, _depth: usize, _index: usize) -> Option<V> {
+        None
+    }
+    /// replaces the value in a slot which has one, see [Lookup::slot]
+    fn set_slot(&self, _depth: usize, _index: usize, value: V) -> Result<(), V> {
+        Err(value)
+    }
+    /// looks `id` up in the [Global] frame, past the frames around it
+    fn lookup_global(&self, id: &K) -> Option<V> {
+        self.lookup(id)
+    }
+    /// replaces the value bound to `id` in the [Global] frame, see [Lookup::lookup_global]
+    fn set_global(&self, id: &K, value: V) -> Result<(), V> {
+        self.set(id, value)
+    }
 }
 
-pub struct Debug<K: PartialEq, V, L: Lookup<K, V>>(L, PhantomData<K>, PhantomData<V>);
-impl<K: PartialEq, V, L: Lookup<K, V>> Debug<K, V, L> {
-    pub fn new(lookup: L) -> Self {
-        Self(lookup, PhantomData, PhantomData)
-    }
+/// where a name is bound, resolved before the expression it is in is evaluated
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Address {
+    /// slot `index` of the frame `depth` frames out from the innermost
+    Local { depth: usize, index: usize },
+    /// the [Global] frame, where the name is looked up by name
+    Global,
 }
-impl<K: PartialEq, V, L: Lookup<K, V>> fmt::Debug for Debug<K, V, L> {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        self.0.debug(f)
+
+/// a name and its value
+pub struct Value<K: PartialEq + fmt::Debug, V: fmt::Debug>(pub K, pub V);
+
+/// a frame of bindings stored as a flat vector, so that code which has resolved its names
+/// to indices can address the slots directly. the names are kept to look the bindings up by
+/// name. slots are empty until they are set, and empty slots are not found
+pub struct Frame<K: PartialEq + fmt::Debug, V: fmt::Debug> {
+    names: Rc<[K]>,
+    slots: RefCell<Box<[Option<V>]>>,
+}
+impl<K: PartialEq + fmt::Debug, V: Clone + fmt::Debug> Frame<K, V> {
+    /// a frame with every slot empty
+    pub fn new(names: Rc<[K]>) -> Self {
+        let slots = names.iter().map(|_| None).collect();
+        Self::with_slots(names, slots)
+    }
+
+    /// a frame of `names`, with the slot of each name at the same index
+    pub fn with_slots(names: Rc<[K]>, slots: Box<[Option<V>]>) -> Self {
+        debug_assert_eq!(names.len(), slots.len());
+        Self {
+            names,
+            slots: RefCell::new(slots),
+        }
+    }
+
+    pub fn get(&self, index: usize) -> Option<V> {
+        self.slots.borrow()[index].clone()
+    }
+
+    pub fn put(&self, index: usize, value: V) {
+        self.slots.borrow_mut()[index] = Some(value);
+    }
+
+    /// the index of `id`. a name given more than once refers to its last slot
+    fn index(&self, id: &K) -> Option<usize> {
+        self.names.iter().rposition(|name| name == id)
     }
 }
+impl<K: PartialEq + fmt::Debug, V: Clone + fmt::Debug> Lookup<K, V> for Frame<K, V> {
+    fn lookup(&self, id: &K) -> Option<V> {
+        self.get(self.index(id)?)
+    }
 
-pub struct Value<K: PartialEq + fmt::Debug, V: fmt::Debug>(pub K, pub V);
-impl<K: PartialEq + fmt::Debug, V: fmt::Debug> Lookup<K, V> for Value<K, V> {
-    fn lookup<'a>(&'a self, id: &K) -> Option<&'a V> {
-        if *id == self.0 {
-            Some(&self.1)
-        } else {
-            None
+    fn set(&self, id: &K, value: V) -> Result<(), V> {
+        match self.index(id) {
+            Some(index) => self.set_slot(0, index, value),
+            None => Err(value),
+        }
+    }
+
+    fn slot(&self, depth: usize, index: usize) -> Option<V> {
+        match depth {
+            0 => self.slots.borrow().get(index)?.clone(),
+            _ => None,
+        }
+    }
+
+    fn set_slot(&self, depth: usize, index: usize, value: V) -> Result<(), V> {
+        match self.slots.borrow_mut().get_mut(index) {
+            Some(slot @ Some(_)) if depth == 0 => {
+                *slot = Some(value);
+                Ok(())
+            }
+            _ => Err(value),
         }
     }
 
     fn debug(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "{:#?} -> {:#?}", self.0, self.1)
+        let last = self.names.len().saturating_sub(1);
+        let slots = self.slots.borrow();
+        self.names
+            .iter()
+            .zip(slots.iter())
+            .enumerate()
+            .try_for_each(|(i, (id, slot))| {
+                match slot {
+                    Some(value) => write!(f, "{:#?} -> {:#?}", id, value)?,
+                    None => write!(f, "{:#?} -> <unset>", id)?,
+                }
+                if last == i {
+                    Ok(())
+                } else {
+                    writeln!(f)
+                }
+            })
     }
 }
 
-pub struct Values<K: PartialEq + fmt::Debug, V: fmt::Debug, R: AsRef<[Value<K, V>]>>(
-    pub R,
-    PhantomData<K>,
-    PhantomData<V>,
-);
-impl<K: PartialEq + fmt::Debug, V: fmt::Debug, R: AsRef<[Value<K, V>]>> Values<K, V, R> {
-    pub fn new(vals: R) -> Self {
-        Self(vals, PhantomData, PhantomData)
+/// a frame of bindings, such as the arguments of a procedure
+pub struct Values<K: PartialEq + fmt::Debug, V: fmt::Debug>(Box<[(K, RefCell<V>)]>);
+impl<K: PartialEq + fmt::Debug, V: fmt::Debug> Values<K, V> {
+    pub fn new(vals: impl IntoIterator<Item = Value<K, V>>) -> Self {
+        Self(
+            vals.into_iter()
+                .map(|Value(id, value)| (id, RefCell::new(value)))
+                .collect(),
+        )
     }
 }
-impl<K: PartialEq + fmt::Debug, V: fmt::Debug, R: AsRef<[Value<K, V>]>> Lookup<K, V>
-    for Values<K, V, R>
-{
-    fn lookup<'a>(&'a self, id: &K) -> Option<&'a V> {
-        for Value(value_id, value) in self.0.as_ref() {
+impl<K: PartialEq + fmt::Debug, V: Clone + fmt::Debug> Lookup<K, V> for Values<K, V> {
+    fn lookup(&self, id: &K) -> Option<V> {
+        for (value_id, value) in self.0.iter() {
             if id == value_id {
-                return Some(value);
+                return Some(value.borrow().clone());
             }
         }
         None
     }
 
+    fn set(&self, id: &K, value: V) -> Result<(), V> {
+        match self.0.iter().position(|(value_id, _)| id == value_id) {
+            Some(index) => self.set_slot(0, index, value),
+            None => Err(value),
+        }
+    }
+
+    fn slot(&self, depth: usize, index: usize) -> Option<V> {
+        match depth {
+            0 => Some(self.0.get(index)?.1.borrow().clone()),
+            _ => None,
+        }
+    }
+
+    fn set_slot(&self, depth: usize, index: usize, value: V) -> Result<(), V> {
+        match self.0.get(index) {
+            Some((_, slot)) if depth == 0 => {
+                *slot.borrow_mut() = value;
+                Ok(())
+            }
+            _ => Err(value),
+        }
+    }
+
     fn debug(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        let last = self.0.as_ref().len() - 1;
-        self.0.as_ref().iter().enumerate().try_for_each(|(i, v)| {
-            v.debug(f)?;
+        let last = self.0.len().saturating_sub(1);
+        self.0.iter().enumerate().try_for_each(|(i, (id, value))| {
+            write!(f, "{:#?} -> {:#?}", id, value.borrow())?;
             if last == i {
                 Ok(())
             } else {
-                write!(f, "\n")
+                writeln!(f)
             }
         })
     }
 }
 
+/// the names a macro expansion introduced, paired with the names they were written as.
+/// they are renamed so they cannot capture or be captured by names where the macro is used,
+/// and refer to the original names in `L`, where the macro was defined
+pub struct Renamed<K: PartialEq + fmt::Debug, L>(pub Rc<[(K, K)]>, pub L);
+impl<K: PartialEq + fmt::Debug, V, L: Lookup<K, V>> Lookup<K, V> for Renamed<K, L> {
+    fn lookup(&self, id: &K) -> Option<V> {
+        let (_, original) = self.0.iter().find(|(renamed, _)| renamed == id)?;
+        self.1.lookup(original)
+    }
+
+    fn set(&self, id: &K, value: V) -> Result<(), V> {
+        match self.0.iter().find(|(renamed, _)| renamed == id) {
+            Some((_, original)) => self.1.set(original, value),
+            None => Err(value),
+        }
+    }
+
+    fn debug(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let last = self.0.len().saturating_sub(1);
+        self.0
+            .iter()
+            .enumerate()
+            .try_for_each(|(i, (renamed, original))| {
+                write!(f, "{:#?} -> renamed {:#?}", renamed, original)?;
+                if last == i {
+                    Ok(())
+                } else {
+                    writeln!(f)
+                }
+            })
+    }
+}
+
+/// the top level, where definitions can be added and replaced after it is built
+pub struct Global<K: Eq + Hash + fmt::Debug, V: fmt::Debug>(RefCell<HashMap<K, V>>);
+impl<K: Eq + Hash + fmt::Debug, V: fmt::Debug> Global<K, V> {
+    pub fn new() -> Self {
+        Self(RefCell::new(HashMap::new()))
+    }
+}
+impl<K: Eq + Hash + Clone + fmt::Debug, V: Clone + fmt::Debug> Lookup<K, V> for Global<K, V> {
+    fn lookup(&self, id: &K) -> Option<V> {
+        self.0.borrow().get(id).cloned()
+    }
+
+    fn set(&self, id: &K, value: V) -> Result<(), V> {
+        match self.0.borrow_mut().get_mut(id) {
+            Some(slot) => {
+                *slot = value;
+                Ok(())
+            }
+            None => Err(value),
+        }
+    }
+
+    fn define(&self, id: &K, value: V) -> Result<(), V> {
+        self.0.borrow_mut().insert(id.clone(), value);
+        Ok(())
+    }
+
+    fn debug(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "<{} global definitions>", self.0.borrow().len())
+    }
+}
+
 impl<K: PartialEq + fmt::Debug, V: fmt::Debug, L: Lookup<K, V>, R: Lookup<K, V>> Lookup<K, V>
     for (L, R)
 {
-    fn lookup<'a>(&'a self, id: &K) -> Option<&'a V> {
+    fn lookup(&self, id: &K) -> Option<V> {
         match self.0.lookup(id) {
             value @ Some(_) => value,
             None => self.1.lookup(id),
         }
     }
 
+    fn set(&self, id: &K, value: V) -> Result<(), V> {
+        self.0.set(id, value).or_else(|value| self.1.set(id, value))
+    }
+
+    fn define(&self, id: &K, value: V) -> Result<(), V> {
+        self.0
+            .define(id, value)
+            .or_else(|value| self.1.define(id, value))
+    }
+
     fn debug(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         self.0.debug(f)?;
-        write!(f, "\n")?;
+        writeln!(f)?;
         self.1.debug(f)
     }
+
+    fn slot(&self, depth: usize, index: usize) -> Option<V> {
+        match depth {
+            0 => self.0.slot(0, index),
+            _ => self.1.slot(depth - 1, index),
+        }
+    }
+
+    fn set_slot(&self, depth: usize, index: usize, value: V) -> Result<(), V> {
+        match depth {
+            0 => self.0.set_slot(0, index, value),
+            _ => self.1.set_slot(depth - 1, index, value),
+        }
+    }
+
+    fn lookup_global(&self, id: &K) -> Option<V> {
+        self.1.lookup_global(id)
+    }
+
+    fn set_global(&self, id: &K, value: V) -> Result<(), V> {
+        self.1.set_global(id, value)
+    }
+}
+
+/// a frame shared with whoever set it up, such as a [Frame] whose slots are set by index
+impl<K: PartialEq, V, L: Lookup<K, V> + ?Sized> Lookup<K, V> for Rc<L> {
+    fn lookup(&self, id: &K) -> Option<V> {
+        self.as_ref().lookup(id)
+    }
+
+    fn set(&self, id: &K, value: V) -> Result<(), V> {
+        self.as_ref().set(id, value)
+    }
+
+    fn define(&self, id: &K, value: V) -> Result<(), V> {
+        self.as_ref().define(id, value)
+    }
+
+    fn debug(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.as_ref().debug(f)
+    }
+
+    fn slot(&self, depth: usize, index: usize) -> Option<V> {
+        self.as_ref().slot(depth, index)
+    }
+
+    fn set_slot(&self, depth: usize, index: usize, value: V) -> Result<(), V> {
+        self.as_ref().set_slot(depth, index, value)
+    }
+
+    fn lookup_global(&self, id: &K) -> Option<V> {
+        self.as_ref().lookup_global(id)
+    }
+
+    fn set_global(&self, id: &K, value: V) -> Result<(), V> {
+        self.as_ref().set_global(id, value)
+    }
 }
 
 impl<K: PartialEq, V: fmt::Debug> Lookup<K, V> for () {
-    fn lookup<'a>(&'a self, _: &K) -> Option<&'a V> {
+    fn lookup(&self, _: &K) -> Option<V> {
         None
     }
 
+    fn set(&self, _: &K, value: V) -> Result<(), V> {
+        Err(value)
+    }
+
     fn debug(&self, _: &mut fmt::Formatter<'_>) -> fmt::Result {
         Ok(())
     }
 }
 
-pub struct NameEnv<'env, V: 'env + fmt::Debug>(Rc<dyn Lookup<&'env str, V> + 'env>);
-impl<'env, V: 'env + fmt::Debug> Lookup<&'env str, V> for NameEnv<'env, V> {
-    fn lookup<'a>(&'a self, id: &&'env str) -> Option<&'a V> {
+pub struct NameEnv<V: fmt::Debug>(Rc<dyn Lookup<Symbol, V> + 'static>);
+impl<V: fmt::Debug> Lookup<Symbol, V> for NameEnv<V> {
+    fn lookup(&self, id: &Symbol) -> Option<V> {
         self.0.lookup(id)
     }
 
+    fn set(&self, id: &Symbol, value: V) -> Result<(), V> {
+        self.0.set(id, value)
+    }
+
+    fn define(&self, id: &Symbol, value: V) -> Result<(), V> {
+        self.0.define(id, value)
+    }
+
     fn debug(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         self.0.debug(f)
     }
+
+    fn slot(&self, depth: usize, index: usize) -> Option<V> {
+        self.0.slot(depth, index)
+    }
+
+    fn set_slot(&self, depth: usize, index: usize, value: V) -> Result<(), V> {
+        self.0.set_slot(depth, index, value)
+    }
+
+    fn lookup_global(&self, id: &Symbol) -> Option<V> {
+        self.0.lookup_global(id)
+    }
+
+    fn set_global(&self, id: &Symbol, value: V) -> Result<(), V> {
+        self.0.set_global(id, value)
+    }
 }
-impl<'env, V: 'env + fmt::Debug> NameEnv<'env, V> {
-    pub fn new() -> Self {
-        Self(Rc::new(()))
+impl<V: fmt::Debug> NameEnv<V> {
+    /// the value of `id`, which was resolved to `address`
+    pub fn lookup_at(&self, id: &Symbol, address: Address) -> Option<V> {
+        match address {
+            Address::Local { depth, index } => self.slot(depth, index),
+            Address::Global => self.lookup_global(id),
+        }
     }
-    pub fn bind<L: Lookup<&'env str, V> + 'env>(&self, vals: L) -> Self {
+
+    /// replaces the value of `id`, which was resolved to `address`
+    pub fn set_at(&self, id: &Symbol, address: Address, value: V) -> Result<(), V> {
+        match address {
+            Address::Local { depth, index } => self.set_slot(depth, index, value),
+            Address::Global => self.set_global(id, value),
+        }
+    }
+}
+impl<V: 'static + Clone + fmt::Debug> NameEnv<V> {
+    /// an environment with only a [Global] frame
+    pub fn global() -> Self {
+        Self(Rc::new(Global::new()))
+    }
+    pub fn bind<L: Lookup<Symbol, V> + 'static>(&self, vals: L) -> Self {
         Self(Rc::new((vals, self.clone())))
     }
 }
-impl<'env, V: fmt::Debug> Clone for NameEnv<'env, V> {
+impl<V: fmt::Debug> fmt::Debug for NameEnv<V> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.debug(f)
+    }
+}
+impl<V: fmt::Debug> Clone for NameEnv<V> {
     fn clone(&self) -> Self {
         Self(self.0.clone())
     }
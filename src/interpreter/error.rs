@@ -0,0 +1,173 @@
+use core::fmt;
+
+use std::rc::Rc;
+
+use super::{cps::Continuation, resolve, Env, Value};
+use crate::fastpass;
+
+/// the ways evaluating an expression can fail
+/// each variant carries the form responsible for the failure,
+/// which is displayed with its location when it was read from source
+///
+/// the alternate display (`{:#}`) additionally dumps the environment of unbound variables
+#[derive(Clone, Debug)]
+pub enum EvalError {
+    /// a symbol with no value bound to it, and the environment it was looked up in
+    Unbound(Value, Env),
+    /// a procedure applied to the wrong number of arguments
+    Arity(String, Value),
+    /// a value of the wrong kind, such as calling a non-procedure
+    Type(String, Value),
+    /// an arithmetic operation with no result, such as division by zero
+    Arithmetic(String, Value),
+    /// a macro procedure given a form it does not understand
+    BadForm(String, Value),
+    /// an error raised by the script itself with `error`, its message and irritants
+    User(String, Rc<[Value]>, Value),
+    /// a value raised with `raise` which nothing handled
+    Raise(Value, Value),
+    /// a continuation resumed with a value, unwinding to the run it was captured in.
+    /// only an error once that run has returned, see [super::cps]
+    Unwind(Rc<Continuation>, Value),
+    /// an effect and the value it was performed with, with no `handle` for it around it
+    Perform(Rc<(Value, Value)>, Value),
+    /// a continuation which cannot be resumed, and the value it was resumed with
+    Resume(String, Value),
+    /// a limit on evaluation which was exceeded, see [super::limits]
+    Limit(String, Value),
+    /// a builtin from a capability which was not granted, see [super::capability]
+    Capability(String, Value),
+}
+
+impl EvalError {
+    pub fn message(&self) -> String {
+        match self {
+            EvalError::Unbound(form, _) => format!("unable to find value for name \"{form}\""),
+            EvalError::Arity(message, _)
+            | EvalError::Type(message, _)
+            | EvalError::Arithmetic(message, _)
+            | EvalError::BadForm(message, _)
+            | EvalError::Resume(message, _)
+            | EvalError::Limit(message, _)
+            | EvalError::Capability(message, _) => message.clone(),
+            EvalError::User(message, irritants, _) => {
+                let mut message = message.clone();
+                for irritant in irritants.iter() {
+                    message.push_str(&format!(" {irritant}"));
+                }
+                message
+            }
+            EvalError::Raise(value, _) => value.to_string(),
+            EvalError::Perform(performed, _) => {
+                let (effect, value) = performed.as_ref();
+                format!("no handle for effect {effect}, performed with {value}")
+            }
+            EvalError::Unwind(_, _) => {
+                "a continuation was resumed after the evaluation it was captured in returned"
+                    .to_string()
+            }
+        }
+    }
+
+    /// the message of an error object, which leaves out the irritants of a user error
+    pub fn reason(&self) -> String {
+        match self {
+            EvalError::User(message, _, _) => message.clone(),
+            err => err.message(),
+        }
+    }
+
+    /// the irritants of an error object, the form responsible for errors other than user errors
+    pub fn irritants(&self) -> Rc<[Value]> {
+        match self {
+            EvalError::User(_, irritants, _) => irritants.clone(),
+            err => Rc::from([resolve::unresolve(err.form())]),
+        }
+    }
+
+    pub fn form(&self) -> &Value {
+        match self {
+            EvalError::Unbound(form, _)
+            | EvalError::Arity(_, form)
+            | EvalError::Type(_, form)
+            | EvalError::Arithmetic(_, form)
+            | EvalError::BadForm(_, form)
+            | EvalError::User(_, _, form)
+            | EvalError::Raise(_, form)
+            | EvalError::Unwind(_, form)
+            | EvalError::Perform(_, form)
+            | EvalError::Resume(_, form)
+            | EvalError::Limit(_, form)
+            | EvalError::Capability(_, form) => form,
+        }
+    }
+
+    /// whether this is a continuation unwinding to a run which is still running,
+    /// rather than an error
+    pub fn resumable(&self) -> bool {
+        matches!(self, EvalError::Unwind(continuation, _) if continuation.resumable())
+    }
+
+    /// attaches the source location of `form` to an error raised while evaluating it,
+    /// unless the error already points somewhere in the source
+    pub fn within(self, form: &Value) -> Self {
+        // the value a continuation is resumed with is kept until it reaches its run
+        if self.resumable() || self.form().span().is_some() || form.span().is_none() {
+            return self;
+        }
+        let form = form.clone();
+        match self {
+            EvalError::Unbound(_, env) => EvalError::Unbound(form, env),
+            EvalError::Arity(message, _) => EvalError::Arity(message, form),
+            EvalError::Type(message, _) => EvalError::Type(message, form),
+            EvalError::Arithmetic(message, _) => EvalError::Arithmetic(message, form),
+            EvalError::BadForm(message, _) => EvalError::BadForm(message, form),
+            EvalError::User(message, irritants, _) => EvalError::User(message, irritants, form),
+            EvalError::Raise(value, _) => EvalError::Raise(value, form),
+            EvalError::Unwind(continuation, _) => EvalError::Unwind(continuation, form),
+            EvalError::Perform(performed, _) => EvalError::Perform(performed, form),
+            EvalError::Resume(message, _) => EvalError::Resume(message, form),
+            EvalError::Limit(message, _) => EvalError::Limit(message, form),
+            EvalError::Capability(message, _) => EvalError::Capability(message, form),
+        }
+    }
+
+    /// the value an exception handler is given for this error,
+    /// the value raised by `raise`, or an error object for any other error
+    pub fn condition(self) -> Value {
+        match self {
+            EvalError::Raise(value, _) => value,
+            err => Value::Error(Rc::new(err)),
+        }
+    }
+
+    pub fn kind(&self) -> &'static str {
+        match self {
+            EvalError::Unbound(_, _) => "unbound variable",
+            EvalError::Arity(_, _) => "arity error",
+            EvalError::Type(_, _) => "type error",
+            EvalError::Arithmetic(_, _) => "arithmetic error",
+            EvalError::BadForm(_, _) => "bad form",
+            EvalError::User(_, _, _) => "error",
+            EvalError::Raise(_, _) => "uncaught exception",
+            EvalError::Unwind(_, _) | EvalError::Resume(_, _) => "continuation",
+            EvalError::Perform(_, _) => "unhandled effect",
+            EvalError::Limit(_, _) => "limit exceeded",
+            EvalError::Capability(_, _) => "capability not granted",
+        }
+    }
+}
+
+impl fmt::Display for EvalError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{}: {}", self.kind(), self.message())?;
+        write!(f, "in: {}", self.form())?;
+        if let Some(span) = self.form().span() {
+            write!(f, "\n{}", fastpass::Display(span))?;
+        }
+        match self {
+            EvalError::Unbound(_, env) if f.alternate() => write!(f, "\nenv:\n{env:?}"),
+            _ => Ok(()),
+        }
+    }
+}
@@ -0,0 +1,293 @@
+//! raising and handling exceptions.
+//! any value can be raised with `raise`. errors from `error`, and from the interpreter
+//! itself, are handled as error objects, see [EvalError::condition]
+
+use std::rc::Rc;
+
+use super::{
+    cps::{self, atom, evaluate_all, frame, Handler},
+    env,
+    inbuilt::{arity, function, no_match},
+    limits,
+    symbol::{self, Symbol},
+    Env, EvalError, EvalResult, List, Tail, TailResult, Value,
+};
+
+fn error_object(value: &Value) -> Result<&Rc<EvalError>, EvalError> {
+    match value {
+        Value::Error(err) => Ok(err),
+        _ => Err(EvalError::Type(
+            format!("expected an error object, got {value}"),
+            value.clone(),
+        )),
+    }
+}
+
+/// (raise value)
+fn raise(args: &[Value]) -> EvalResult {
+    match args {
+        // an error object is raised as the error it was made from
+        [Value::Error(err)] => Err(err.as_ref().clone()),
+        [value] => Err(EvalError::Raise(
+            value.clone(),
+            Value::List(List::from(args), None),
+        )),
+        _ => Err(arity("raise", "1 argument", args)),
+    }
+}
+
+/// (raise-continuable value)
+/// calls the innermost exception handler with value, giving what the handler gives back
+fn raise_continuable(env: Env) -> Value {
+    Value::Procedure(
+        env,
+        Rc::new(|_, env, exprs| match exprs {
+            [_] => {
+                let form = Value::List(List::from(exprs), None);
+                evaluate_all(&env, exprs, move |mut values| {
+                    cps::raise_continuable(values.remove(0), form.clone())
+                })
+            }
+            _ => Err(arity("raise-continuable", "1 argument", exprs)),
+        }),
+        Rc::new("value"),
+    )
+}
+
+/// (with-exception-handler handler thunk)
+/// calls thunk, and if it raises, calls handler with what was raised where it was raised,
+/// with the handlers around this one installed. it is an error for handler to return,
+/// unless what was raised was raised by `raise-continuable`
+fn with_exception_handler(env: Env) -> Value {
+    Value::Procedure(
+        env,
+        Rc::new(|_, env, exprs| match exprs {
+            [_, _] => evaluate_all(&env, exprs, |values| {
+                let [handler, thunk] = <[Value; 2]>::try_from(values).expect("2 arguments");
+                cps::with_exception_handler(handler, thunk)
+            }),
+            _ => Err(arity("with-exception-handler", "2 arguments", exprs)),
+        }),
+        Rc::new("handler thunk"),
+    )
+}
+
+/// (guard (name (cond body)...) body)
+/// evaluates body, and if it raises, binds what was raised to name and evaluates the body
+/// of the first clause whose cond passes. when none do, the error is raised again
+fn guard(env: Env) -> Value {
+    Value::Procedure(
+        env,
+        Rc::new(|_, env, exprs| {
+            let [Value::List(spec, _), body] = exprs else {
+                return Err(no_match("guard", exprs));
+            };
+            let [Value::Symbol(name, _), clauses @ ..] = spec.as_ref() else {
+                return Err(no_match("guard", exprs));
+            };
+            let clauses = clauses
+                .iter()
+                .map(|clause| match clause {
+                    Value::List(parts, _) => match parts.as_ref() {
+                        [cond, body] => Ok([cond.clone(), body.clone()]),
+                        _ => Err(clause_error(clause)),
+                    },
+                    _ => Err(clause_error(clause)),
+                })
+                .collect::<Result<Rc<[_]>, _>>()?;
+            let (name, caller) = (*name, env.clone());
+            let handler: Handler = Rc::new(move |err| {
+                let condition = err.clone().condition();
+                let env = caller.bind(env::Values::new([env::Value(name, condition)]));
+                guard_clauses(env, clauses.clone(), 0, err)
+            });
+            Ok(Tail::Catch(env, body.clone(), handler))
+        }),
+        Rc::new("(name (cond body)...) body"),
+    )
+}
+
+fn clause_error(clause: &Value) -> EvalError {
+    EvalError::BadForm(
+        "did not match the (cond body) form of macro procedure \"guard\"'s clauses".to_string(),
+        clause.clone(),
+    )
+}
+
+/// tests the cond of each clause from `from` in turn, evaluating the body of the first
+/// which passes, or raising `err` again if none do
+fn guard_clauses(env: Env, clauses: Rc<[[Value; 2]]>, from: usize, err: EvalError) -> TailResult {
+    for (i, [cond, body]) in clauses.iter().enumerate().skip(from) {
+        if let Value::Symbol(symbol::ELSE, _) = cond {
+            return Ok(Tail::Eval(env, body.clone()));
+        }
+        match atom(&env, cond)? {
+            Some(cond) if cond.truthy() => return Ok(Tail::Eval(env, body.clone())),
+            Some(_) => (),
+            // the rest of the clauses are tested once the cond has a value
+            None => {
+                let (caller, body, clauses) = (env.clone(), body.clone(), clauses.clone());
+                return Ok(Tail::Then(
+                    env,
+                    cond.clone(),
+                    frame(move |cond| match cond.truthy() {
+                        true => Ok(Tail::Eval(caller.clone(), body.clone())),
+                        false => guard_clauses(caller.clone(), clauses.clone(), i + 1, err.clone()),
+                    }),
+                ));
+            }
+        }
+    }
+    Err(err)
+}
+
+fn is_error_object(args: &[Value]) -> EvalResult {
+    match args {
+        [value] => Ok(Value::Bool(matches!(value, Value::Error(_)))),
+        _ => Err(arity("error-object?", "1 argument", args)),
+    }
+}
+
+fn error_object_message(args: &[Value]) -> EvalResult {
+    match args {
+        [value] => {
+            let reason = error_object(value)?.reason();
+            limits::allocate_bytes(reason.len())?;
+            Ok(Value::String(reason.into()))
+        }
+        _ => Err(arity("error-object-message", "1 argument", args)),
+    }
+}
+
+fn error_object_irritants(args: &[Value]) -> EvalResult {
+    match args {
+        [value] => Ok(Value::List(
+            List::from(&*error_object(value)?.irritants()),
+            None,
+        )),
+        _ => Err(arity("error-object-irritants", "1 argument", args)),
+    }
+}
+
+/// the kind of error, such as `type-error` or `unbound-variable`
+fn error_object_kind(args: &[Value]) -> EvalResult {
+    match args {
+        [value] => {
+            let kind = error_object(value)?.kind().replace(' ', "-");
+            Ok(Value::Symbol(Symbol::new(&kind), None))
+        }
+        _ => Err(arity("error-object-kind", "1 argument", args)),
+    }
+}
+
+pub fn bindings(env: Env) -> Vec<env::Value<&'static str, Value>> {
+    vec![
+        env::Value("raise", function(env.clone(), "value", raise)),
+        env::Value("raise-continuable", raise_continuable(env.clone())),
+        env::Value(
+            "with-exception-handler",
+            with_exception_handler(env.clone()),
+        ),
+        env::Value("guard", guard(env.clone())),
+        env::Value(
+            "error-object?",
+            function(env.clone(), "value", is_error_object),
+        ),
+        env::Value(
+            "error-object-message",
+            function(env.clone(), "error", error_object_message),
+        ),
+        env::Value(
+            "error-object-irritants",
+            function(env.clone(), "error", error_object_irritants),
+        ),
+        env::Value(
+            "error-object-kind",
+            function(env, "error", error_object_kind),
+        ),
+    ]
+}
+
+#[test]
+fn exceptions_test() {
+    use super::test::{eval, interpret_str};
+    assert_eq!(
+        Some("(\"bad input\" (1 2))".to_string()),
+        eval("(guard (e ((error-object? e) (list (error-object-message e) (error-object-irritants e))))
+                (error \"bad input\" 1 2))")
+    );
+    // errors of the interpreter itself are error objects too
+    assert_eq!(
+        Some("(unbound-variable arity-error type-error)".to_string()),
+        eval(
+            "(map (lambda (thunk) (guard (e (#t (error-object-kind e))) (thunk)))
+                   (list (lambda () undefined) (lambda () ((lambda (x) x))) (lambda () (car 1))))"
+        )
+    );
+    // a handler is called where the exception was raised, and continues from there
+    // when it was raised by raise-continuable
+    assert_eq!(
+        Some("43".to_string()),
+        eval("(with-exception-handler (lambda (e) 42) (lambda () (+ (raise-continuable 1) 1)))")
+    );
+    assert_eq!(
+        Some("(handled oops)".to_string()),
+        eval("(call/cc (lambda (k)
+                (with-exception-handler (lambda (e) (k (list 'handled e))) (lambda () (+ 1 (raise 'oops))))))")
+    );
+    // but it is an error for it to return from any other exception
+    assert_eq!(
+        Some("(error oops)".to_string()),
+        eval("(guard (e ((error-object? e) (list (error-object-kind e) (car (error-object-irritants e)))))
+                (with-exception-handler (lambda (e) 42) (lambda () (+ 1 (raise 'oops)))))")
+    );
+    // the handler is called in the dynamic extent of the raise
+    assert_eq!(
+        Some("(in handler out)".to_string()),
+        eval("(let ((log (box '())))
+                (begin
+                  (define (note x) (set-box! log (cons x (unbox log))))
+                  (define _ (with-exception-handler (lambda (e) (note 'handler))
+                    (lambda () (dynamic-wind (lambda () (note 'in)) (lambda () (raise-continuable 1)) (lambda () (note 'out))))))
+                  (reverse (unbox log))))")
+    );
+    // with the handlers around it installed, and within any guard it is installed in
+    assert_eq!(
+        Some("(outer (inner 1))".to_string()),
+        eval(
+            "(with-exception-handler (lambda (e) (list 'outer e))
+                (lambda () (with-exception-handler (lambda (e) (raise-continuable (list 'inner e)))
+                  (lambda () (raise-continuable 1)))))"
+        )
+    );
+    assert_eq!(
+        Some("guarded".to_string()),
+        eval(
+            "(with-exception-handler (lambda (e) 'handled)
+                (lambda () (guard (e (#t 'guarded)) (raise-continuable 1))))"
+        )
+    );
+    assert_eq!(
+        Some("(handled type-error)".to_string()),
+        eval("(call/cc (lambda (k) (with-exception-handler (lambda (e) (k (list 'handled (error-object-kind e))))
+                (lambda () (map (lambda (x) (car x)) '(1 2))))))")
+    );
+    assert!(matches!(
+        interpret_str("(raise-continuable 'unhandled)"),
+        Err(EvalError::Raise(_, _))
+    ));
+    // a guard with no clause which passes raises again, to the guard around it
+    assert_eq!(
+        Some("(outer 5)".to_string()),
+        eval("(guard (e (#t (list 'outer e))) (guard (e ((string? e) 'inner)) (raise 5)))")
+    );
+    assert!(matches!(
+        interpret_str("(guard (e ((string? e) 'caught)) (raise 'uncaught))"),
+        Err(EvalError::Raise(Value::Symbol(_, _), _))
+    ));
+    // continuations pass through handlers, rather than being caught by them
+    assert_eq!(
+        Some("escaped".to_string()),
+        eval("(call/cc (lambda (k) (guard (e (#t 'caught)) (k 'escaped))))")
+    );
+}
@@ -0,0 +1,677 @@
+use core::fmt::Display;
+use std::{cell::RefCell, collections::HashMap, rc::Rc};
+
+use super::{
+    cells::WeakList,
+    cps::evaluate,
+    env,
+    env::Lookup,
+    inbuilt::arity,
+    resolve,
+    symbol::{self, Symbol},
+    Env, EvalError, List, Tail, TailResult, Value,
+};
+use crate::ast::Span;
+
+/// what a macro call expands to
+#[derive(Clone)]
+pub struct Expansion {
+    pub expr: Value,
+    /// the names the expansion introduced, paired with the names they were written as,
+    /// see [env::Renamed]
+    pub renames: Rc<[(Symbol, Symbol)]>,
+}
+
+impl Expansion {
+    /// an expansion which introduces no names of its own
+    pub fn new(expr: Value) -> Self {
+        Expansion {
+            expr,
+            renames: Rc::from([]),
+        }
+    }
+}
+
+pub type ExpandResult = Result<Expansion, EvalError>;
+
+/// expands the unevaluated arguments of a call, given the environment the macro was defined in
+type MacroFn = Box<dyn Fn(&Env, &[Value]) -> ExpandResult + 'static>;
+
+/// expansions by the address of the call they expanded,
+/// with a weak reference to the call which keeps the address from being reused
+type CallSites = HashMap<*const Value, (WeakList, Expansion)>;
+
+/// a procedure which transforms the form it is called with into another form,
+/// which is evaluated in its place
+pub struct Macro {
+    env: Env,
+    expand: MacroFn,
+    repr: Rc<dyn Display>,
+    /// the expansion of each call evaluated so far
+    cache: RefCell<CallSites>,
+}
+
+impl Display for Macro {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "{}", self.repr)
+    }
+}
+
+impl Macro {
+    pub fn value(
+        env: Env,
+        expand: impl Fn(&Env, &[Value]) -> ExpandResult + 'static,
+        repr: Rc<dyn Display>,
+    ) -> Value {
+        Value::Macro(Rc::new(Macro {
+            env,
+            expand: Box::new(expand),
+            repr,
+            cache: RefCell::new(HashMap::new()),
+        }))
+    }
+
+    pub fn expand(&self, args: &[Value]) -> ExpandResult {
+        (self.expand)(&self.env, args)
+    }
+
+    /// evaluates the expansion of `call`, `(macro args...)`, where it was called.
+    /// each call is only expanded the first time it is evaluated
+    pub fn call(&self, caller: Env, call: &List) -> TailResult {
+        let site = call.as_ptr();
+        let cached = self
+            .cache
+            .borrow()
+            .get(&site)
+            .map(|(_, expansion)| expansion.clone());
+        let expansion = match cached {
+            Some(expansion) => expansion,
+            None => {
+                // the call may have been resolved, see [resolve]
+                let args: Vec<_> = call[1..].iter().map(resolve::unresolve).collect();
+                let expansion = self.expand(&args)?;
+                let mut cache = self.cache.borrow_mut();
+                // calls which have been dropped cannot be evaluated again
+                if cache.len() == cache.capacity() {
+                    cache.retain(|_, (call, _)| call.strong_count() > 0);
+                }
+                cache.insert(site, (call.downgrade(), expansion.clone()));
+                expansion
+            }
+        };
+        Ok(Tail::Eval(
+            renamed(&self.env, caller, &expansion.renames),
+            expansion.expr,
+        ))
+    }
+}
+
+/// binds the names an expansion introduced, if there are any
+fn renamed(definition: &Env, env: Env, renames: &Rc<[(Symbol, Symbol)]>) -> Env {
+    match renames.is_empty() {
+        true => env,
+        false => env.bind(env::Renamed(renames.clone(), definition.clone())),
+    }
+}
+
+/// an expansion which introduced names, as a call which evaluates it with those
+/// names bound, so that it can stand in for the macro call within a larger form
+fn bind_renames(mac: &Macro, expansion: Expansion) -> Value {
+    if expansion.renames.is_empty() {
+        return expansion.expr;
+    }
+    let renames = expansion.renames.clone();
+    let binder = Value::Procedure(
+        mac.env.clone(),
+        Rc::new(move |env, caller, args| match args {
+            [expr] => Ok(Tail::Eval(renamed(env, caller, &renames), expr.clone())),
+            _ => Err(arity("renamed", "1 argument", args)),
+        }),
+        Rc::new(DisplayRenames(expansion.renames)),
+    );
+    Value::List(List::from([binder, expansion.expr]), None)
+}
+
+struct DisplayRenames(Rc<[(Symbol, Symbol)]>);
+impl Display for DisplayRenames {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "renamed")?;
+        for (i, (renamed, original)) in self.0.iter().enumerate() {
+            let separator = if i == 0 { " " } else { ", " };
+            write!(f, "{separator}{renamed} -> {original}")?;
+        }
+        Ok(())
+    }
+}
+
+/// expands every macro call within `expr` whose macro is bound in `env`,
+/// and is not shadowed by a binding around the call
+pub fn expand(env: &Env, expr: &Value) -> Result<Value, EvalError> {
+    Scope::new(env, false).expand(expr)
+}
+
+/// expands `expr` as [expand] does, so that it can be printed and read back. names the
+/// expansions introduced which cannot be written as the names they were written as are
+/// bound to what those names are bound to by definitions of their own, which are given
+/// before the expanded form
+pub fn expand_to_print(env: &Env, expr: &Value) -> Result<Vec<Value>, EvalError> {
+    let scope = Scope::new(env, true);
+    let expr = scope.expand(expr)?;
+    let mut forms: Vec<_> = scope
+        .unresolved
+        .borrow()
+        .iter()
+        .filter(|rename| rename.value.is_some())
+        .map(|rename| {
+            let define = [symbol::DEFINE, rename.renamed, rename.original];
+            Value::List(define.map(|name| Value::Symbol(name, None)).into(), None)
+        })
+        .collect();
+    forms.push(expr);
+    Ok(forms)
+}
+
+/// expands `expr` once if it is a macro call, otherwise gives it back as it is
+pub fn expand_once(env: &Env, expr: &Value) -> Result<Value, EvalError> {
+    match Scope::new(env, false).macro_call(expr) {
+        Some((mac, args)) => Ok(bind_renames(&mac, mac.expand(args)?)),
+        None => Ok(expr.clone()),
+    }
+}
+
+/// a name a macro expansion introduced, see [Expansion::renames]
+#[derive(Clone)]
+struct Rename {
+    renamed: Symbol,
+    original: Symbol,
+    /// what the original name is bound to where the macro was defined
+    value: Option<Value>,
+}
+
+/// where an expression is, while expanding
+#[derive(Clone)]
+struct Scope {
+    env: Env,
+    /// names bound by the forms around the expression, which shadow what they are bound to
+    /// in `env`. their values are not known until the expression is evaluated
+    bound: Vec<Symbol>,
+    /// the names introduced by the expansions the expression is within
+    renames: Vec<Rename>,
+    /// the introduced names which are written as they were renamed, as the names they were
+    /// written as are bound to something else where they are used. an expansion binds them
+    /// with [bind_renames], unless it is expanded to be printed, see [expand_to_print]
+    unresolved: Rc<RefCell<Vec<Rename>>>,
+    printing: bool,
+}
+
+impl Scope {
+    fn new(env: &Env, printing: bool) -> Self {
+        Scope {
+            env: env.clone(),
+            bound: Vec::new(),
+            renames: Vec::new(),
+            unresolved: Rc::new(RefCell::new(Vec::new())),
+            printing,
+        }
+    }
+
+    fn with(&self, names: impl IntoIterator<Item = Symbol>) -> Self {
+        let mut scope = self.clone();
+        scope.bound.extend(names);
+        scope
+    }
+
+    /// the introduced name `name` is, unless a binding around the expression shadows it
+    fn rename(&self, name: Symbol) -> Option<&Rename> {
+        match self.bound.contains(&name) {
+            true => None,
+            false => self.renames.iter().find(|rename| rename.renamed == name),
+        }
+    }
+
+    /// the name a form starts with, unless a binding around it shadows the name.
+    /// a name an expansion introduced is the name it was written as
+    fn form(&self, head: &Value) -> Option<Symbol> {
+        match head {
+            Value::Symbol(name, _) if !self.bound.contains(name) => {
+                Some(self.rename(*name).map_or(*name, |rename| rename.original))
+            }
+            _ => None,
+        }
+    }
+
+    /// the symbol `name` as it is written in the expanded form. a name an expansion
+    /// introduced is written as the name it was written as, where that name is bound
+    /// to the same thing as where the macro was defined
+    fn symbol(&self, name: Symbol, span: &Span) -> Value {
+        let Some(rename) = self.rename(name) else {
+            return Value::Symbol(name, span.clone());
+        };
+        if !self.bound.contains(&rename.original)
+            && self.env.lookup(&rename.original) == rename.value
+        {
+            return Value::Symbol(rename.original, span.clone());
+        }
+        let mut unresolved = self.unresolved.borrow_mut();
+        if !unresolved
+            .iter()
+            .any(|unresolved| unresolved.renamed == name)
+        {
+            unresolved.push(rename.clone());
+        }
+        Value::Symbol(name, span.clone())
+    }
+
+    /// the macro and arguments of `expr`, if it is a call of a macro
+    fn macro_call<'a>(&self, expr: &'a Value) -> Option<(Rc<Macro>, &'a [Value])> {
+        let Value::List(items, _) = expr else {
+            return None;
+        };
+        match items.split_first()? {
+            (Value::Symbol(name, _), args) if !self.bound.contains(name) => {
+                match self.env.lookup(name) {
+                    Some(Value::Macro(mac)) => Some((mac, args)),
+                    _ => None,
+                }
+            }
+            _ => None,
+        }
+    }
+
+    fn expand(&self, expr: &Value) -> Result<Value, EvalError> {
+        if let Some((mac, args)) = self.macro_call(expr) {
+            let expansion = mac.expand(args).map_err(|err| err.within(expr))?;
+            // the introduced names are bound for the rest of the expansion,
+            // so that macros they refer to are found
+            let mut scope = Scope {
+                env: renamed(&mac.env, self.env.clone(), &expansion.renames),
+                ..self.clone()
+            };
+            scope
+                .renames
+                .extend(expansion.renames.iter().map(|&(renamed, original)| Rename {
+                    renamed,
+                    original,
+                    value: mac.env.lookup(&original),
+                }));
+            let expr = scope.expand(&expansion.expr)?;
+            if self.printing {
+                return Ok(expr);
+            }
+            let unresolved = self.unresolved.borrow();
+            let renames = expansion
+                .renames
+                .iter()
+                .filter(|(renamed, _)| unresolved.iter().any(|rename| rename.renamed == *renamed))
+                .copied()
+                .collect();
+            return Ok(bind_renames(&mac, Expansion { expr, renames }));
+        }
+        let (items, span) = match expr {
+            Value::Symbol(name, span) => return Ok(self.symbol(*name, span)),
+            Value::List(items, span) => (items, span),
+            _ => return Ok(expr.clone()),
+        };
+        let Some(first) = items.first() else {
+            return Ok(expr.clone());
+        };
+        let form = self.form(first);
+        let head = &match first {
+            Value::Symbol(name, span) => self.symbol(*name, span),
+            _ => first.clone(),
+        };
+        let items = match (form, &items[1..]) {
+            (Some(symbol::QUOTE | symbol::QUASIQUOTE | symbol::SYNTAX_RULES), rest) => {
+                core::iter::once(head.clone())
+                    .chain(rest.iter().cloned())
+                    .collect()
+            }
+            (Some(symbol::DEFINE | symbol::DEFINE_MACRO | symbol::DEFINE_SYNTAX), _) => {
+                self.definition(items)?
+            }
+            (Some(symbol::LAMBDA), [params, body]) => {
+                let body = self.with(param_names(params)).expand(body)?;
+                vec![head.clone(), params.clone(), body]
+            }
+            (Some(symbol::MACRO), [Value::Symbol(binding, _), body]) => {
+                let body = self.with([*binding]).expand(body)?;
+                vec![head.clone(), items[1].clone(), body]
+            }
+            (
+                Some(symbol::LET | symbol::LET_SYNTAX),
+                [Value::Symbol(name, _), Value::List(bindings, span), body],
+            ) => {
+                let scope = self.with(binding_names(bindings)).with([*name]);
+                vec![
+                    head.clone(),
+                    items[1].clone(),
+                    Value::List(self.bindings(bindings)?.into(), span.clone()),
+                    scope.expand(body)?,
+                ]
+            }
+            (Some(symbol::LET | symbol::LET_SYNTAX), [Value::List(bindings, span), body]) => {
+                let scope = self.with(binding_names(bindings));
+                vec![
+                    head.clone(),
+                    Value::List(self.bindings(bindings)?.into(), span.clone()),
+                    scope.expand(body)?,
+                ]
+            }
+            (
+                Some(symbol::LETREC | symbol::LETREC_STAR | symbol::LETREC_SYNTAX),
+                [Value::List(bindings, span), body],
+            ) => {
+                let scope = self.with(binding_names(bindings));
+                vec![
+                    head.clone(),
+                    Value::List(scope.bindings(bindings)?.into(), span.clone()),
+                    scope.expand(body)?,
+                ]
+            }
+            (Some(symbol::BEGIN), [defines @ .., _]) => {
+                let scope = self.with(defines.iter().filter_map(defined_name));
+                let mut expanded = vec![head.clone()];
+                for define in defines {
+                    expanded.push(match define {
+                        Value::List(define, span) => {
+                            Value::List(scope.definition(define)?.into(), span.clone())
+                        }
+                        _ => define.clone(),
+                    });
+                }
+                expanded.push(scope.expand(&items[items.len() - 1])?);
+                expanded
+            }
+            (Some(symbol::SET), [name, value]) => {
+                let target = self.expand(name)?;
+                let introduced =
+                    matches!(name, Value::Symbol(name, _) if self.rename(*name).is_some());
+                // a definition of its own would be set in place of what the name refers to
+                if self.printing && introduced && target == *name {
+                    return Err(EvalError::BadForm(
+                        "cannot print an expansion which sets a name it introduced, \
+                         as the name is bound to something else where the macro is used"
+                            .to_string(),
+                        expr.clone(),
+                    ));
+                }
+                vec![head.clone(), target, self.expand(value)?]
+            }
+            (Some(symbol::EXCEPTION_GUARD), [Value::List(spec, span), body])
+                if matches!(spec.first(), Some(Value::Symbol(_, _))) =>
+            {
+                let Value::Symbol(name, _) = spec[0] else {
+                    unreachable!("the name was matched")
+                };
+                let scope = self.with([name]);
+                let mut expanded = vec![spec[0].clone()];
+                for clause in &spec[1..] {
+                    expanded.push(match clause {
+                        Value::List(parts, span) => Value::List(
+                            parts
+                                .iter()
+                                .map(|part| scope.expand(part))
+                                .collect::<Result<_, _>>()?,
+                            span.clone(),
+                        ),
+                        _ => clause.clone(),
+                    });
+                }
+                vec![
+                    head.clone(),
+                    Value::List(expanded.into(), span.clone()),
+                    self.expand(body)?,
+                ]
+            }
+            (Some(symbol::HANDLE), [body, clauses @ ..]) => {
+                let mut expanded = vec![head.clone(), self.expand(body)?];
+                for clause in clauses {
+                    expanded.push(match clause {
+                        Value::List(parts, span) => match parts.as_ref() {
+                            [name, bindings, body] => {
+                                let body = self.with(param_names(bindings)).expand(body)?;
+                                Value::List(
+                                    List::from([name.clone(), bindings.clone(), body]),
+                                    span.clone(),
+                                )
+                            }
+                            _ => clause.clone(),
+                        },
+                        _ => clause.clone(),
+                    });
+                }
+                expanded
+            }
+            (Some(symbol::PMATCH), [value, branches @ .., fail]) => {
+                let mut expanded = vec![head.clone(), self.expand(value)?];
+                for branch in branches {
+                    expanded.push(match branch {
+                        Value::List(parts, span) => match parts.split_first() {
+                            Some((structure, rest)) => {
+                                let mut names = Vec::new();
+                                symbols(structure, &mut names);
+                                let scope = self.with(names);
+                                let mut parts = vec![structure.clone()];
+                                for part in rest {
+                                    parts.push(scope.expand(part)?);
+                                }
+                                Value::List(parts.into(), span.clone())
+                            }
+                            None => branch.clone(),
+                        },
+                        _ => branch.clone(),
+                    });
+                }
+                expanded.push(self.expand(fail)?);
+                expanded
+            }
+            _ => items
+                .iter()
+                .map(|item| self.expand(item))
+                .collect::<Result<_, _>>()?,
+        };
+        Ok(Value::List(items.into(), span.clone()))
+    }
+
+    /// `((name value)...)`, with each value expanded
+    fn bindings(&self, bindings: &[Value]) -> Result<Vec<Value>, EvalError> {
+        bindings
+            .iter()
+            .map(|binding| match binding {
+                Value::List(parts, span) => match parts.as_ref() {
+                    [name, value] => Ok(Value::List(
+                        List::from([name.clone(), self.expand(value)?]),
+                        span.clone(),
+                    )),
+                    _ => Ok(binding.clone()),
+                },
+                _ => Ok(binding.clone()),
+            })
+            .collect()
+    }
+
+    /// a define form, with its value or body expanded
+    fn definition(&self, define: &[Value]) -> Result<Vec<Value>, EvalError> {
+        match define {
+            [keyword, name @ Value::Symbol(_, _), value] => Ok(vec![
+                self.expand(keyword)?,
+                name.clone(),
+                self.expand(value)?,
+            ]),
+            [keyword, name_args @ Value::List(parts, _), body] => {
+                let params = parts.get(1..).unwrap_or_default();
+                let scope = self.with(params.iter().flat_map(param_names));
+                Ok(vec![
+                    self.expand(keyword)?,
+                    name_args.clone(),
+                    scope.expand(body)?,
+                ])
+            }
+            _ => Ok(define.to_vec()),
+        }
+    }
+}
+
+/// the names bound by the parameters of a lambda, see [super::params::Params]
+fn param_names(params: &Value) -> Vec<Symbol> {
+    match params {
+        Value::Symbol(symbol::DOT | symbol::OPTIONAL | symbol::KEY | symbol::REST, _) => vec![],
+        Value::Symbol(name, _) => vec![*name],
+        Value::List(params, _) => params
+            .iter()
+            .filter_map(|param| match param {
+                Value::Symbol(symbol::DOT | symbol::OPTIONAL | symbol::KEY | symbol::REST, _) => {
+                    None
+                }
+                Value::Symbol(name, _) => Some(*name),
+                Value::List(parts, _) => match parts.first() {
+                    Some(Value::Symbol(name, _)) => Some(*name),
+                    _ => None,
+                },
+                _ => None,
+            })
+            .collect(),
+        _ => vec![],
+    }
+}
+
+fn binding_names(bindings: &[Value]) -> Vec<Symbol> {
+    bindings
+        .iter()
+        .filter_map(|binding| match binding {
+            Value::List(parts, _) => match parts.first() {
+                Some(Value::Symbol(name, _)) => Some(*name),
+                _ => None,
+            },
+            _ => None,
+        })
+        .collect()
+}
+
+fn defined_name(define: &Value) -> Option<Symbol> {
+    let Value::List(define, _) = define else {
+        return None;
+    };
+    match define.get(1)? {
+        Value::Symbol(name, _) => Some(*name),
+        Value::List(name_args, _) => match name_args.first()? {
+            Value::Symbol(name, _) => Some(*name),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// every symbol within a pmatch? structure, some of which it binds
+fn symbols(structure: &Value, names: &mut Vec<Symbol>) {
+    match structure {
+        Value::Symbol(name, _) => names.push(*name),
+        Value::List(parts, _) => parts.iter().for_each(|part| symbols(part, names)),
+        _ => (),
+    }
+}
+
+fn macroexpander(
+    env: Env,
+    name: &'static str,
+    expand: fn(&Env, &Value) -> Result<Value, EvalError>,
+) -> Value {
+    Value::Procedure(
+        env,
+        Rc::new(move |_, env, exprs| match exprs {
+            [expr] => {
+                let caller = env.clone();
+                evaluate(&env, expr, move |form| {
+                    Ok(Tail::Value(expand(&caller, &form)?))
+                })
+            }
+            _ => Err(arity(name, "1 argument", exprs)),
+        }),
+        Rc::new("form"),
+    )
+}
+
+pub fn bindings(env: Env) -> Vec<env::Value<&'static str, Value>> {
+    vec![
+        env::Value(
+            "macroexpand",
+            macroexpander(env.clone(), "macroexpand", expand),
+        ),
+        env::Value(
+            "macroexpand-1",
+            macroexpander(env, "macroexpand-1", expand_once),
+        ),
+    ]
+}
+
+#[test]
+fn macro_expansion_test() {
+    use super::test::eval;
+    assert_eq!(
+        Some("(- 2 1)".to_string()),
+        eval("(begin (define-macro (swap args) (pmatch? args ((f a b) (list f b a)) #f))
+                     (macroexpand-1 '(swap - 1 2)))")
+    );
+    assert_eq!(
+        Some("(+ 1 (- 3 2))".to_string()),
+        eval("(begin (define-macro (swap args) (pmatch? args ((f a b) (list f b a)) #f))
+                     (macroexpand '(swap + (swap - 2 3) 1)))")
+    );
+    // locally bound names are not expanded as macros
+    assert_eq!(
+        Some("(1 2 3)".to_string()),
+        eval("(begin (define-macro (swap args) (pmatch? args ((f a b) (list f b a)) #f))
+                     (let ((swap list)) (swap 1 2 3)))")
+    );
+    // each call site is only expanded once, whether it is expanded before it is evaluated,
+    // or while it is evaluated, as local macros are
+    for program in [
+        "(define expansions (box 0))
+         (define-macro (counted args) (begin (define _ (set-box! expansions (+ (unbox expansions) 1))) (car args)))
+         (let loop ((i 0)) (if? (= i 10) (unbox expansions) (loop (counted (+ i 1)))))",
+        "(define expansions (box 0))
+         (define-macro (counted args) (begin (define _ (set-box! expansions (+ (unbox expansions) 1))) (car args)))
+         (let ((local counted))
+           (let loop ((i 0)) (if? (= i 10) (unbox expansions) (loop (local (+ i 1))))))",
+    ] {
+        assert_eq!(Some("1".to_string()), eval(program), "{program}");
+    }
+}
+
+#[test]
+fn expand_to_print_test() {
+    use super::{test::read, Session};
+    let prelude = "(define-syntax my-or
+                     (syntax-rules ()
+                       ((_) #f)
+                       ((_ e) e)
+                       ((_ e rest ...) (let ((t e)) (if? t t (my-or rest ...))))))
+                   (define t 5)
+                   (define count 0)
+                   (define-syntax bump! (syntax-rules () ((_ n) (set! count (+ count n)))))";
+    // the expanded program, printed and read back, evaluates as the program does
+    for program in [
+        "(list (my-or #f t) (my-or #f #f 3) (let ((t 1)) (my-or #f t)))",
+        "(define (f x) (my-or x t)) (list (f #f) (f 2))",
+        // `if?` is bound where the macro is used, so the `if?` the macro introduced
+        // is defined as itself under the name it was renamed to
+        "(define (h if?) (my-or #f if?)) (list (h 3) (let ((if? 7)) (my-or #f if?)))",
+        "(bump! 2) (bump! 3) count",
+    ] {
+        let program = format!("{prelude} {program}");
+        let mut printed = String::new();
+        Session::default()
+            .expand(read(&program), |form| {
+                printed.push_str(&format!("{form}\n"))
+            })
+            .unwrap();
+        assert!(!printed.contains("procedure"), "{printed}");
+        let run = |source: &str| Session::default().run(read(source), |_| ()).unwrap();
+        assert_eq!(run(&program), run(&printed), "{printed}");
+    }
+    let shadowed = format!("{prelude} (define (k count) (bump! 1))");
+    assert!(matches!(
+        Session::default().expand(read(&shadowed), |_| ()),
+        Err(EvalError::BadForm(_, _))
+    ));
+}
@@ -1,370 +1,790 @@
 use std::rc::Rc;
 
-use crate::interpreter::{eval, Value};
+use crate::interpreter::{eval, List, Value};
 
-use super::{env, DisplayList, Env, EvalResult};
+use super::{
+    cps::{atom, evaluate, evaluate_all, frame, then},
+    env,
+    env::Lookup,
+    expand::{Expansion, Macro},
+    limits,
+    params::{self, Params},
+    run,
+    symbol::{self, Symbol},
+    syntax::Rules,
+    vm, Env, EvalError, EvalResult, Tail, TailResult,
+};
 
-pub fn lambda<'env>(env: Env<'env>) -> Value<'env> {
+pub fn no_match(name: &str, exprs: &[Value]) -> EvalError {
+    EvalError::BadForm(
+        format!("did not match any forms of macro procedure \"{name}\""),
+        Value::List(List::from(exprs), None),
+    )
+}
+
+/// the error for a [function] given the wrong number of arguments
+pub fn arity(name: &str, expected: &str, args: &[Value]) -> EvalError {
+    EvalError::Arity(
+        format!("\"{name}\" expected {expected}, got {}", args.len()),
+        Value::List(List::from(args), None),
+    )
+}
+
+/// a procedure which is applied to the values of its arguments,
+/// evaluated in the caller's environment
+pub fn function(
+    env: Env,
+    repr: &'static str,
+    f: impl Fn(&[Value]) -> EvalResult + 'static,
+) -> Value {
+    let f = Rc::new(f);
     Value::Procedure(
         env,
-        Rc::new(|env, exprs| match exprs {
-            [Value::List(bindings), body] => {
-                Ok(lambda_internal(env.clone(), bindings, body.clone()))
-            }
-            _ => panic!("{exprs:#?} did not match any forms of macro procedure \"lambda\""),
+        Rc::new(move |_, env, exprs| {
+            let f = f.clone();
+            evaluate_all(&env, exprs, move |args| Ok(Tail::Value(f(&args)?)))
+        }),
+        Rc::new(repr),
+    )
+}
+
+/// a [function] which continues with a tail, such as a call of another procedure
+pub fn tail_function(
+    env: Env,
+    repr: &'static str,
+    f: impl Fn(&[Value]) -> TailResult + 'static,
+) -> Value {
+    let f = Rc::new(f);
+    Value::Procedure(
+        env,
+        Rc::new(move |_, env, exprs| {
+            let f = f.clone();
+            evaluate_all(&env, exprs, move |args| f(&args))
+        }),
+        Rc::new(repr),
+    )
+}
+
+/// applies a procedure to arguments which have already been evaluated
+pub fn apply(procedure: &Value, args: &[Value]) -> TailResult {
+    match procedure {
+        Value::Procedure(env, f, _) => {
+            // symbols and lists would be evaluated again by the procedure, so they are quoted
+            let quote = quote(env.clone());
+            let args: Vec<_> = args
+                .iter()
+                .map(|arg| match arg {
+                    Value::Symbol(_, _) | Value::List(_, _) => {
+                        Value::List(List::from([quote.clone(), arg.clone()]), None)
+                    }
+                    _ => arg.clone(),
+                })
+                .collect();
+            f(env, env.clone(), &args)
+        }
+        Value::Closure(closure) => vm::call(closure, args.to_vec()),
+        _ => Err(EvalError::Type(
+            "cannot call non-procedure".to_string(),
+            procedure.clone(),
+        )),
+    }
+}
+
+pub fn lambda(env: Env) -> Value {
+    Value::Procedure(
+        env,
+        Rc::new(|_, env, exprs| match exprs {
+            [Value::List(bindings, _), body] => Ok(Tail::Value(lambda_internal(
+                env.clone(),
+                bindings,
+                body.clone(),
+            )?)),
+            // `(lambda args body)` binds every argument as a list
+            [rest @ Value::Symbol(_, _), body] => Ok(Tail::Value(lambda_internal(
+                env.clone(),
+                &[Value::Symbol(symbol::REST, None), rest.clone()],
+                body.clone(),
+            )?)),
+            _ => Err(no_match("lambda", exprs)),
         }),
         Rc::new("(bindings...) body"),
     )
 }
 
-fn lambda_internal<'env>(
-    env: Env<'env>,
-    bindings: &[Value<'env>],
-    body: Value<'env>,
-) -> Value<'env> {
-    let bindings: Rc<[&'env str]> = bindings
-        .iter()
-        .map(|v| match v {
-            Value::Symbol(binding) => *binding,
-            _ => panic!("invalid binding: {v}"),
+fn lambda_internal(env: Env, bindings: &[Value], body: Value) -> EvalResult {
+    let params = Rc::new(Params::parse(bindings)?);
+    let repr = params.clone();
+    let procedure = Rc::new(move |env: &Env, caller: Env, args: &[Value]| {
+        let body = body.clone();
+        params.bind(env, &caller, args, move |env| {
+            Ok(Tail::Eval(env, body.clone()))
         })
-        .collect::<Vec<_>>()
-        .into();
-    let proc_bindings = bindings.clone();
-    let procedure = Rc::new(move |env: Env<'env>, args: &[Value<'env>]| {
-        let bindings = proc_bindings.clone();
-        if args.len() != bindings.len() {
-            panic!("wrong number of args:\nbindings: {bindings:#?}\nargs: {args:#?}");
-        }
-        let mut v = Vec::with_capacity(args.len());
-        bindings
-            .iter()
-            .zip(args.iter())
-            .try_for_each(|(binding, arg)| {
-                v.push(env::Value(*binding, eval(env.clone(), arg.clone())?));
-                Ok::<(), ()>(())
-            })?;
-        eval(env.bind(env::Values::new(Rc::from(v))), body.clone())
     });
 
-    Value::Procedure(env, procedure, Rc::new(DisplayList(bindings.clone())))
+    Ok(Value::Procedure(env, procedure, repr))
 }
 
-pub fn lambda_macro<'env>(env: Env<'env>) -> Value<'env> {
+pub fn lambda_macro(env: Env) -> Value {
     Value::Procedure(
         env,
-        Rc::new(|env, exprs| match exprs {
-            [Value::Symbol(binding), body] => {
-                Ok(lambda_macro_internal(env.clone(), binding, body.clone()))
-            }
-            _ => panic!("{exprs:#?} did not match any forms of macro procedure \"macro\""),
+        Rc::new(|_, env, exprs| match exprs {
+            [Value::Symbol(binding, _), body] => Ok(Tail::Value(lambda_macro_internal(
+                env.clone(),
+                *binding,
+                body.clone(),
+            ))),
+            _ => Err(no_match("macro", exprs)),
         }),
         Rc::new("binding body"),
     )
 }
 
-fn lambda_macro_internal<'env>(
-    env: Env<'env>,
-    binding: &'env str,
-    body: Value<'env>,
-) -> Value<'env> {
-    let procedure = Rc::new(move |env: Env<'env>, args: &[Value<'env>]| {
-        eval(
-            env.bind(env::Value(binding, Value::List(Rc::from(args)))),
+fn lambda_macro_internal(env: Env, binding: Symbol, body: Value) -> Value {
+    let expand = move |env: &Env, args: &[Value]| {
+        let expansion = eval(
+            env.bind(env::Values::new([env::Value(
+                binding,
+                Value::List(List::from(args), None),
+            )])),
             body.clone(),
-        )
-    });
+        )?;
+        Ok(Expansion::new(expansion))
+    };
 
-    Value::Procedure(env, procedure, Rc::new(binding))
+    Macro::value(env, expand, Rc::new(binding))
 }
 
-pub fn bind_let<'env>(env: Env<'env>) -> Value<'env> {
+pub fn syntax_rules(env: Env) -> Value {
     Value::Procedure(
         env,
-        Rc::new(|env, exprs| match exprs {
-            [Value::List(bindings), body] => {
-                let mut v = Vec::with_capacity(bindings.len());
-                bindings.iter().try_for_each(|binding| match binding {
-                    Value::List(binding) => match &binding[..] {
-                        [Value::Symbol(name), expr] => {
-                            v.push(env::Value(*name, eval(env.clone(), expr.clone())?));
-                            Ok(())
-                        }
-                        _ => panic!("{binding:#?} did not match the (name value) form"),
-                    },
-                    _ => panic!("{binding:#?} did not match the ((name value)...) form"),
-                })?;
-                eval(
-                    env.bind(env::Values::new(v.into_boxed_slice())),
+        Rc::new(|_, env, exprs| Ok(Tail::Value(syntax_rules_internal(env.clone(), exprs)?))),
+        Rc::new("[ellipsis] (literal...) ((keyword pattern...) template)..."),
+    )
+}
+
+/// a macro which expands the template of the first pattern matching its arguments,
+/// renaming the names the template introduces so that they refer to what they
+/// were bound to where the macro was defined
+fn syntax_rules_internal(env: Env, exprs: &[Value]) -> EvalResult {
+    let rules = Rc::new(Rules::parse(exprs)?);
+    let repr = rules.clone();
+    Ok(Macro::value(env, move |_, args| rules.expand(args), repr))
+}
+
+pub fn bind_let(env: Env) -> Value {
+    Value::Procedure(
+        env,
+        Rc::new(|_, env, exprs| match exprs {
+            [Value::List(bindings, _), body] => {
+                let (names, exprs): (Vec<_>, Vec<_>) = let_bindings(bindings)?
+                    .into_iter()
+                    .map(|(name, expr)| (name, expr.clone()))
+                    .unzip();
+                let (caller, body) = (env.clone(), body.clone());
+                evaluate_all(&env, &exprs, move |values| {
+                    let bound = names
+                        .iter()
+                        .zip(values)
+                        .map(|(name, value)| env::Value(*name, value));
+                    Ok(Tail::Eval(
+                        caller.bind(env::Values::new(bound)),
+                        body.clone(),
+                    ))
+                })
+            }
+            // named let, binding `name` within body to a procedure
+            // which takes the bindings as arguments and evaluates body again
+            [Value::Symbol(name, _), Value::List(bindings, _), body] => {
+                let (params, exprs): (Vec<_>, Vec<_>) = let_bindings(bindings)?
+                    .into_iter()
+                    .map(|(name, expr)| (Value::Symbol(name, None), expr.clone()))
+                    .unzip();
+                let (name, caller, params, body) = (
+                    *name,
+                    env.clone(),
+                    Rc::<[Value]>::from(params),
                     body.clone(),
-                )
+                );
+                evaluate_all(&env, &exprs, move |args| {
+                    let (params, body) = (params.clone(), body.clone());
+                    let procedure: Definition = Rc::new(move |env| {
+                        Ok(Tail::Value(lambda_internal(env, &params, body.clone())?))
+                    });
+                    bind_recursive(caller.clone(), vec![(name, procedure)], true, move |env| {
+                        apply(&Value::from_env(env, name)?, &args)
+                    })
+                })
+            }
+            _ => Err(no_match("let", exprs)),
+        }),
+        Rc::new("[name] ((binding value)...) body"),
+    )
+}
+
+/// (letrec ((binding value)...) body), or (letrec* ...) when `sequential`.
+/// each value can refer to every binding, see [bind_recursive]
+pub fn letrec(env: Env, sequential: bool) -> Value {
+    let name = if sequential { "letrec*" } else { "letrec" };
+    Value::Procedure(
+        env,
+        Rc::new(move |_, env, exprs| match exprs {
+            [Value::List(bindings, _), body] => {
+                let definitions = let_bindings(bindings)?
+                    .into_iter()
+                    .map(|(name, expr)| {
+                        let expr = expr.clone();
+                        let value: Definition =
+                            Rc::new(move |env| Ok(Tail::Eval(env, expr.clone())));
+                        (name, value)
+                    })
+                    .collect();
+                let body = body.clone();
+                bind_recursive(env, definitions, sequential, move |env| {
+                    Ok(Tail::Eval(env, body.clone()))
+                })
             }
-            _ => panic!("{exprs:#?} did not match any forms of macro-procedure \"let\""),
+            _ => Err(no_match(name, exprs)),
         }),
         Rc::new("((binding value)...) body"),
     )
 }
 
-pub fn quote<'env>(env: Env<'env>) -> Value<'env> {
-    Value::Procedure(env, Rc::new(quote_internal), Rc::new("symbol"))
+/// matches the ((name value)...) bindings of the let forms
+fn let_bindings(bindings: &[Value]) -> Result<Vec<(Symbol, &Value)>, EvalError> {
+    let mut bound = Vec::with_capacity(bindings.len());
+    bindings
+        .iter()
+        .map(|binding| match binding {
+            Value::List(parts, _) => match &parts[..] {
+                [symbol @ Value::Symbol(name, _), expr] => {
+                    params::bind_once(&mut bound, *name, symbol)?;
+                    Ok((*name, expr))
+                }
+                _ => Err(EvalError::BadForm(
+                    "did not match the (name value) form".to_string(),
+                    binding.clone(),
+                )),
+            },
+            _ => Err(EvalError::BadForm(
+                "did not match the ((name value)...) form".to_string(),
+                binding.clone(),
+            )),
+        })
+        .collect()
+}
+
+pub fn quote(env: Env) -> Value {
+    Value::Procedure(
+        env,
+        Rc::new(|_, env, exprs| Ok(Tail::Value(quote_internal(env, exprs)?))),
+        Rc::new("symbol"),
+    )
 }
 
-fn quote_internal<'env>(_: Env<'env>, exprs: &[Value<'env>]) -> EvalResult<'env> {
+fn quote_internal(_: Env, exprs: &[Value]) -> EvalResult {
     match exprs {
         [expr] => Ok(expr.clone()),
-        _ => Ok(Value::List(Rc::from(exprs))),
+        _ => Ok(Value::List(List::from(exprs), None)),
     }
 }
 
-pub fn quasiquote<'env>(env: Env<'env>) -> Value<'env> {
-    Value::Procedure(env, Rc::new(quasiquote_internal), Rc::new("symbol"))
+pub fn quasiquote(env: Env) -> Value {
+    Value::Procedure(
+        env,
+        Rc::new(|definition, env, exprs| match exprs {
+            [template] => match quasiquote_internal(definition, template, 0)? {
+                Quasi::Constant(value) => Ok(Tail::Value(value)),
+                Quasi::Expr(expr) => Ok(Tail::Eval(env, expr)),
+            },
+            _ => Err(no_match("quasiquote", exprs)),
+        }),
+        Rc::new("template"),
+    )
+}
+
+/// a part of a quasiquote template
+enum Quasi {
+    /// a part with nothing to evaluate, which is copied as it is
+    Constant(Value),
+    /// the expression which evaluates to the part
+    Expr(Value),
 }
 
-fn quasiquote_internal<'env>(env: Env<'env>, exprs: &[Value<'env>]) -> EvalResult<'env> {
-    match exprs {
-        [Value::List(exprs)] => match exprs.as_ref() {
-            [Value::Symbol("unquote"), exprs] => unquote(env, &[exprs.clone()]),
-            _ => {
-                let mut v = Vec::with_capacity(exprs.len());
-                exprs.iter().try_for_each(|expr| {
-                    v.push(quasiquote_internal(env.clone(), &[expr.clone()])?);
-                    Ok::<(), ()>(())
-                })?;
-                match &v[..] {
-                    [value] => Ok(value.clone()),
-                    _ => Ok(Value::List(Rc::from(v))),
-                }
+impl Quasi {
+    fn expr(self, env: &Env) -> Value {
+        match self {
+            Quasi::Constant(value @ (Value::Symbol(_, _) | Value::List(_, _))) => {
+                Value::List(List::from([quote(env.clone()), value]), None)
             }
-        },
-        [symbol] => quote_internal(env, &[symbol.clone()]),
-        _ => panic!("{exprs:#?} did not match any forms of macro procedure \"quasiquote\""),
+            Quasi::Constant(value) | Quasi::Expr(value) => value,
+        }
     }
 }
 
-fn unquote<'env>(env: Env<'env>, exprs: &[Value<'env>]) -> EvalResult<'env> {
-    match exprs.as_ref() {
-        [expr] => eval(env, expr.clone()),
-        _ => panic!("{exprs:#?} did not match any forms of macro procedure \"unquote\""),
+/// the expression which copies `template`, evaluating the unquoted expressions at `depth` 0.
+/// each nested quasiquote is one level deeper, and each unquote within it one level shallower.
+/// a list is copied by a call, so that its unquoted expressions are evaluated as its arguments
+fn quasiquote_internal(env: &Env, template: &Value, depth: usize) -> Result<Quasi, EvalError> {
+    let Value::List(exprs, _) = template else {
+        return Ok(Quasi::Constant(template.clone()));
+    };
+    // each part of the list, and whether it is spliced into the list
+    let parts = match exprs.as_ref() {
+        [Value::Symbol(symbol::UNQUOTE, _), expr] if depth == 0 => {
+            return Ok(Quasi::Expr(expr.clone()))
+        }
+        [Value::Symbol(symbol::UNQUOTE_SPLICING, _), _] if depth == 0 => {
+            return Err(EvalError::BadForm(
+                "unquote-splicing is only valid within a list".to_string(),
+                template.clone(),
+            ))
+        }
+        [Value::Symbol(symbol::UNQUOTE | symbol::UNQUOTE_SPLICING, _), ..] if depth == 0 => {
+            return Err(no_match("unquote", exprs))
+        }
+        [symbol @ Value::Symbol(symbol::UNQUOTE | symbol::UNQUOTE_SPLICING, _), expr] => vec![
+            (Quasi::Constant(symbol.clone()), false),
+            (quasiquote_internal(env, expr, depth - 1)?, false),
+        ],
+        [symbol @ Value::Symbol(symbol::QUASIQUOTE, _), expr] => vec![
+            (Quasi::Constant(symbol.clone()), false),
+            (quasiquote_internal(env, expr, depth + 1)?, false),
+        ],
+        _ => exprs
+            .iter()
+            .map(|expr| match expr {
+                Value::List(splice, _) if depth == 0 => match splice.as_ref() {
+                    [Value::Symbol(symbol::UNQUOTE_SPLICING, _), list] => {
+                        Ok((Quasi::Expr(list.clone()), true))
+                    }
+                    _ => Ok((quasiquote_internal(env, expr, depth)?, false)),
+                },
+                _ => Ok((quasiquote_internal(env, expr, depth)?, false)),
+            })
+            .collect::<Result<_, EvalError>>()?,
+    };
+    if parts
+        .iter()
+        .all(|(part, _)| matches!(part, Quasi::Constant(_)))
+    {
+        return Ok(Quasi::Constant(template.clone()));
     }
+    let splices: Rc<[bool]> = parts.iter().map(|(_, splice)| *splice).collect();
+    let copy = function(env.clone(), "value...", move |values| {
+        let mut v = Vec::with_capacity(values.len());
+        for (value, splice) in values.iter().zip(splices.iter()) {
+            match (value, splice) {
+                (Value::List(values, _), true) => v.extend(values.iter().cloned()),
+                (value, true) => {
+                    return Err(EvalError::Type(
+                        format!("unquote-splicing expected a list, got {value}"),
+                        value.clone(),
+                    ))
+                }
+                (value, false) => v.push(value.clone()),
+            }
+        }
+        limits::allocate(v.len())?;
+        Ok(Value::List(List::from(v), None))
+    });
+    let call = std::iter::once(copy).chain(parts.into_iter().map(|(part, _)| part.expr(env)));
+    Ok(Quasi::Expr(Value::List(call.collect(), None)))
 }
 
-pub fn embed_eval<'env>(env: Env<'env>) -> Value<'env> {
+pub fn embed_eval(env: Env) -> Value {
     Value::Procedure(
         env,
-        Rc::new(|env, exprs| match exprs {
-            [expr] => eval(env.clone(), eval(env, expr.clone())?),
-            _ => panic!("{exprs:#?} did not match any forms of procedure \"eval\""),
+        Rc::new(|_, env, exprs| match exprs {
+            [expr] => {
+                let caller = env.clone();
+                evaluate(&env, expr, move |form| Ok(Tail::Eval(caller.clone(), form)))
+            }
+            _ => Err(no_match("eval", exprs)),
         }),
         Rc::new("symbol"),
     )
 }
 
-pub fn begin<'env>(env: Env<'env>) -> Value<'env> {
+pub fn begin(env: Env) -> Value {
     Value::Procedure(
         env,
-        Rc::new(begin_internal),
+        Rc::new(|_, env, exprs| begin_internal(env, exprs)),
         Rc::new("((define-form)...) body"),
     )
 }
 
-pub fn begin_internal<'env>(env: Env<'env>, exprs: &[Value<'env>]) -> EvalResult<'env> {
+pub fn begin_internal(env: Env, exprs: &[Value]) -> TailResult {
     match exprs {
         [defines @ .., body] => {
-            let env = defines
+            // the definitions are bound together, so they can refer to each other
+            let definitions = defines
                 .iter()
-                .try_fold(env, |env, define_expr| match define_expr {
-                    Value::List(lst) => define(env, &lst),
-                    _ => panic!("expected a form of \"define\" / \"define-macro\""),
-                })?;
-            eval(env, body.clone())
+                .map(|define_expr| match define_expr {
+                    Value::List(lst, _) => definition(lst),
+                    _ => Err(EvalError::BadForm(
+                        "expected a form of \"define\" / \"define-macro\" / \"define-syntax\""
+                            .to_string(),
+                        define_expr.clone(),
+                    )),
+                })
+                .collect::<Result<Vec<_>, _>>()?;
+            let body = body.clone();
+            bind_recursive(env, definitions, true, move |env| {
+                Ok(Tail::Eval(env, body.clone()))
+            })
         }
-        _ => panic!("{exprs:#?} did not match any forms of macro procedure \"begin\""),
+        _ => Err(no_match("begin", exprs)),
     }
 }
 
-fn define<'env>(env: Env<'env>, exprs: &[Value<'env>]) -> Result<Env<'env>, ()> {
+/// defines a name at the top level, replacing any definition it already had.
+/// the value can refer to its own name, as it is looked up in the top level when used
+pub fn define_global(env: Env, exprs: &[Value]) -> Result<Env, EvalError> {
+    let (name, value) = definition(exprs)?;
+    let value = run(value(env.clone())?)?;
+    Ok(bind_global(env, name, value))
+}
+
+/// binds `name` in the global frame of `env`, or on top of `env` if it has none
+pub fn bind_global(env: Env, name: Symbol, value: Value) -> Env {
+    match env.define(&name, value) {
+        Ok(()) => env,
+        Err(value) => env.bind(env::Values::new([env::Value(name, value)])),
+    }
+}
+
+/// evaluates the value of a definition, given the environment it is bound in
+type Definition = Rc<dyn Fn(Env) -> TailResult>;
+
+/// matches a define form, giving the name it defines and how to evaluate its value
+fn definition(exprs: &[Value]) -> Result<(Symbol, Definition), EvalError> {
+    let define_form = |form: &str| {
+        EvalError::BadForm(
+            format!("did not match the define form \"{form}\""),
+            Value::List(List::from(exprs), None),
+        )
+    };
     match exprs {
-        [Value::Symbol("define"), Value::Symbol(name), value] => Ok(env
-            .clone()
-            .bind(env::Value(*name, eval(env, value.clone())?))),
-        [Value::Symbol("define"), name_args, body] => match name_args {
-            Value::List(name_args) => match name_args.as_ref() {
-                [Value::Symbol(name), args @ ..] => {
-                    let value = lambda_internal(env.clone(), args, body.clone());
-                    Ok(env.clone().bind(env::Value(*name, value)))
+        [Value::Symbol(symbol::DEFINE, _), Value::Symbol(name, _), value] => {
+            let value = value.clone();
+            Ok((
+                *name,
+                Rc::new(move |env| Ok(Tail::Eval(env, value.clone()))),
+            ))
+        }
+        [Value::Symbol(symbol::DEFINE, _), name_args, body] => match name_args {
+            Value::List(name_args, _) => match name_args.as_ref() {
+                [Value::Symbol(name, _), args @ ..] => {
+                    let (args, body) = (Rc::<[Value]>::from(args), body.clone());
+                    Ok((
+                        *name,
+                        Rc::new(move |env| {
+                            Ok(Tail::Value(lambda_internal(env, &args, body.clone())?))
+                        }),
+                    ))
                 }
-                _ => panic!(
-                    "{exprs:#?} did not match the define form \"(define (name args...) body)\""
-                ),
+                _ => Err(define_form("(define (name args...) body)")),
             },
-            _ => {
-                panic!("{exprs:#?} did not match the define form \"(define (name args...) body)\"")
-            }
+            _ => Err(define_form("(define (name args...) body)")),
         },
-        [Value::Symbol("define-macro"), name_arg, body] => {
-            match name_arg {
-                Value::List(name_args) => match name_args.as_ref() {
-                    [Value::Symbol(name), Value::Symbol(binding)] => {
-                        let value = lambda_macro_internal(env.clone(), binding, body.clone());
-                        Ok(env.clone().bind(env::Value(*name, value)))
-                    }
-                    _ => panic!(
-                    "{exprs:#?} did not match the define form \"(define-macro (name arg) body)\""
-                ),
-                },
-                _ => {
-                    panic!("{exprs:#?} did not match the define form \"(define-macro (name arg) body)\"")
+        [Value::Symbol(symbol::DEFINE_MACRO, _), name_arg, body] => match name_arg {
+            Value::List(name_args, _) => match name_args.as_ref() {
+                [Value::Symbol(name, _), Value::Symbol(binding, _)] => {
+                    let (binding, body) = (*binding, body.clone());
+                    Ok((
+                        *name,
+                        Rc::new(move |env| {
+                            Ok(Tail::Value(lambda_macro_internal(
+                                env,
+                                binding,
+                                body.clone(),
+                            )))
+                        }),
+                    ))
                 }
-            }
+                _ => Err(define_form("(define-macro (name arg) body)")),
+            },
+            _ => Err(define_form("(define-macro (name arg) body)")),
+        },
+        [Value::Symbol(symbol::DEFINE_SYNTAX, _), Value::Symbol(name, _), rules] => {
+            let rules = rules.clone();
+            Ok((
+                *name,
+                Rc::new(move |env| Ok(Tail::Eval(env, rules.clone()))),
+            ))
         }
-        _ => panic!(
-            "{exprs:#?} did not match any forms of macro procedure \"define\" / \"define-macro\""
-        ),
+        _ => Err(no_match(
+            "define\" / \"define-macro\" / \"define-syntax",
+            exprs,
+        )),
     }
 }
 
-fn truthy<'env>(env: Env<'env>, cond: Value<'env>) -> Result<bool, ()> {
-    Ok(match eval(env.clone(), cond.clone())? {
-        Value::Bool(cond) => cond,
-        Value::List(exprs) => exprs.len() != 0,
-        _ => true,
+/// binds every name before any value is evaluated, so that the values can refer to
+/// each other, then continues with the names bound. with `sequential`, each value is bound
+/// as soon as it is evaluated (letrec*), otherwise the values are bound once all of them
+/// have been evaluated (letrec). names are not found until they are bound
+fn bind_recursive(
+    env: Env,
+    definitions: Vec<(Symbol, Definition)>,
+    sequential: bool,
+    rest: impl Fn(Env) -> TailResult + 'static,
+) -> TailResult {
+    // every name is bound in one frame, rather than a frame each
+    let names: Rc<[_]> = definitions.iter().map(|(name, _)| *name).collect();
+    let frame = Rc::new(env::Frame::new(names));
+    let recursive = Recursive {
+        env: env.bind(frame.clone()),
+        frame,
+        definitions: definitions.into_iter().map(|(_, value)| value).collect(),
+        sequential,
+        rest: Box::new(rest),
+    };
+    bind_from(Rc::new(recursive), 0, Vec::new())
+}
+
+/// a [bind_recursive] whose values are being evaluated
+struct Recursive {
+    env: Env,
+    frame: Rc<env::Frame<Symbol, Value>>,
+    definitions: Vec<Definition>,
+    sequential: bool,
+    rest: Box<dyn Fn(Env) -> TailResult>,
+}
+
+/// evaluates the value of the definition at `index` and those after it.
+/// `values` are the values evaluated so far which are not bound yet
+fn bind_from(recursive: Rc<Recursive>, index: usize, values: Vec<Value>) -> TailResult {
+    let Some(definition) = recursive.definitions.get(index) else {
+        for (index, value) in values.into_iter().enumerate() {
+            recursive.frame.put(index, value);
+        }
+        return (recursive.rest)(recursive.env.clone());
+    };
+    let value = definition(recursive.env.clone())?;
+    then(value, move |value| {
+        let mut values = values.clone();
+        match recursive.sequential {
+            true => recursive.frame.put(index, value),
+            false => values.push(value),
+        }
+        bind_from(recursive.clone(), index + 1, values)
     })
 }
 
-pub fn if_cond<'env>(env: Env<'env>) -> Value<'env> {
+pub fn set(env: Env) -> Value {
     Value::Procedure(
         env,
-        Rc::new(|env, exprs| match exprs {
-            [cond, pass, fail] => {
-                if truthy(env.clone(), cond.clone())? {
-                    eval(env, pass.clone())
-                } else {
-                    eval(env, fail.clone())
+        Rc::new(|_, env, exprs| {
+            let (id, address, span, expr) = match exprs {
+                [Value::Symbol(id, span), expr] => (*id, None, span, expr),
+                [Value::Resolved(id, address, span), expr] => (*id, Some(*address), span, expr),
+                _ => return Err(no_match("set!", exprs)),
+            };
+            let (name, caller) = (Value::Symbol(id, span.clone()), env.clone());
+            evaluate(&env, expr, move |value| {
+                let set = match address {
+                    Some(address) => caller.set_at(&id, address, value),
+                    None => caller.set(&id, value),
+                };
+                match set {
+                    Ok(()) => Ok(Tail::Value(Value::List(List::from([]), None))),
+                    Err(_) => Err(EvalError::Unbound(name.clone(), caller.clone())),
                 }
+            })
+        }),
+        Rc::new("name value"),
+    )
+}
+
+pub fn if_cond(env: Env) -> Value {
+    Value::Procedure(
+        env,
+        Rc::new(|_, env, exprs| match exprs {
+            [cond, pass, fail] => {
+                let (caller, pass, fail) = (env.clone(), pass.clone(), fail.clone());
+                evaluate(&env, cond, move |cond| match cond.truthy() {
+                    true => Ok(Tail::Eval(caller.clone(), pass.clone())),
+                    false => Ok(Tail::Eval(caller.clone(), fail.clone())),
+                })
             }
-            _ => panic!("{exprs:#?} did not match any forms of macro procedure \"if?\""),
+            _ => Err(no_match("if?", exprs)),
         }),
         Rc::new("cond pass-body fail-body"),
     )
 }
 
-pub fn guard<'env>(env: Env<'env>) -> Value<'env> {
+pub fn guard(env: Env) -> Value {
     Value::Procedure(
         env,
-        Rc::new(|env, exprs| match exprs {
-            [branches @ .., fail] => {
-                for branch in branches {
-                    match branch {
-                        Value::List(exprs) => match guard_branch(env.clone(), exprs) {
-                            Some(res) => return res,
-                            _ => (),
-                        },
-                        _ => panic!(
-                            "{exprs:#?} did not match any forms of macro procedure \"guard?\""
-                        ),
-                    };
-                }
-                eval(env, fail.clone())
-            }
-            _ => panic!("{exprs:#?} did not match any forms of macro procedure \"guard?\""),
+        Rc::new(|_, env, exprs| match exprs {
+            [branches @ .., fail] => guard_branches(env, branches, fail),
+            _ => Err(no_match("guard?", exprs)),
         }),
         Rc::new("(guard? body)... fail"),
     )
 }
 
-fn guard_branch<'env>(env: Env<'env>, exprs: &[Value<'env>]) -> Option<EvalResult<'env>> {
-    match exprs {
-        [cond, body] => match truthy(env.clone(), cond.clone()) {
-            Ok(cond) => {
-                if cond {
-                    Some(eval(env, body.clone()))
-                } else {
-                    None
-                }
+/// tests the cond of each branch in turn, evaluating the body of the first which passes
+fn guard_branches(env: Env, branches: &[Value], fail: &Value) -> TailResult {
+    for (i, branch) in branches.iter().enumerate() {
+        let [cond, body] = match branch {
+            Value::List(parts, _) => match parts.as_ref() {
+                [cond, body] => [cond, body],
+                _ => return Err(EvalError::BadForm(
+                    "did not match the (guard? body) form of macro procedure \"guard?\"'s branches"
+                        .to_string(),
+                    branch.clone(),
+                )),
+            },
+            _ => return Err(no_match("guard?", branches)),
+        };
+        match atom(&env, cond)? {
+            Some(cond) if cond.truthy() => return Ok(Tail::Eval(env, body.clone())),
+            Some(_) => (),
+            // the rest of the branches are tested once the cond has a value
+            None => {
+                let (caller, body, fail) = (env.clone(), body.clone(), fail.clone());
+                let rest = Rc::<[Value]>::from(&branches[i + 1..]);
+                return Ok(Tail::Then(
+                    env,
+                    cond.clone(),
+                    frame(move |cond| match cond.truthy() {
+                        true => Ok(Tail::Eval(caller.clone(), body.clone())),
+                        false => guard_branches(caller.clone(), &rest, &fail),
+                    }),
+                ));
             }
-            Err(err) => Some(Err(err)),
-        },
-        _ => panic!(
-            "{exprs:#?} did not match the (guard? body) form of macro procedure \"guard?\"'s branches"
-        ),
+        }
     }
+    Ok(Tail::Eval(env, fail.clone()))
 }
 
-pub fn pmatch<'env>(env: Env<'env>) -> Value<'env> {
+pub fn pmatch(env: Env) -> Value {
     Value::Procedure(
         env,
-        Rc::new(|env, exprs| match exprs {
-            [value, branches @ .., fail] => {
-                let value = eval(env.clone(), value.clone())?;
-                for branch in branches.as_ref() {
-                    match branch {
-                        Value::List(branch) => match branch.as_ref() {
-                            [structure, body] => {
-                                match structure_match(env.clone(), value.clone(), structure.clone())
-                                {
-                                    Ok(env) => return eval(env, body.clone()),
-                                    _ => (),
-                                }
-                            }
-                            [structure, guard, body] => {
-                                match structure_match(env.clone(), value.clone(), structure.clone())
-                                {
-                                    Ok(env) => match truthy(env.clone(), guard.clone()) {
-                                        Ok(guard) => {
-                                            if guard {
-                                                return eval(env, body.clone());
-                                            }
-                                        }
-                                        _ => (),
-                                    },
-                                    _ => (),
-                                }
-                            }
-                            _ => panic!(),
-                        },
-                        _ => panic!(
-                            "{exprs:#?} did not match any forms of macro procedure \"guard?\""
-                        ),
-                    };
+        Rc::new(|_, env, exprs| match exprs {
+            [value, branches @ .., fail] => match atom(&env, value)? {
+                Some(value) => pmatch_branches(env, value, branches, fail),
+                None => {
+                    let (caller, fail) = (env.clone(), fail.clone());
+                    let branches = Rc::<[Value]>::from(branches);
+                    Ok(Tail::Then(
+                        env,
+                        value.clone(),
+                        frame(move |value| {
+                            pmatch_branches(caller.clone(), value, &branches, &fail)
+                        }),
+                    ))
                 }
-                eval(env, fail.clone())
-            }
-            _ => panic!("{exprs:#?} did not match any forms of macro procedure \"guard?\""),
+            },
+            _ => Err(no_match("pmatch?", exprs)),
         }),
         Rc::new("(structure [guard?] body)... fail"),
     )
 }
 
-fn structure_match<'env>(env: Env<'env>, l: Value<'env>, r: Value<'env>) -> Result<Env<'env>, ()> {
-    match r {
-        Value::Symbol(id) => Ok(env.bind(env::Value(id, l))),
-        Value::List(r) => match r.as_ref() {
-            [Value::Symbol("quote"), r] => {
-                if l == *r {
-                    Ok(env)
-                } else {
-                    Err(())
-                }
+/// matches `value` against each branch in turn,
+/// evaluating the body of the first which matches and whose guard passes
+fn pmatch_branches(env: Env, value: Value, branches: &[Value], fail: &Value) -> TailResult {
+    for (i, branch) in branches.iter().enumerate() {
+        let (structure, guard, body) = match branch {
+            Value::List(parts, _) => match parts.as_ref() {
+                [structure, body] => (structure, None, body),
+                [structure, guard, body] => (structure, Some(guard), body),
+                _ => return Err(EvalError::BadForm(
+                    "did not match the (structure [guard?] body) form of macro procedure \"pmatch?\"'s branches".to_string(),
+                    branch.clone(),
+                )),
+            },
+            _ => return Err(no_match("pmatch?", branches)),
+        };
+        let mut names = Vec::new();
+        if !structure_match(&value, structure, &mut names)? {
+            continue;
+        }
+        // the names are bound in one frame, latest first,
+        // so that a name bound twice has the value it was bound to last
+        let bound = env.bind(env::Values::new(names.into_iter().rev()));
+        let Some(guard) = guard else {
+            return Ok(Tail::Eval(bound, body.clone()));
+        };
+        match atom(&bound, guard)? {
+            Some(guard) if guard.truthy() => return Ok(Tail::Eval(bound, body.clone())),
+            Some(_) => (),
+            // the rest of the branches are matched once the guard has a value
+            None => {
+                let (caller, within, body, fail) =
+                    (env.clone(), bound.clone(), body.clone(), fail.clone());
+                let (value, rest) = (value.clone(), Rc::<[Value]>::from(&branches[i + 1..]));
+                return Ok(Tail::Then(
+                    bound,
+                    guard.clone(),
+                    frame(move |guard| match guard.truthy() {
+                        true => Ok(Tail::Eval(within.clone(), body.clone())),
+                        false => pmatch_branches(caller.clone(), value.clone(), &rest, &fail),
+                    }),
+                ));
             }
-            [Value::Symbol("quote"), ..] => panic!("invalid quote form in pmatch"),
+        }
+    }
+    Ok(Tail::Eval(env, fail.clone()))
+}
+
+/// whether `l` matches the structure `r`, adding the names `r` binds with their values
+/// to `names`, in the order they are bound
+fn structure_match(
+    l: &Value,
+    r: &Value,
+    names: &mut Vec<env::Value<Symbol, Value>>,
+) -> Result<bool, EvalError> {
+    match r {
+        Value::Symbol(id, _) => {
+            names.push(env::Value(*id, l.clone()));
+            Ok(true)
+        }
+        Value::List(r, span) => match r.as_ref() {
+            [Value::Symbol(symbol::QUOTE, _), r] => Ok(l == r),
+            [Value::Symbol(symbol::QUOTE, _), ..] => Err(EvalError::BadForm(
+                "invalid quote form in pmatch".to_string(),
+                Value::List(r.clone(), span.clone()),
+            )),
             _ => match l {
-                Value::List(l) => {
-                    if l.len() != r.len() {
-                        return Err(());
-                    };
-                    l.iter().zip(r.iter()).try_fold(env, |env, (l, r)| {
-                        structure_match(env, l.clone(), r.clone())
-                    })
+                Value::List(l, _) if l.len() == r.len() => {
+                    for (l, r) in l.iter().zip(r.iter()) {
+                        if !structure_match(l, r, names)? {
+                            return Ok(false);
+                        }
+                    }
+                    Ok(true)
                 }
-                _ => Err(()),
+                _ => Ok(false),
             },
         },
-        _ => {
-            if l == r {
-                Ok(env)
-            } else {
-                Err(())
-            }
-        }
+        _ => Ok(l == r),
     }
 }
+
+pub fn error(env: Env) -> Value {
+    Value::Procedure(
+        env,
+        Rc::new(|_, env, exprs| match exprs {
+            [_, ..] => {
+                let form = Value::List(List::from(exprs), None);
+                evaluate_all(&env, exprs, move |values| {
+                    let message = match &values[0] {
+                        Value::String(message) => message.to_string(),
+                        message => message.to_string(),
+                    };
+                    Err(EvalError::User(
+                        message,
+                        Rc::from(&values[1..]),
+                        form.clone(),
+                    ))
+                })
+            }
+            _ => Err(no_match("error", exprs)),
+        }),
+        Rc::new("message irritant..."),
+    )
+}
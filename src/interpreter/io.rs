@@ -0,0 +1,68 @@
+use std::{
+    io::{self, Write},
+    rc::Rc,
+};
+
+use super::{
+    env,
+    inbuilt::{arity, function},
+    Env, EvalError, EvalResult, List, Value,
+};
+
+fn print(output: impl core::fmt::Display, args: &[Value]) -> EvalResult {
+    let mut stdout = io::stdout();
+    write!(stdout, "{output}")
+        .and_then(|_| stdout.flush())
+        .map_err(|err| {
+            EvalError::User(
+                format!("unable to write to stdout: {err}"),
+                Rc::from([]),
+                Value::List(List::from(args), None),
+            )
+        })?;
+    Ok(Value::List(List::from([]), None))
+}
+
+/// writes strings without quotes or escapes, and other values as they are written
+fn display(args: &[Value]) -> EvalResult {
+    match args {
+        [Value::String(string)] => print(string, args),
+        [value] => print(value, args),
+        _ => Err(arity("display", "1 argument", args)),
+    }
+}
+
+fn write(args: &[Value]) -> EvalResult {
+    match args {
+        [value] => print(value, args),
+        _ => Err(arity("write", "1 argument", args)),
+    }
+}
+
+fn newline(args: &[Value]) -> EvalResult {
+    match args {
+        [] => print('\n', args),
+        _ => Err(arity("newline", "no arguments", args)),
+    }
+}
+
+/// (command-line) the script being run and the arguments given to it
+pub fn command_line(env: Env, args: &[String]) -> Value {
+    let args: Vec<_> = args
+        .iter()
+        .map(|arg| Value::String(Rc::from(arg.as_str())))
+        .collect();
+    let args = Value::List(List::from(args), None);
+    function(env, "", move |exprs| match exprs {
+        [] => Ok(args.clone()),
+        _ => Err(arity("command-line", "no arguments", exprs)),
+    })
+}
+
+pub fn bindings(env: Env) -> Vec<env::Value<&'static str, Value>> {
+    vec![
+        env::Value("display", function(env.clone(), "value", display)),
+        env::Value("write", function(env.clone(), "value", write)),
+        env::Value("newline", function(env, "", newline)),
+    ]
+}
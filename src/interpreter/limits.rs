@@ -0,0 +1,159 @@
+//! limits on evaluation, so that a script cannot loop forever or use up all the memory.
+//! what is left of each limit is kept per thread while a [super::Session] evaluates,
+//! and handed back to the session afterwards, so its limits cover all of its evaluations
+
+use std::cell::Cell;
+
+use super::{EvalError, List, Value};
+
+/// how much evaluation can do, where `None` is unlimited
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Limits {
+    /// the number of calls which can be made
+    pub fuel: Option<u64>,
+    /// how deeply calls can nest, counted in the calls waiting on another to return
+    pub depth: Option<usize>,
+    /// the number of list cells which can be allocated, by builtins which make lists,
+    /// rest parameters and quasiquote. a list shares the cells of the list it is the cdr of,
+    /// so only the cells a builtin adds are counted. strings made by builtins and the names
+    /// of symbols made while evaluating take up a cell for each [CELL_BYTES] bytes of them
+    pub cells: Option<u64>,
+}
+
+thread_local! {
+    static FUEL: Cell<Option<u64>> = const { Cell::new(None) };
+    static CELLS: Cell<Option<u64>> = const { Cell::new(None) };
+    static MAX_DEPTH: Cell<Option<usize>> = const { Cell::new(None) };
+    /// the number of calls waiting on another to return,
+    /// in the evaluations further out than the one running
+    static DEPTH: Cell<usize> = const { Cell::new(0) };
+}
+
+/// runs `f` within `limits`, leaving in `limits` what `f` did not use
+pub fn within<T>(limits: &mut Limits, f: impl FnOnce() -> T) -> T {
+    let outer = Limits {
+        fuel: FUEL.replace(limits.fuel),
+        depth: MAX_DEPTH.replace(limits.depth),
+        cells: CELLS.replace(limits.cells),
+    };
+    let value = f();
+    limits.fuel = FUEL.replace(outer.fuel);
+    limits.cells = CELLS.replace(outer.cells);
+    MAX_DEPTH.set(outer.depth);
+    value
+}
+
+/// uses a unit of fuel for the call `call` gives
+pub fn step(call: impl FnOnce() -> Value) -> Result<(), EvalError> {
+    match FUEL.get() {
+        None => Ok(()),
+        Some(0) => Err(EvalError::Limit(
+            "out of fuel, the limit on the number of calls was reached".to_string(),
+            call(),
+        )),
+        Some(fuel) => {
+            FUEL.set(Some(fuel - 1));
+            Ok(())
+        }
+    }
+}
+
+/// the calls waiting in evaluations further out, for an evaluation starting
+pub fn depth() -> usize {
+    DEPTH.get()
+}
+
+/// records the calls waiting, for evaluations started from here
+pub fn set_depth(depth: usize) {
+    DEPTH.set(depth);
+}
+
+/// the deepest calls can nest
+pub fn max_depth() -> usize {
+    MAX_DEPTH.get().unwrap_or(usize::MAX)
+}
+
+/// the error for calls nesting deeper than [max_depth]
+pub fn too_deep(form: Value) -> EvalError {
+    EvalError::Limit(
+        format!("calls nested deeper than the limit of {}", max_depth()),
+        form,
+    )
+}
+
+/// the size of a list cell, which [allocate_bytes] counts in
+pub const CELL_BYTES: usize = core::mem::size_of::<Value>();
+
+/// allocates the cells taken up by `bytes` bytes of a string or name
+pub fn allocate_bytes(bytes: usize) -> Result<(), EvalError> {
+    allocate(bytes.div_ceil(CELL_BYTES).max(1))
+}
+
+/// allocates `count` list cells
+pub fn allocate(count: usize) -> Result<(), EvalError> {
+    match CELLS.get() {
+        None => Ok(()),
+        Some(cells) => match cells.checked_sub(count as u64) {
+            Some(left) => {
+                CELLS.set(Some(left));
+                Ok(())
+            }
+            None => Err(EvalError::Limit(
+                "the limit on the number of list cells allocated was reached".to_string(),
+                Value::List(List::from([]), None),
+            )),
+        },
+    }
+}
+
+#[test]
+fn expand_test() {
+    use super::{test::read, Session};
+    let mut session = Session::default();
+    session.limits(Limits { fuel: Some(100), ..Limits::default() });
+    // expanding evaluates definitions, which are kept to the limits of the session
+    let expanded = session.expand(read("(define (loop) (loop)) (define x (loop))"), |_| ());
+    assert!(matches!(expanded, Err(EvalError::Limit(_, _))));
+}
+
+#[test]
+fn limits_test() {
+    use super::{test::read, Engine, Session};
+    let run = |limits, engine, source: &str| {
+        let mut session = Session::default();
+        session.limits(limits);
+        session.engine(engine);
+        session.run(read(source), |_| ())
+    };
+    let count = "(define (count n) (if? (= n 0) 0 (+ 1 (count (- n 1)))))";
+    let fuel = Limits { fuel: Some(1000), ..Limits::default() };
+    let depth = Limits { depth: Some(100), ..Limits::default() };
+    let cells = Limits { cells: Some(1000), ..Limits::default() };
+    for engine in [Engine::Tree, Engine::Vm] {
+        let exceeded = |limits, source: &str| {
+            matches!(run(limits, engine, source), Err(EvalError::Limit(_, _)))
+        };
+        assert!(exceeded(fuel, "(define (loop) (loop)) (loop)"));
+        // limits end evaluation, rather than being caught
+        assert!(exceeded(fuel, "(define (loop) (loop)) (guard (e (#t 'caught)) (loop))"));
+        assert!(exceeded(depth, &format!("{count} (count 1000)")));
+        assert!(exceeded(cells, "(define (grow l) (grow (cons 1 l))) (grow '())"));
+        // strings, and the names of symbols made while evaluating, take up cells too
+        assert!(exceeded(cells, "(define (grow s) (grow (string-append s \"x\"))) (grow \"\")"));
+        assert!(exceeded(cells, "(define (spin) (let ((_ (gensym))) (spin))) (spin)"));
+        assert!(exceeded(cells, "(define (spin) (let ((_ (string->symbol (symbol->string (gensym))))) (spin))) (spin)"));
+        assert!(run(depth, engine, &format!("{count} (count 10)")).is_ok());
+        // a list of n values built with cons and walked with cdr takes up n cells
+        let build = "(define (build n l) (if? (= n 0) l (build (- n 1) (cons n l))))
+            (define (walk l n) (if? (null? l) n (walk (cdr l) (+ n 1))))";
+        let enough = Limits { cells: Some(20010), ..Limits::default() };
+        let walked = run(enough, engine, &format!("{build} (walk (member 10 (build 20000 '())) 0)"));
+        assert_eq!(Some(Value::Int(19991)), walked.unwrap());
+        assert!(exceeded(enough, &format!("{build} (build 20011 '())")));
+    }
+    // the fuel of a session is shared by all of its evaluations
+    let mut session = Session::default();
+    session.limits(Limits { fuel: Some(3), ..Limits::default() });
+    assert!(session.run(read("(+ 1 2) (+ 3 4)"), |_| ()).is_ok());
+    assert!(session.run(read("(+ 1 2) (+ 3 4)"), |_| ()).is_err());
+}
@@ -0,0 +1,470 @@
+use std::rc::Rc;
+
+use super::{
+    cps::{evaluate_all, iterate, Step},
+    env,
+    inbuilt::{apply, arity, function, tail_function},
+    limits, Env, EvalError, EvalResult, List, TailResult, Value,
+};
+
+fn list(value: &Value) -> Result<&List, EvalError> {
+    match value {
+        Value::List(values, _) => Ok(values),
+        _ => Err(EvalError::Type(
+            format!("expected a list, got {value}"),
+            value.clone(),
+        )),
+    }
+}
+
+/// a new list of `values`, allocating a cell for each
+fn from_vec(values: Vec<Value>) -> EvalResult {
+    limits::allocate(values.len())?;
+    Ok(Value::List(List::from(values), None))
+}
+
+/// the values collected by a builtin so far, the latest first. the steps of a builtin share
+/// what they have collected, so a continuation resumed again collects from where it was
+#[derive(Clone)]
+struct Collected<T>(Option<Rc<(T, Collected<T>)>>);
+
+impl<T: Clone> Collected<T> {
+    fn new() -> Self {
+        Collected(None)
+    }
+
+    fn push(&self, value: T) -> Self {
+        Collected(Some(Rc::new((value, self.clone()))))
+    }
+
+    /// the values collected, the first first
+    fn to_vec(&self) -> Vec<T> {
+        let mut values = Vec::new();
+        let mut collected = &self.0;
+        while let Some(cell) = collected {
+            values.push(cell.0.clone());
+            collected = &cell.1 .0;
+        }
+        values.reverse();
+        values
+    }
+}
+
+fn cons(args: &[Value]) -> EvalResult {
+    match args {
+        [head, tail] => Ok(Value::List(List::cons(head.clone(), list(tail)?)?, None)),
+        _ => Err(arity("cons", "2 arguments", args)),
+    }
+}
+
+fn car(args: &[Value]) -> EvalResult {
+    match args {
+        [value] => match list(value)?.first() {
+            Some(head) => Ok(head.clone()),
+            None => Err(EvalError::Type(
+                "cannot take the car of the empty list".to_string(),
+                value.clone(),
+            )),
+        },
+        _ => Err(arity("car", "1 argument", args)),
+    }
+}
+
+fn cdr(args: &[Value]) -> EvalResult {
+    match args {
+        [value] => match list(value)? {
+            values if !values.is_empty() => Ok(Value::List(values.skip(1), None)),
+            _ => Err(EvalError::Type(
+                "cannot take the cdr of the empty list".to_string(),
+                value.clone(),
+            )),
+        },
+        _ => Err(arity("cdr", "1 argument", args)),
+    }
+}
+
+fn predicate(name: &'static str, test: fn(&[Value]) -> bool) -> impl Fn(&[Value]) -> EvalResult {
+    move |args| match args {
+        [Value::List(values, _)] => Ok(Value::Bool(test(values))),
+        [_] => Ok(Value::Bool(false)),
+        _ => Err(arity(name, "1 argument", args)),
+    }
+}
+
+fn length(args: &[Value]) -> EvalResult {
+    match args {
+        [value] => Ok(Value::Int(list(value)?.len() as i64)),
+        _ => Err(arity("length", "1 argument", args)),
+    }
+}
+
+fn append(args: &[Value]) -> EvalResult {
+    let mut values = Vec::new();
+    for arg in args {
+        values.extend(list(arg)?.iter().cloned());
+    }
+    from_vec(values)
+}
+
+fn reverse(args: &[Value]) -> EvalResult {
+    match args {
+        [value] => from_vec(list(value)?.iter().rev().cloned().collect()),
+        _ => Err(arity("reverse", "1 argument", args)),
+    }
+}
+
+fn list_ref(args: &[Value]) -> EvalResult {
+    match args {
+        [value, index @ Value::Int(i)] => {
+            let values = list(value)?;
+            usize::try_from(*i)
+                .ok()
+                .and_then(|i| values.get(i))
+                .cloned()
+                .ok_or_else(|| {
+                    EvalError::Type(
+                        format!(
+                            "index {i} is out of bounds for a list of length {}",
+                            values.len()
+                        ),
+                        index.clone(),
+                    )
+                })
+        }
+        [_, index] => Err(EvalError::Type(
+            format!("expected an integer index, got {index}"),
+            index.clone(),
+        )),
+        _ => Err(arity("list-ref", "2 arguments", args)),
+    }
+}
+
+fn map(args: &[Value]) -> TailResult {
+    match args {
+        [procedure, lists @ ..] if !lists.is_empty() => {
+            let lists = lists
+                .iter()
+                .map(|value| list(value).cloned())
+                .collect::<Result<Rc<[_]>, _>>()?;
+            let len = lists.iter().map(|list| list.len()).min().unwrap_or(0);
+            let procedure = procedure.clone();
+            let step = move |i: usize, collected: Collected<Value>| match i < len {
+                true => {
+                    let args = lists.iter().map(|list| list[i].clone()).collect();
+                    Ok(Step::Call(procedure.clone(), args, (i, collected)))
+                }
+                false => Ok(Step::Done(from_vec(collected.to_vec())?)),
+            };
+            iterate(step(0, Collected::new())?, move |(i, collected), value| {
+                step(i + 1, collected.push(value))
+            })
+        }
+        _ => Err(arity("map", "at least 2 arguments", args)),
+    }
+}
+
+fn filter(args: &[Value]) -> TailResult {
+    match args {
+        [procedure, value] => {
+            let (procedure, values) = (procedure.clone(), list(value)?.clone());
+            let tested = values.clone();
+            let step = move |i: usize, collected: Collected<Value>| match tested.get(i) {
+                Some(value) => Ok(Step::Call(
+                    procedure.clone(),
+                    vec![value.clone()],
+                    (i, collected),
+                )),
+                None => Ok(Step::Done(from_vec(collected.to_vec())?)),
+            };
+            iterate(step(0, Collected::new())?, move |(i, collected), keep| {
+                let collected = match keep.truthy() {
+                    true => collected.push(values[i].clone()),
+                    false => collected,
+                };
+                step(i + 1, collected)
+            })
+        }
+        _ => Err(arity("filter", "2 arguments", args)),
+    }
+}
+
+/// folds `values` in order, with `args` giving the arguments of procedure
+/// from the accumulator and a value
+fn fold(
+    procedure: &Value,
+    init: &Value,
+    values: Vec<Value>,
+    args: fn(Value, Value) -> Vec<Value>,
+) -> TailResult {
+    let procedure = procedure.clone();
+    let step = move |i: usize, acc: Value| match values.get(i) {
+        Some(value) => Step::Call(procedure.clone(), args(acc, value.clone()), i),
+        None => Step::Done(acc),
+    };
+    iterate(step(0, init.clone()), move |i, acc| Ok(step(i + 1, acc)))
+}
+
+fn fold_left(args: &[Value]) -> TailResult {
+    match args {
+        [procedure, init, value] => fold(procedure, init, list(value)?.to_vec(), |acc, value| {
+            vec![acc, value]
+        }),
+        _ => Err(arity("fold-left", "3 arguments", args)),
+    }
+}
+
+fn fold_right(args: &[Value]) -> TailResult {
+    match args {
+        [procedure, init, value] => {
+            let values = list(value)?.iter().rev().cloned().collect();
+            fold(procedure, init, values, |acc, value| vec![value, acc])
+        }
+        _ => Err(arity("fold-right", "3 arguments", args)),
+    }
+}
+
+fn assoc(args: &[Value]) -> EvalResult {
+    match args {
+        [key, alist] => {
+            for entry in list(alist)?.iter() {
+                match entry {
+                    Value::List(pair, _) if pair.first() == Some(key) => return Ok(entry.clone()),
+                    Value::List(_, _) => (),
+                    _ => {
+                        return Err(EvalError::Type(
+                            format!("expected an association list of lists, got entry {entry}"),
+                            entry.clone(),
+                        ))
+                    }
+                }
+            }
+            Ok(Value::Bool(false))
+        }
+        _ => Err(arity("assoc", "2 arguments", args)),
+    }
+}
+
+fn member(args: &[Value]) -> EvalResult {
+    match args {
+        [value, values] => {
+            let values = list(values)?;
+            match values.iter().position(|v| v == value) {
+                Some(i) => Ok(Value::List(values.skip(i), None)),
+                None => Ok(Value::Bool(false)),
+            }
+        }
+        _ => Err(arity("member", "2 arguments", args)),
+    }
+}
+
+/// a pass of a stable bottom up merge sort, merging each pair of runs in turn
+#[derive(Clone)]
+struct Merge {
+    runs: Rc<[Rc<[Value]>]>,
+    /// the left run of the pair being merged
+    pair: usize,
+    /// how far into the left and right runs have been merged
+    left: usize,
+    right: usize,
+    merging: Collected<Value>,
+    merged: Collected<Rc<[Value]>>,
+}
+
+impl Merge {
+    /// the next comparison of the sort, or the sorted values once there are none left
+    fn step(mut self, less: &Value) -> Result<Step<Merge>, EvalError> {
+        loop {
+            if self.runs.len() <= 1 {
+                let sorted = self.runs.first().map_or(Vec::new(), |run| run.to_vec());
+                return Ok(Step::Done(from_vec(sorted)?));
+            }
+            let (Some(left), Some(right)) =
+                (self.runs.get(self.pair), self.runs.get(self.pair + 1))
+            else {
+                // the last run of an odd number of runs has nothing to merge with
+                if let Some(run) = self.runs.get(self.pair) {
+                    self.merged = self.merged.push(run.clone());
+                }
+                self.runs = Rc::from(self.merged.to_vec());
+                (self.pair, self.merged) = (0, Collected::new());
+                continue;
+            };
+            match (left.get(self.left), right.get(self.right)) {
+                (Some(l), Some(r)) => {
+                    // only taking from the right when it is strictly less keeps equal values in order
+                    let args = vec![r.clone(), l.clone()];
+                    return Ok(Step::Call(less.clone(), args, self));
+                }
+                _ => {
+                    let mut run = self.merging.to_vec();
+                    run.extend_from_slice(&left[self.left..]);
+                    run.extend_from_slice(&right[self.right..]);
+                    self.merged = self.merged.push(Rc::from(run));
+                    (self.pair, self.left, self.right) = (self.pair + 2, 0, 0);
+                    self.merging = Collected::new();
+                }
+            }
+        }
+    }
+
+    /// takes the next value of the pair being merged, from the right when it was less
+    fn take(mut self, right_less: bool) -> Self {
+        let value = match right_less {
+            true => {
+                self.right += 1;
+                self.runs[self.pair + 1][self.right - 1].clone()
+            }
+            false => {
+                self.left += 1;
+                self.runs[self.pair][self.left - 1].clone()
+            }
+        };
+        self.merging = self.merging.push(value);
+        self
+    }
+}
+
+fn sort(args: &[Value]) -> TailResult {
+    match args {
+        [value, less] => {
+            let merge = Merge {
+                runs: list(value)?
+                    .iter()
+                    .map(|value| Rc::from([value.clone()]))
+                    .collect(),
+                pair: 0,
+                left: 0,
+                right: 0,
+                merging: Collected::new(),
+                merged: Collected::new(),
+            };
+            let less = less.clone();
+            iterate(merge.step(&less)?, move |merge, right_less| {
+                merge.take(right_less.truthy()).step(&less)
+            })
+        }
+        _ => Err(arity("sort", "2 arguments", args)),
+    }
+}
+
+/// (apply procedure arg... list)
+/// the call is in tail position, so it is not run to a value here
+fn apply_list(env: Env) -> Value {
+    Value::Procedure(
+        env,
+        Rc::new(|_, env, exprs| {
+            evaluate_all(&env, exprs, |args| match args.as_slice() {
+                [procedure, args @ .., last] => {
+                    let mut args = args.to_vec();
+                    args.extend(list(last)?.iter().cloned());
+                    apply(procedure, &args)
+                }
+                _ => Err(arity("apply", "at least 2 arguments", &args)),
+            })
+        }),
+        Rc::new("procedure arg... list"),
+    )
+}
+
+pub fn bindings(env: Env) -> Vec<env::Value<&'static str, Value>> {
+    vec![
+        env::Value("cons", function(env.clone(), "value list", cons)),
+        env::Value("car", function(env.clone(), "list", car)),
+        env::Value("cdr", function(env.clone(), "list", cdr)),
+        env::Value(
+            "list",
+            function(env.clone(), "value...", |args| from_vec(args.to_vec())),
+        ),
+        env::Value(
+            "null?",
+            function(
+                env.clone(),
+                "value",
+                predicate("null?", |values| values.is_empty()),
+            ),
+        ),
+        env::Value(
+            "pair?",
+            function(
+                env.clone(),
+                "value",
+                predicate("pair?", |values| !values.is_empty()),
+            ),
+        ),
+        env::Value("length", function(env.clone(), "list", length)),
+        env::Value("append", function(env.clone(), "list...", append)),
+        env::Value("reverse", function(env.clone(), "list", reverse)),
+        env::Value("list-ref", function(env.clone(), "list index", list_ref)),
+        env::Value(
+            "map",
+            tail_function(env.clone(), "procedure list list...", map),
+        ),
+        env::Value(
+            "filter",
+            tail_function(env.clone(), "procedure list", filter),
+        ),
+        env::Value(
+            "fold-left",
+            tail_function(env.clone(), "procedure init list", fold_left),
+        ),
+        env::Value(
+            "fold-right",
+            tail_function(env.clone(), "procedure init list", fold_right),
+        ),
+        env::Value("assoc", function(env.clone(), "key alist", assoc)),
+        env::Value("member", function(env.clone(), "value list", member)),
+        env::Value("sort", tail_function(env.clone(), "list less?", sort)),
+        env::Value("apply", apply_list(env)),
+    ]
+}
+
+#[test]
+fn lists_test() {
+    use super::test::{eval, interpret_str};
+    assert_eq!(Some("(1 2 3)".to_string()), eval("(cons 1 (list 2 3))"));
+    assert_eq!(Some("(b c)".to_string()), eval("(cdr (quote (a b c)))"));
+    assert_eq!(Some("#t".to_string()), eval("(null? (cdr (list 1)))"));
+    assert_eq!(
+        Some("(3 2 1 4)".to_string()),
+        eval("(append (reverse (list 1 2 3)) (list 4))")
+    );
+    assert_eq!(
+        Some("(2 4 6)".to_string()),
+        eval("(map (lambda (x) (* x 2)) (list 1 2 3))")
+    );
+    assert_eq!(
+        Some("(11 22)".to_string()),
+        eval("(map + (list 1 2) (list 10 20 30))")
+    );
+    assert_eq!(
+        Some("(2 4)".to_string()),
+        eval("(filter even? (list 1 2 3 4))")
+    );
+    assert_eq!(
+        Some("(3 (2 1))".to_string()),
+        eval("(fold-left (lambda (acc x) (list x acc)) 1 (list 2 3))")
+    );
+    assert_eq!(
+        Some("(1 (2 3))".to_string()),
+        eval("(fold-right list 3 (list 1 2))")
+    );
+    assert_eq!(
+        Some("(b 2)".to_string()),
+        eval("(assoc (quote b) (quote ((a 1) (b 2))))")
+    );
+    assert_eq!(
+        Some("(c d)".to_string()),
+        eval("(member (quote c) (quote (a b c d)))")
+    );
+    assert_eq!(Some("6".to_string()), eval("(apply + 1 (list 2 3))"));
+    assert_eq!(Some("(1 2 3)".to_string()), eval("(sort (list 3 1 2) <)"));
+    assert_eq!(Some("()".to_string()), eval("(sort (list) <)"));
+    assert_eq!(
+        Some("((0 b) (0 d) (1 a) (1 c) (1 e))".to_string()),
+        eval("(sort '((1 a) (0 b) (1 c) (0 d) (1 e)) (lambda (a b) (< (car a) (car b))))")
+    );
+    assert!(matches!(
+        interpret_str("(car (list))"),
+        Err(EvalError::Type(_, _))
+    ));
+}
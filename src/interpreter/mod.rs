@@ -1,16 +1,65 @@
 use core::fmt::Display;
 use std::rc::Rc;
 
+mod boxes;
+mod capability;
+mod cells;
+mod cps;
+mod effect;
 mod env;
+mod error;
+mod exception;
+mod expand;
 mod inbuilt;
+mod io;
+mod limits;
+mod list;
+mod number;
+mod params;
+mod resolve;
+mod string;
+mod symbol;
+mod syntax;
+#[cfg(test)]
+mod test;
 mod values;
+mod vm;
 
-//mod cps;
-
+use cps::run;
+use env::Lookup;
+pub use capability::{Capabilities, Capability};
+pub use cells::List;
+pub use error::EvalError;
+pub use limits::Limits;
+pub use symbol::Symbol;
 pub use values::Value;
 
-type EvalResult<'env> = Result<Value<'env>, ()>;
-type Env<'env> = env::NameEnv<'env, Value<'env>>;
+type EvalResult = Result<Value, EvalError>;
+type TailResult = Result<Tail, EvalError>;
+type Env = env::NameEnv<Value>;
+
+/// the result of applying a procedure
+/// expressions in tail position are handed back to [eval] rather than evaluated in place,
+/// so that tail calls run in constant stack
+#[derive(Clone)]
+pub enum Tail {
+    Value(Value),
+    Eval(Env, Value),
+    /// evaluates an expression, then continues with its value, see [cps]
+    Then(Env, Value, cps::Frame),
+    /// applies a procedure to the continuation of the call, see [cps]
+    Capture(Value),
+    /// evaluates an expression, handing an error raised while evaluating it to a handler,
+    /// see [cps]
+    Catch(Env, Value, cps::Handler),
+    /// evaluates an expression, handing effects performed while evaluating it to clauses,
+    /// see [cps]
+    Handle(Env, Value, Rc<cps::Effects>),
+    /// performs an effect with a value
+    Perform(Value, Value),
+    /// resumes the computation an effect was performed in with a value
+    Resume(Rc<cps::Resumption>, Value),
+}
 
 pub struct DisplayList<D: Display>(Rc<[D]>);
 impl<D: Display> Display for DisplayList<D> {
@@ -29,9 +78,11 @@ impl<D: Display> Display for DisplayList<D> {
     }
 }
 
-pub fn interpret<'env>(expr: Value<'env>) -> Result<(), ()> {
-    let env = Env::new();
-    let env = env.clone().bind(env::Values::new([
+/// the environment programs start in, with every builtin bound.
+/// the builtins of capabilities not in `capabilities` are bound to [capability::denied]
+fn root(capabilities: Capabilities) -> Env {
+    let env = Env::global();
+    let special_forms = [
         env::Value(
             "lambda",
             inbuilt::lambda(env.clone()),
@@ -68,37 +119,402 @@ pub fn interpret<'env>(expr: Value<'env>) -> Result<(), ()> {
             "if?",
             inbuilt::if_cond(env.clone())
         ),
-        env::Value("eval", inbuilt::embed_eval(env.clone())),
-    ]));
-
-    let value = match expr {
-        Value::List(exprs) => inbuilt::begin_internal(env, exprs.as_ref()),
-        expr @ Value::Symbol(_) => inbuilt::begin_internal(env, &[expr]),
-        _ => unreachable!(),
-    }?;
-    println!("{value}");
-    Ok(())
-}
-
-fn eval<'env>(env: Env<'env>, expr: Value<'env>) -> EvalResult<'env> {
-    match expr {
-        Value::List(expressions) => invoke(env, &expressions),
-        Value::Symbol(str) => Ok(Value::from_env(env, str)?),
-        _ => Ok(expr),
-    }
-}
-
-fn invoke<'env>(env: Env<'env>, exprs: &[Value<'env>]) -> EvalResult<'env> {
-    match exprs {
-        [] => panic!("cannot eval {exprs:#?}"),
-        [procedure, args @ ..] => {
-            let procedure = eval(env.clone(), procedure.clone())?;
-            match procedure {
-                Value::Procedure(proc_env, proc, _) => {
-                    proc(env.bind(proc_env), args)
+        env::Value("error", inbuilt::error(env.clone())),
+        env::Value("set!", inbuilt::set(env.clone())),
+        env::Value("letrec", inbuilt::letrec(env.clone(), false)),
+        env::Value("letrec*", inbuilt::letrec(env.clone(), true)),
+        env::Value("syntax-rules", inbuilt::syntax_rules(env.clone())),
+        env::Value("let-syntax", inbuilt::bind_let(env.clone())),
+        env::Value("letrec-syntax", inbuilt::letrec(env.clone(), false)),
+    ];
+    let core = special_forms.into_iter()
+        .chain(number::bindings(env.clone()))
+        .chain(list::bindings(env.clone()))
+        .chain(string::bindings(env.clone()))
+        .chain(boxes::bindings(env.clone()))
+        .chain(cps::bindings(env.clone()))
+        .chain(exception::bindings(env.clone()))
+        .chain(effect::bindings(env.clone()))
+        .chain(expand::bindings(env.clone()))
+        .collect();
+    // there are no builtins for the filesystem or the network yet.
+    // `command-line` gives no arguments until [Session::command_line] binds it again
+    let groups = [
+        (Capability::Core, core),
+        (Capability::Io, io::bindings(env.clone())),
+        (Capability::Process, vec![env::Value("command-line", io::command_line(env.clone(), &[]))]),
+        (Capability::Eval, vec![env::Value("eval", inbuilt::embed_eval(env.clone()))]),
+    ];
+    for (capability, bindings) in groups {
+        for env::Value(name, value) in bindings {
+            let value = match capabilities.granted(capability) {
+                true => value,
+                false => capability::denied(env.clone(), name, capability),
+            };
+            env.define(&Symbol::new(name), value).expect("the root environment is global");
+        }
+    }
+    env
+}
+
+/// how a [Session] evaluates its forms
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Engine {
+    /// walks the expression trees with [eval]
+    #[default]
+    Tree,
+    /// compiles each form to bytecode, and runs it on the [vm]
+    Vm,
+}
+
+/// a top level which keeps its definitions between evaluations,
+/// such as the top level of the repl
+pub struct Session {
+    env: Env,
+    engine: Engine,
+    /// what is left of the limits of the session
+    limits: Limits,
+    capabilities: Capabilities,
+}
+
+impl Default for Session {
+    fn default() -> Self {
+        Session::new(Capabilities::default())
+    }
+}
+
+impl Session {
+    /// a session whose forms can only use the builtins of `capabilities`
+    pub fn new(capabilities: Capabilities) -> Self {
+        Session {
+            env: root(capabilities),
+            engine: Engine::default(),
+            limits: Limits::default(),
+            capabilities,
+        }
+    }
+
+    /// evaluates later forms with `engine`
+    pub fn engine(&mut self, engine: Engine) {
+        self.engine = engine;
+    }
+
+    /// limits the evaluations from here on to `limits` between them
+    pub fn limits(&mut self, limits: Limits) {
+        self.limits = limits;
+    }
+
+    /// binds `command-line` to a procedure returning `args` as a list of strings
+    pub fn command_line(&mut self, args: &[String]) {
+        let command_line = match self.capabilities.granted(Capability::Process) {
+            true => io::command_line(self.env.clone(), args),
+            false => capability::denied(self.env.clone(), "command-line", Capability::Process),
+        };
+        self.env.define(&Symbol::new("command-line"), command_line).expect("a session's environment is global");
+    }
+
+    /// evaluates each top level form of `program` in turn, giving the value of each
+    /// which is not a definition to `each`.
+    /// returns the value of the last form, unless it was a definition
+    pub fn run(
+        &mut self,
+        program: Value,
+        mut each: impl FnMut(&Value),
+    ) -> Result<Option<Value>, EvalError> {
+        // forms are moved out of the program, so that nothing holds on to a form after
+        // it has been evaluated, and its values can be dropped as evaluation moves past them
+        let forms = match program {
+            Value::List(forms, _) => forms.to_vec(),
+            form => vec![form],
+        };
+        let mut last = None;
+        for form in forms {
+            last = self.eval(form)?;
+            if let Some(value) = &last {
+                each(value);
+            }
+        }
+        Ok(last)
+    }
+
+    /// expands the macros in each top level form of `program` in turn,
+    /// giving each expanded form to `each`, preceded by any definitions it needs to be
+    /// read back, see [expand::expand_to_print]. definitions are evaluated,
+    /// so that later forms can use the macros and procedures they define
+    pub fn expand(
+        &mut self,
+        program: Value,
+        mut each: impl FnMut(&Value),
+    ) -> Result<(), EvalError> {
+        let forms = match program {
+            Value::List(forms, _) => forms.to_vec(),
+            form => vec![form],
+        };
+        for form in forms {
+            let expanded = self.within_limits(|session| {
+                let expanded = expand::expand_to_print(&session.env, &form).map_err(|err| err.within(&form))?;
+                for form in &expanded {
+                    session.define(form)?;
                 }
-                _ => panic!("cannot call non-procedure: {procedure}"),
+                Ok(expanded)
+            })?;
+            expanded.iter().for_each(&mut each);
+        }
+        Ok(())
+    }
+
+    /// expands the macros in a form, then evaluates it.
+    /// a define form binds its name for later evaluations,
+    /// any other expression gives its value
+    pub fn eval(&mut self, expr: Value) -> Result<Option<Value>, EvalError> {
+        self.within_limits(|session| {
+            let expanded = expand::expand(&session.env, &expr).map_err(|err| err.within(&expr))?;
+            // as in [Session::run], the form is not held on to while it is evaluated
+            drop(expr);
+            // the vm resolves the names of what it compiles itself
+            let expanded = match session.engine {
+                Engine::Tree => resolve::resolve(expanded),
+                Engine::Vm => expanded,
+            };
+            match session.define(&expanded)? {
+                true => Ok(None),
+                false => match session.engine {
+                    Engine::Tree => eval(session.env.clone(), expanded).map(Some),
+                    Engine::Vm => vm::evaluate(&session.env, &expanded).map(Some),
+                },
+            }
+        })
+    }
+
+    /// runs `f` within what is left of the limits of the session
+    fn within_limits<T>(&mut self, f: impl FnOnce(&mut Self) -> T) -> T {
+        let mut limits = self.limits;
+        let value = limits::within(&mut limits, || f(self));
+        self.limits = limits;
+        value
+    }
+
+    /// evaluates `expr` if it is a define form, giving whether it was
+    fn define(&mut self, expr: &Value) -> Result<bool, EvalError> {
+        // definitions the compiler does not handle, such as of macros, are left to [eval]
+        if let (Engine::Vm, Value::List(form, _)) = (self.engine, expr) {
+            if let Some((name, function)) = vm::compile_definition(form) {
+                let value = vm::execute(&self.env, function).map_err(|err| err.within(expr))?;
+                self.env = inbuilt::bind_global(self.env.clone(), name, value);
+                return Ok(true);
+            }
+        }
+        match expr {
+            Value::List(form, _) if matches!(
+                form.first(),
+                Some(Value::Symbol(symbol::DEFINE | symbol::DEFINE_MACRO | symbol::DEFINE_SYNTAX, _))
+            ) => {
+                self.env = inbuilt::define_global(self.env.clone(), form).map_err(|err| err.within(expr))?;
+                Ok(true)
             }
+            _ => Ok(false),
         }
     }
 }
+
+fn eval(env: Env, expr: Value) -> EvalResult {
+    run(Tail::Eval(env, expr))
+}
+
+fn invoke(env: Env, call: &List) -> TailResult {
+    match call.as_ref() {
+        [] => Err(EvalError::BadForm(
+            "cannot evaluate the empty list".to_string(),
+            Value::List(call.clone(), None),
+        )),
+        [procedure, ..] => {
+            limits::step(|| Value::List(call.clone(), None))?;
+            let caller = env.clone();
+            let call = call.clone();
+            cps::evaluate(&env, procedure, move |procedure| {
+                call_procedure(caller.clone(), &call, procedure)
+            })
+        }
+    }
+}
+
+/// applies the value of the head of a call to its unevaluated arguments
+fn call_procedure(env: Env, call: &List, procedure: Value) -> TailResult {
+    let args = &call[1..];
+    match procedure {
+        Value::Procedure(proc_env, proc, _) => proc(&proc_env, env, args),
+        Value::Macro(mac) => mac.call(env, call),
+        Value::Closure(closure) => {
+            cps::evaluate_all(&env, args, move |args| vm::call(&closure, args))
+        }
+        _ => Err(EvalError::Type(
+            "cannot call non-procedure".to_string(),
+            procedure,
+        )),
+    }
+}
+
+#[test]
+fn unbound_variable() {
+    match test::interpret_str("undefined") {
+        Err(EvalError::Unbound(Value::Symbol(name, Some(span)), _)) => {
+            assert_eq!("undefined", &*name.name());
+            assert_eq!("undefined", span.view().as_str());
+        }
+        res => panic!("expected an unbound variable error, got {res:?}"),
+    }
+}
+
+#[test]
+fn wrong_arity() {
+    assert!(matches!(
+        test::interpret_str("((lambda (a b) a) (quote x))"),
+        Err(EvalError::Arity(_, _))
+    ));
+}
+
+#[test]
+fn tail_calls_run_in_constant_stack() {
+    // (x (x (x ... ()))) nested deeper than the stack could take as ordinary calls
+    let mut deep = Value::List(List::from([]), None);
+    for _ in 0..10_000 {
+        deep = Value::List(List::from([Value::Symbol(Symbol::new("x"), None), deep]), None);
+    }
+    let Value::List(defines, _) =
+        test::read("(define (walk xs) (pmatch? xs ((x rest) (walk rest)) (quote done)))")
+    else {
+        panic!()
+    };
+    let quoted = Value::List(List::from([Value::Symbol(symbol::QUOTE, None), deep]), None);
+    let call = Value::List(List::from([Value::Symbol(Symbol::new("walk"), None), quoted]), None);
+    let program = Value::List(List::from([defines[0].clone(), call]), None);
+    assert!(matches!(test::interpret(program), Ok(Value::Symbol(done, _)) if &*done.name() == "done"));
+}
+
+#[test]
+fn lexical_scoping() {
+    // free variables are looked up where the procedure was defined
+    let value = test::interpret_str(
+        "(define x (quote outer))
+         (define (get-x) x)
+         (define (f x) (get-x))
+         (f (quote inner))",
+    );
+    assert_eq!("outer", value.unwrap().to_string());
+    // arguments are evaluated where the procedure is called
+    let value = test::interpret_str(
+        "(define (make x) (lambda (y) y))
+         (define g (make (quote captured)))
+         (let ((x (quote caller))) (g x))",
+    );
+    assert_eq!("caller", value.unwrap().to_string());
+}
+
+#[test]
+fn macro_expansion_is_evaluated_by_the_caller() {
+    let value = test::interpret_str(
+        "(define-macro (swap args) (pmatch? args ((f a b) (quasiquote ((unquote f) (unquote b) (unquote a)))) #f))
+         (define (first a b) a)
+         (let ((x (quote x)) (y (quote y))) (swap first x y))",
+    );
+    assert_eq!("y", value.unwrap().to_string());
+}
+
+#[test]
+fn countdown_loop() {
+    let value = test::interpret_str(
+        "(define (count n acc) (if? (= n 0) acc (count (- n 1) (+ acc 1))))
+         (count 300000 0)",
+    );
+    assert_eq!("300000", value.unwrap().to_string());
+}
+
+#[test]
+fn quasiquote() {
+    use test::eval;
+    assert_eq!(Some("(a)".to_string()), eval("`(a)"));
+    assert_eq!(Some("(1 (2))".to_string()), eval("(let ((x 1) (y 2)) `(,x (,y)))"));
+    assert_eq!(Some("(0 1 2 3)".to_string()), eval("(let ((xs (list 1 2))) `(0 ,@xs 3))"));
+    assert_eq!(Some("(1)".to_string()), eval("`(,@'() 1)"));
+    assert_eq!(Some("(a `(b ,(c 3)))".to_string()), eval("`(a `(b ,(c ,(+ 1 2))))"));
+    assert!(matches!(test::interpret_str("`(,@1)"), Err(EvalError::Type(_, _))));
+    assert!(matches!(test::interpret_str("`,@(list 1)"), Err(EvalError::BadForm(_, _))));
+}
+
+#[test]
+fn session_keeps_definitions() {
+    let mut session = Session::default();
+    use test::read;
+    let Value::List(forms, _) = read("(define (double x) (* x 2)) (define y 21)") else {
+        panic!()
+    };
+    for form in forms.iter() {
+        assert!(matches!(session.eval(form.clone()), Ok(None)));
+    }
+    let Value::List(forms, _) = read("(double y)") else { panic!() };
+    assert_eq!("42", session.eval(forms[0].clone()).unwrap().unwrap().to_string());
+    assert!(session.eval(Value::Symbol(Symbol::new("z"), None)).is_err());
+}
+
+#[test]
+fn values_outlive_their_source() {
+    let mut session = Session::default();
+    let mut run = |source: String| {
+        let program = test::read(&source);
+        session.run(program, |_| ()).unwrap()
+    };
+    run("(define (tag x) `(tagged ,x))".to_string());
+    let value = run("(tag 'name)".to_string()).unwrap();
+    assert_eq!("(tagged name)", value.to_string());
+    let Some(Value::Procedure(..)) = run("tag".to_string()) else { panic!() };
+}
+
+#[test]
+fn mutation() {
+    use test::eval;
+    assert_eq!(
+        Some("3".to_string()),
+        eval("(define (counter n) (lambda () (let ((_ (set! n (+ n 1)))) n)))
+              (define next (counter 0))
+              (next) (next) (next)")
+    );
+    assert_eq!(
+        Some("(#&2 2)".to_string()),
+        eval("(define b (box 1)) (set-box! b (+ (unbox b) 1)) (list b (unbox b))")
+    );
+    assert!(matches!(test::interpret_str("(set! undefined 1)"), Err(EvalError::Unbound(_, _))));
+    // top level definitions are replaced, so procedures see the new definition
+    assert_eq!(
+        Some("new".to_string()),
+        eval("(define (f) 'old) (define (g) (f)) (define (f) 'new) (g)")
+    );
+}
+
+#[test]
+fn recursive_bindings() {
+    use test::eval;
+    assert_eq!(
+        Some("#t".to_string()),
+        eval("(letrec ((even? (lambda (n) (if? (= n 0) #t (odd? (- n 1)))))
+                       (odd? (lambda (n) (if? (= n 0) #f (even? (- n 1))))))
+                (even? 1000))")
+    );
+    assert_eq!(Some("3".to_string()), eval("(letrec* ((a 1) (b (+ a 2))) b)"));
+    // letrec values cannot use each other before they are all evaluated
+    assert!(matches!(test::interpret_str("(letrec ((a 1) (b (+ a 2))) b)"), Err(EvalError::Unbound(_, _))));
+    assert_eq!(
+        Some("(3 2 1)".to_string()),
+        eval("(let loop ((n 3) (acc '())) (if? (= n 0) (reverse acc) (loop (- n 1) (cons n acc))))")
+    );
+    assert_eq!(Some("100000".to_string()), eval("(let loop ((n 0)) (if? (= n 100000) n (loop (+ n 1))))"));
+    assert_eq!(
+        Some("#f".to_string()),
+        eval("(begin
+                (define (even? n) (if? (= n 0) #t (odd? (- n 1))))
+                (define (odd? n) (if? (= n 0) #f (even? (- n 1))))
+                (even? 7))")
+    );
+    // the definitions of a begin share one frame, where a name defined twice has its last value
+    assert_eq!(Some("(2 2)".to_string()), eval("(begin (define x 1) (define x 2) (list x (eval 'x)))"));
+}
+
@@ -0,0 +1,573 @@
+use super::{
+    env,
+    inbuilt::{arity, function},
+    Env, EvalError, EvalResult, List, Value,
+};
+
+#[derive(Clone, Copy)]
+enum Number {
+    Int(i64),
+    Float(f64),
+}
+
+impl Number {
+    fn float(self) -> f64 {
+        match self {
+            Number::Int(int) => int as f64,
+            Number::Float(float) => float,
+        }
+    }
+}
+
+impl From<Number> for Value {
+    fn from(number: Number) -> Self {
+        match number {
+            Number::Int(int) => Value::Int(int),
+            Number::Float(float) => Value::Float(float),
+        }
+    }
+}
+
+fn number(value: &Value) -> Result<Number, EvalError> {
+    match value {
+        Value::Int(int) => Ok(Number::Int(*int)),
+        Value::Float(float) => Ok(Number::Float(*float)),
+        _ => Err(EvalError::Type(
+            format!("expected a number, got {value}"),
+            value.clone(),
+        )),
+    }
+}
+
+/// the exact integer equal to the integral `float`, which is `value`,
+/// or an error when it is beyond the range of 64 bit integers
+fn exact_integer(float: f64, value: &Value) -> Result<i64, EvalError> {
+    // -(i64::MIN as f64) is 2^63, one past i64::MAX
+    if (i64::MIN as f64..-(i64::MIN as f64)).contains(&float) {
+        Ok(float as i64)
+    } else {
+        Err(EvalError::Arithmetic(
+            format!("{value} is too large for a 64 bit integer"),
+            value.clone(),
+        ))
+    }
+}
+
+fn integer(value: &Value) -> Result<i64, EvalError> {
+    match value {
+        Value::Int(int) => Ok(*int),
+        Value::Float(float) if float.fract() == 0.0 => exact_integer(*float, value),
+        _ => Err(EvalError::Type(
+            format!("expected an integer, got {value}"),
+            value.clone(),
+        )),
+    }
+}
+
+fn overflow(name: &str, args: &[Value]) -> EvalError {
+    EvalError::Arithmetic(
+        format!("integer overflow in \"{name}\""),
+        Value::List(List::from(args), None),
+    )
+}
+
+/// applies `int` when both numbers are exact, and `float` otherwise
+/// `int` returns None on overflow
+fn combine(
+    name: &str,
+    args: &[Value],
+    l: Number,
+    r: Number,
+    int: fn(i64, i64) -> Option<i64>,
+    float: fn(f64, f64) -> f64,
+) -> Result<Number, EvalError> {
+    match (l, r) {
+        (Number::Int(l), Number::Int(r)) => int(l, r)
+            .map(Number::Int)
+            .ok_or_else(|| overflow(name, args)),
+        (l, r) => Ok(Number::Float(float(l.float(), r.float()))),
+    }
+}
+
+fn fold(
+    name: &'static str,
+    identity: i64,
+    int: fn(i64, i64) -> Option<i64>,
+    float: fn(f64, f64) -> f64,
+) -> impl Fn(&[Value]) -> EvalResult {
+    move |args| {
+        args.iter()
+            .try_fold(Number::Int(identity), |acc, arg| {
+                combine(name, args, acc, number(arg)?, int, float)
+            })
+            .map(Value::from)
+    }
+}
+
+/// `-` and `/` negate or invert a single argument,
+/// and otherwise apply to each argument in turn from the first
+fn fold_from_first(
+    name: &'static str,
+    identity: i64,
+    int: fn(i64, i64) -> Option<i64>,
+    float: fn(f64, f64) -> f64,
+) -> impl Fn(&[Value]) -> EvalResult {
+    move |args| match args {
+        [] => Err(arity(name, "at least 1 argument", args)),
+        [x] => combine(name, args, Number::Int(identity), number(x)?, int, float).map(Value::from),
+        [x, xs @ ..] => xs
+            .iter()
+            .try_fold(number(x)?, |acc, arg| {
+                combine(name, args, acc, number(arg)?, int, float)
+            })
+            .map(Value::from),
+    }
+}
+
+fn divide(args: &[Value]) -> EvalResult {
+    let div = |l: Number, r: Number| match (l, r) {
+        (Number::Int(_), Number::Int(0)) => Err(EvalError::Arithmetic(
+            "division by zero".to_string(),
+            Value::List(List::from(args), None),
+        )),
+        (Number::Int(l), Number::Int(r)) if l.checked_rem(r) == Some(0) => l
+            .checked_div(r)
+            .map(Number::Int)
+            .ok_or_else(|| overflow("/", args)),
+        (l, r) => Ok(Number::Float(l.float() / r.float())),
+    };
+    match args {
+        [] => Err(arity("/", "at least 1 argument", args)),
+        [x] => div(Number::Int(1), number(x)?).map(Value::from),
+        [x, xs @ ..] => xs
+            .iter()
+            .try_fold(number(x)?, |acc, arg| div(acc, number(arg)?))
+            .map(Value::from),
+    }
+}
+
+/// the result is inexact when either argument is
+fn integer_division(
+    name: &'static str,
+    op: fn(i64, i64) -> Option<i64>,
+) -> impl Fn(&[Value]) -> EvalResult {
+    move |args| match args {
+        [l, r] => match integer(r)? {
+            0 => Err(EvalError::Arithmetic(
+                "division by zero".to_string(),
+                Value::List(List::from(args), None),
+            )),
+            r => {
+                let result = op(integer(l)?, r).ok_or_else(|| overflow(name, args))?;
+                Ok(if args.iter().any(|x| matches!(x, Value::Float(_))) {
+                    Value::Float(result as f64)
+                } else {
+                    Value::Int(result)
+                })
+            }
+        },
+        _ => Err(arity(name, "2 arguments", args)),
+    }
+}
+
+/// tests that every adjacent pair of arguments is ordered by `test`
+fn compare(
+    name: &'static str,
+    test: fn(&f64, &f64) -> bool,
+    exact: fn(&i64, &i64) -> bool,
+) -> impl Fn(&[Value]) -> EvalResult {
+    move |args| {
+        if args.is_empty() {
+            return Err(arity(name, "at least 1 argument", args));
+        }
+        let numbers = args.iter().map(number).collect::<Result<Vec<_>, _>>()?;
+        Ok(Value::Bool(numbers.windows(2).all(|pair| match pair {
+            [Number::Int(l), Number::Int(r)] => exact(l, r),
+            [l, r] => test(&l.float(), &r.float()),
+            _ => unreachable!(),
+        })))
+    }
+}
+
+/// applies `int` to exact numbers and `float` to inexact ones
+fn unary(
+    name: &'static str,
+    int: fn(i64) -> Option<Number>,
+    float: fn(f64) -> Number,
+) -> impl Fn(&[Value]) -> EvalResult {
+    move |args| match args {
+        [x] => match number(x)? {
+            Number::Int(x) => int(x).map(Value::from).ok_or_else(|| overflow(name, args)),
+            Number::Float(x) => Ok(float(x).into()),
+        },
+        _ => Err(arity(name, "1 argument", args)),
+    }
+}
+
+fn predicate(name: &'static str, test: fn(Number) -> bool) -> impl Fn(&[Value]) -> EvalResult {
+    move |args| match args {
+        [x] => Ok(Value::Bool(test(number(x)?))),
+        _ => Err(arity(name, "1 argument", args)),
+    }
+}
+
+fn extremum(name: &'static str, pick: fn(f64, f64) -> bool) -> impl Fn(&[Value]) -> EvalResult {
+    move |args| {
+        let numbers = args.iter().map(number).collect::<Result<Vec<_>, _>>()?;
+        let inexact = numbers.iter().any(|n| matches!(n, Number::Float(_)));
+        let best = numbers
+            .into_iter()
+            .reduce(|best, n| {
+                if pick(n.float(), best.float()) {
+                    n
+                } else {
+                    best
+                }
+            })
+            .ok_or_else(|| arity(name, "at least 1 argument", args))?;
+        Ok(match (best, inexact) {
+            (Number::Int(int), true) => Value::Float(int as f64),
+            (best, _) => best.into(),
+        })
+    }
+}
+
+fn sqrt(args: &[Value]) -> EvalResult {
+    match args {
+        [x] => match number(x)? {
+            Number::Int(int) if int >= 0 => {
+                let root = (int as f64).sqrt().round() as i64;
+                if root.checked_mul(root) == Some(int) {
+                    Ok(Value::Int(root))
+                } else {
+                    Ok(Value::Float((int as f64).sqrt()))
+                }
+            }
+            x => Ok(Value::Float(x.float().sqrt())),
+        },
+        _ => Err(arity("sqrt", "1 argument", args)),
+    }
+}
+
+fn expt(args: &[Value]) -> EvalResult {
+    match args {
+        [base, power] => match (number(base)?, number(power)?) {
+            (Number::Int(base), Number::Int(power)) if power >= 0 => u32::try_from(power)
+                .ok()
+                .and_then(|power| base.checked_pow(power))
+                .map(Value::Int)
+                .ok_or_else(|| overflow("expt", args)),
+            (base, power) => Ok(Value::Float(base.float().powf(power.float()))),
+        },
+        _ => Err(arity("expt", "2 arguments", args)),
+    }
+}
+
+fn exact(args: &[Value]) -> EvalResult {
+    match args {
+        [x] => match number(x)? {
+            Number::Float(float) if float.fract() != 0.0 || !float.is_finite() => Err(
+                EvalError::Arithmetic(format!("{x} has no exact representation"), x.clone()),
+            ),
+            Number::Float(float) => exact_integer(float, x).map(Value::Int),
+            Number::Int(int) => Ok(Value::Int(int)),
+        },
+        _ => Err(arity("exact", "1 argument", args)),
+    }
+}
+
+fn inexact(args: &[Value]) -> EvalResult {
+    match args {
+        [x] => Ok(Value::Float(number(x)?.float())),
+        _ => Err(arity("inexact", "1 argument", args)),
+    }
+}
+
+fn type_test(name: &'static str, test: fn(&Value) -> bool) -> impl Fn(&[Value]) -> EvalResult {
+    move |args| match args {
+        [x] => Ok(Value::Bool(test(x))),
+        _ => Err(arity(name, "1 argument", args)),
+    }
+}
+
+fn parity(name: &'static str, even: bool) -> impl Fn(&[Value]) -> EvalResult {
+    move |args| match args {
+        [x] => Ok(Value::Bool((integer(x)? % 2 == 0) == even)),
+        _ => Err(arity(name, "1 argument", args)),
+    }
+}
+
+fn modulo(l: i64, r: i64) -> Option<i64> {
+    let rem = l.checked_rem(r)?;
+    Some(if rem != 0 && (rem < 0) != (r < 0) {
+        rem + r
+    } else {
+        rem
+    })
+}
+
+pub fn bindings(env: Env) -> Vec<env::Value<&'static str, Value>> {
+    let int = |x| Some(Number::Int(x));
+    vec![
+        env::Value(
+            "+",
+            function(
+                env.clone(),
+                "number...",
+                fold("+", 0, i64::checked_add, |l, r| l + r),
+            ),
+        ),
+        env::Value(
+            "*",
+            function(
+                env.clone(),
+                "number...",
+                fold("*", 1, i64::checked_mul, |l, r| l * r),
+            ),
+        ),
+        env::Value(
+            "-",
+            function(
+                env.clone(),
+                "number number...",
+                fold_from_first("-", 0, i64::checked_sub, |l, r| l - r),
+            ),
+        ),
+        env::Value("/", function(env.clone(), "number number...", divide)),
+        env::Value(
+            "quotient",
+            function(
+                env.clone(),
+                "integer integer",
+                integer_division("quotient", i64::checked_div),
+            ),
+        ),
+        env::Value(
+            "remainder",
+            function(
+                env.clone(),
+                "integer integer",
+                integer_division("remainder", i64::checked_rem),
+            ),
+        ),
+        env::Value(
+            "modulo",
+            function(
+                env.clone(),
+                "integer integer",
+                integer_division("modulo", modulo),
+            ),
+        ),
+        env::Value(
+            "=",
+            function(
+                env.clone(),
+                "number number...",
+                compare("=", f64::eq, i64::eq),
+            ),
+        ),
+        env::Value(
+            "<",
+            function(
+                env.clone(),
+                "number number...",
+                compare("<", f64::lt, i64::lt),
+            ),
+        ),
+        env::Value(
+            "<=",
+            function(
+                env.clone(),
+                "number number...",
+                compare("<=", f64::le, i64::le),
+            ),
+        ),
+        env::Value(
+            ">",
+            function(
+                env.clone(),
+                "number number...",
+                compare(">", f64::gt, i64::gt),
+            ),
+        ),
+        env::Value(
+            ">=",
+            function(
+                env.clone(),
+                "number number...",
+                compare(">=", f64::ge, i64::ge),
+            ),
+        ),
+        env::Value(
+            "min",
+            function(
+                env.clone(),
+                "number number...",
+                extremum("min", |n, best| n < best),
+            ),
+        ),
+        env::Value(
+            "max",
+            function(
+                env.clone(),
+                "number number...",
+                extremum("max", |n, best| n > best),
+            ),
+        ),
+        env::Value(
+            "abs",
+            function(
+                env.clone(),
+                "number",
+                unary(
+                    "abs",
+                    |x| x.checked_abs().map(Number::Int),
+                    |x| Number::Float(x.abs()),
+                ),
+            ),
+        ),
+        env::Value(
+            "floor",
+            function(
+                env.clone(),
+                "number",
+                unary("floor", int, |x| Number::Float(x.floor())),
+            ),
+        ),
+        env::Value(
+            "ceiling",
+            function(
+                env.clone(),
+                "number",
+                unary("ceiling", int, |x| Number::Float(x.ceil())),
+            ),
+        ),
+        env::Value(
+            "round",
+            function(
+                env.clone(),
+                "number",
+                unary("round", int, |x| Number::Float(x.round_ties_even())),
+            ),
+        ),
+        env::Value(
+            "truncate",
+            function(
+                env.clone(),
+                "number",
+                unary("truncate", int, |x| Number::Float(x.trunc())),
+            ),
+        ),
+        env::Value("sqrt", function(env.clone(), "number", sqrt)),
+        env::Value("expt", function(env.clone(), "base power", expt)),
+        env::Value("exact", function(env.clone(), "number", exact)),
+        env::Value("inexact", function(env.clone(), "number", inexact)),
+        env::Value(
+            "number?",
+            function(
+                env.clone(),
+                "value",
+                type_test("number?", |x| matches!(x, Value::Int(_) | Value::Float(_))),
+            ),
+        ),
+        env::Value(
+            "integer?",
+            function(
+                env.clone(),
+                "value",
+                type_test("integer?", |x| match x {
+                    Value::Int(_) => true,
+                    Value::Float(float) => float.fract() == 0.0,
+                    _ => false,
+                }),
+            ),
+        ),
+        env::Value(
+            "exact?",
+            function(
+                env.clone(),
+                "number",
+                predicate("exact?", |x| matches!(x, Number::Int(_))),
+            ),
+        ),
+        env::Value(
+            "zero?",
+            function(
+                env.clone(),
+                "number",
+                predicate("zero?", |x| x.float() == 0.0),
+            ),
+        ),
+        env::Value(
+            "positive?",
+            function(
+                env.clone(),
+                "number",
+                predicate("positive?", |x| x.float() > 0.0),
+            ),
+        ),
+        env::Value(
+            "negative?",
+            function(
+                env.clone(),
+                "number",
+                predicate("negative?", |x| x.float() < 0.0),
+            ),
+        ),
+        env::Value(
+            "even?",
+            function(env.clone(), "integer", parity("even?", true)),
+        ),
+        env::Value("odd?", function(env, "integer", parity("odd?", false))),
+    ]
+}
+
+#[test]
+fn arithmetic_test() {
+    use super::test::{eval, interpret_str};
+    assert_eq!(Some("10".to_string()), eval("(+ 1 2 3 4)"));
+    assert_eq!(Some("2.5".to_string()), eval("(+ 1 1.5)"));
+    assert_eq!(Some("-3".to_string()), eval("(- 3)"));
+    assert_eq!(Some("2".to_string()), eval("(/ 6 3)"));
+    assert_eq!(Some("0.5".to_string()), eval("(/ 1 2)"));
+    assert_eq!(Some("-1".to_string()), eval("(remainder -7 2)"));
+    assert_eq!(Some("1".to_string()), eval("(modulo -7 2)"));
+    assert_eq!(Some("#t".to_string()), eval("(< 1 2 3.5)"));
+    assert_eq!(Some("#f".to_string()), eval("(= 1 1 2)"));
+    assert_eq!(Some("3".to_string()), eval("(sqrt 9)"));
+    assert_eq!(Some("1024".to_string()), eval("(expt 2 10)"));
+    assert_eq!(Some("2.0".to_string()), eval("(max 1 2 1.5)"));
+    assert!(matches!(interpret_str("(/ 1 0)"), Err(EvalError::Arithmetic(_, _))));
+    assert!(matches!(interpret_str("(+ 1 (quote a))"), Err(EvalError::Type(_, _))));
+    assert!(matches!(
+        interpret_str("(* 9223372036854775807 2)"),
+        Err(EvalError::Arithmetic(_, _))
+    ));
+}
+
+#[test]
+fn inexact_integer_test() {
+    use super::test::{eval, interpret_str};
+    assert_eq!(Some("3.0".to_string()), eval("(quotient 7.0 2)"));
+    assert_eq!(Some("-1.0".to_string()), eval("(remainder -7 2.0)"));
+    assert_eq!(Some("1.0".to_string()), eval("(modulo -7.0 2)"));
+    assert_eq!(Some("3".to_string()), eval("(quotient 7 2)"));
+    assert_eq!(Some("4".to_string()), eval("(exact 4.0)"));
+    assert_eq!(Some("#t".to_string()), eval("(even? 4.0)"));
+    assert_eq!(
+        Some("-9223372036854775808".to_string()),
+        eval("(exact -9223372036854775808.0)")
+    );
+    for source in [
+        "(exact 1e30)",
+        "(exact 9223372036854775808.0)",
+        "(even? 1e30)",
+        "(odd? -1e19)",
+        "(quotient 1e30 2)",
+    ] {
+        assert!(
+            matches!(interpret_str(source), Err(EvalError::Arithmetic(_, _))),
+            "{source}"
+        );
+    }
+}
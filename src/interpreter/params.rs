@@ -0,0 +1,348 @@
+use core::fmt::Display;
+use std::rc::Rc;
+
+use super::{
+    cps::evaluate_all,
+    env, eval, limits,
+    symbol::{self, Symbol},
+    DisplayList, Env, EvalError, List, TailResult, Value,
+};
+
+/// a parameter which can be left out of a call,
+/// and the expression for its value when it is. without one, the value is `#f`
+type Optional = (Symbol, Option<Value>);
+
+/// the parameters of a procedure,
+/// `(required... [#!optional optional...] [#!key key...] [#!rest rest])`
+/// where `. rest` can be written instead of `#!rest rest`,
+/// and optional and key parameters are either `name` or `(name default)`
+pub struct Params {
+    required: Vec<Symbol>,
+    optional: Vec<Optional>,
+    keys: Vec<Optional>,
+    rest: Option<Symbol>,
+    /// the parameters as they were written
+    list: Rc<[Value]>,
+}
+
+impl Display for Params {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "{}", DisplayList(self.list.clone()))
+    }
+}
+
+enum Section {
+    Required,
+    Optional,
+    Key,
+    Rest,
+}
+
+fn optional(param: &Value) -> Result<Optional, EvalError> {
+    match param {
+        Value::Symbol(name, _) => Ok((*name, None)),
+        Value::List(parts, _) => match parts.as_ref() {
+            [Value::Symbol(name, _), default] => Ok((*name, Some(default.clone()))),
+            _ => Err(EvalError::BadForm(
+                "invalid parameter, expected a symbol or (name default)".to_string(),
+                param.clone(),
+            )),
+        },
+        _ => Err(EvalError::BadForm(
+            "invalid parameter, expected a symbol or (name default)".to_string(),
+            param.clone(),
+        )),
+    }
+}
+
+/// adds `name`, bound by `binding`, to the names `bound` by one form,
+/// failing if it is already one of them. `_` can be bound any number of times
+pub fn bind_once(bound: &mut Vec<Symbol>, name: Symbol, binding: &Value) -> Result<(), EvalError> {
+    if name != symbol::WILDCARD && bound.contains(&name) {
+        return Err(EvalError::BadForm(
+            format!("{name} is bound more than once"),
+            binding.clone(),
+        ));
+    }
+    bound.push(name);
+    Ok(())
+}
+
+impl Params {
+    pub fn parse(params: &[Value]) -> Result<Self, EvalError> {
+        let mut parsed = Params {
+            required: Vec::new(),
+            optional: Vec::new(),
+            keys: Vec::new(),
+            rest: None,
+            list: Rc::from(params),
+        };
+        let mut section = Section::Required;
+        let mut bound = Vec::with_capacity(params.len());
+        for param in params {
+            match (param, &section) {
+                (Value::Symbol(symbol::OPTIONAL, _), Section::Required) => section = Section::Optional,
+                (Value::Symbol(symbol::KEY, _), Section::Required | Section::Optional) => {
+                    section = Section::Key
+                }
+                (Value::Symbol(symbol::REST | symbol::DOT, _), Section::Rest) => {
+                    return Err(EvalError::BadForm(
+                        "only one rest parameter is allowed".to_string(),
+                        param.clone(),
+                    ))
+                }
+                (Value::Symbol(symbol::REST | symbol::DOT, _), _) => section = Section::Rest,
+                (Value::Symbol(marker @ (symbol::OPTIONAL | symbol::KEY), _), _) => {
+                    return Err(EvalError::BadForm(
+                        format!("{marker} is out of place, parameters are ordered required, #!optional, #!key, then #!rest"),
+                        param.clone(),
+                    ))
+                }
+                (Value::Symbol(name, _), Section::Required) => {
+                    bind_once(&mut bound, *name, param)?;
+                    parsed.required.push(*name)
+                }
+                (_, Section::Optional | Section::Key) => {
+                    let optional = optional(param)?;
+                    bind_once(&mut bound, optional.0, param)?;
+                    match section {
+                        Section::Optional => parsed.optional.push(optional),
+                        _ => parsed.keys.push(optional),
+                    }
+                }
+                (Value::Symbol(name, _), Section::Rest) if parsed.rest.is_none() => {
+                    bind_once(&mut bound, *name, param)?;
+                    parsed.rest = Some(*name)
+                }
+                (_, Section::Rest) => {
+                    return Err(EvalError::BadForm(
+                        "expected a single rest parameter".to_string(),
+                        param.clone(),
+                    ))
+                }
+                _ => {
+                    return Err(EvalError::BadForm(
+                        "invalid binding, expected a symbol".to_string(),
+                        param.clone(),
+                    ))
+                }
+            }
+        }
+        match (section, parsed.rest) {
+            (Section::Rest, None) => Err(EvalError::BadForm(
+                "expected a rest parameter".to_string(),
+                Value::List(List::from(&*parsed.list), None),
+            )),
+            _ => Ok(parsed),
+        }
+    }
+
+    /// evaluates `args` in `caller`, then continues with them bound to the parameters
+    /// in a frame on top of `env`
+    pub fn bind(
+        self: &Rc<Self>,
+        env: &Env,
+        caller: &Env,
+        args: &[Value],
+        then: impl Fn(Env) -> TailResult + Clone + 'static,
+    ) -> TailResult {
+        // without keywords, the number of arguments is known before evaluating them,
+        // otherwise the arguments are kept for the errors of keywords
+        let exprs = match self.keys.is_empty() {
+            true => {
+                self.arity(args.len(), args)?;
+                None
+            }
+            false => Some(Rc::<[Value]>::from(args)),
+        };
+        let (params, env) = (self.clone(), env.clone());
+        evaluate_all(caller, args, move |values| {
+            let args = exprs.as_deref().unwrap_or(&[]);
+            then(params.bind_values(&env, values, args)?)
+        })
+    }
+
+    /// binds the values of `args` to the parameters in a frame on top of `env`
+    fn bind_values(
+        &self,
+        env: &Env,
+        mut values: Vec<Value>,
+        args: &[Value],
+    ) -> Result<Env, EvalError> {
+        let keywords = match self.keys.is_empty() {
+            true => Vec::new(),
+            false => self.keywords(&mut values, args)?,
+        };
+        self.arity(values.len(), args)?;
+
+        let mut positional = values.into_iter();
+        let mut bound = Vec::with_capacity(self.list.len());
+        for name in &self.required {
+            let value = positional.next().expect("arity was checked");
+            bound.push(env::Value(*name, value));
+        }
+        for (name, default) in &self.optional {
+            let value = match positional.next() {
+                Some(value) => value,
+                None => default_value(env, &bound, default)?,
+            };
+            bound.push(env::Value(*name, value));
+        }
+        for (name, default) in &self.keys {
+            let value = match keywords.iter().find(|(keyword, _)| keyword == name) {
+                Some((_, value)) => value.clone(),
+                None => default_value(env, &bound, default)?,
+            };
+            bound.push(env::Value(*name, value));
+        }
+        if let Some(rest) = self.rest {
+            let rest_args: Vec<_> = positional.collect();
+            limits::allocate(rest_args.len())?;
+            bound.push(env::Value(rest, Value::List(List::from(rest_args), None)));
+        }
+        Ok(env.bind(env::Values::new(bound)))
+    }
+
+    /// the names of the parameters, in the order of their slots in the frame they are bound in
+    pub fn names(&self) -> impl Iterator<Item = Symbol> + '_ {
+        let optional = self.optional.iter().chain(&self.keys).map(|(name, _)| *name);
+        self.required.iter().copied().chain(optional).chain(self.rest)
+    }
+
+    /// the required parameters and the rest parameter,
+    /// unless there are optional or key parameters
+    pub fn positional(&self) -> Option<(&[Symbol], Option<Symbol>)> {
+        match self.optional.is_empty() && self.keys.is_empty() {
+            true => Some((&self.required, self.rest)),
+            false => None,
+        }
+    }
+
+    /// checks that `count` arguments can be bound to the parameters
+    pub fn arity(&self, count: usize, args: &[Value]) -> Result<(), EvalError> {
+        let min = self.required.len();
+        let max = self.rest.is_none().then_some(min + self.optional.len());
+        if count >= min && max.is_none_or(|max| count <= max) {
+            return Ok(());
+        }
+        let plural = if max.unwrap_or(min) == 1 { "" } else { "s" };
+        let expected = match max {
+            Some(max) if max == min => format!("{min} argument{plural}"),
+            Some(max) => format!("{min} to {max} argument{plural}"),
+            None => format!("at least {min} argument{plural}"),
+        };
+        Err(EvalError::Arity(
+            format!("expected {expected} {self}, got {count}"),
+            Value::List(List::from(args), None),
+        ))
+    }
+
+    /// takes each `#:name value` pair out of `values`, leaving the positional arguments
+    fn keywords(
+        &self,
+        values: &mut Vec<Value>,
+        args: &[Value],
+    ) -> Result<Vec<(Symbol, Value)>, EvalError> {
+        let mut keywords = Vec::new();
+        let mut positional = Vec::with_capacity(values.len());
+        let mut values_iter = std::mem::take(values).into_iter();
+        while let Some(value) = values_iter.next() {
+            let Some(keyword) = keyword(&value) else {
+                positional.push(value);
+                continue;
+            };
+            let Some((name, _)) = self.keys.iter().find(|(name, _)| *name.name() == *keyword) else {
+                return Err(EvalError::Arity(
+                    format!("unknown keyword {value}, expected one of {self}"),
+                    Value::List(List::from(args), None),
+                ));
+            };
+            match values_iter.next() {
+                Some(arg) => keywords.push((*name, arg)),
+                None => {
+                    return Err(EvalError::Arity(
+                        format!("missing a value for keyword {value}"),
+                        Value::List(List::from(args), None),
+                    ))
+                }
+            }
+        }
+        *values = positional;
+        Ok(keywords)
+    }
+}
+
+/// keywords are symbols written `#:name`, which evaluate to themselves
+pub fn keyword(value: &Value) -> Option<Rc<str>> {
+    match value {
+        Value::Symbol(symbol, _) => symbol.name().strip_prefix("#:").map(Rc::from),
+        _ => None,
+    }
+}
+
+/// the default of a parameter, evaluated where the procedure was defined
+/// with the parameters before it bound
+fn default_value(
+    env: &Env,
+    bound: &[env::Value<Symbol, Value>],
+    default: &Option<Value>,
+) -> Result<Value, EvalError> {
+    match default {
+        Some(default) => {
+            let bound = bound
+                .iter()
+                .map(|env::Value(name, value)| env::Value(*name, value.clone()));
+            eval(env.bind(env::Values::new(bound)), default.clone())
+        }
+        None => Ok(Value::Bool(false)),
+    }
+}
+
+#[test]
+fn parameters_test() {
+    use super::test::{eval, interpret_str};
+    assert_eq!(Some("(1 2 3)".to_string()), eval("((lambda args args) 1 2 3)"));
+    assert_eq!(Some("(1 (2 3))".to_string()), eval("((lambda (a . rest) (list a rest)) 1 2 3)"));
+    assert_eq!(Some("()".to_string()), eval("((lambda (a #!rest rest) rest) 1)"));
+    assert_eq!(
+        Some("((1 2) (1 5))".to_string()),
+        eval("(begin
+                (define (f a #!optional (b (+ a 1))) (list a b))
+                (list (f 1) (f 1 5)))")
+    );
+    assert_eq!(Some("#f".to_string()), eval("((lambda (#!optional a) a))"));
+    assert_eq!(
+        Some("(1 (2 3) 20)".to_string()),
+        eval("(begin
+                (define (g a #!key (b 10) (c (* b 2)) . rest) (list a rest c))
+                (g 1 #:b 10 2 3))")
+    );
+    assert_eq!(Some("#:b".to_string()), eval("#:b"));
+    for source in [
+        "((lambda (a b) a) 1)",
+        "((lambda (a #!optional b) a) 1 2 3)",
+        "((lambda (a . rest) a))",
+        "((lambda (#!key a) a) #:b 1)",
+        "((lambda (#!key a) a) #:a)",
+    ] {
+        assert!(matches!(interpret_str(source), Err(EvalError::Arity(_, _))), "{source}");
+    }
+    assert!(matches!(interpret_str("(lambda (a . ) a)"), Err(EvalError::BadForm(_, _))));
+    assert!(matches!(interpret_str("(lambda (#!key a #!optional b) a)"), Err(EvalError::BadForm(_, _))));
+    // a name can only be bound once by each form, and the error points at its second binding
+    for source in [
+        "(define (f a a) a)",
+        "(lambda (a #!optional (b 1) #!key a) a)",
+        "(lambda (a . a) a)",
+        "(let ((x 1) (x 2)) x)",
+        "(let loop ((i 0) (i 1)) i)",
+        "(letrec* ((f 1) (f 2)) f)",
+    ] {
+        let Err(EvalError::BadForm(_, Value::Symbol(_, Some(span)))) = interpret_str(source) else {
+            panic!("expected a name bound twice to be a bad form in {source}")
+        };
+        let name = span.view().as_str();
+        assert!(source[..span.view().start].contains(&format!("{name} ")), "{source}");
+    }
+    assert_eq!(Some("2".to_string()), eval("(let ((_ 1) (_ 2) (x 2)) x)"));
+}
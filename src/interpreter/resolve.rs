@@ -0,0 +1,494 @@
+//! the resolution pass of the tree-walker, run on each top level form once its macros are
+//! expanded. each name an expression evaluates is resolved to where it is bound, see
+//! [Address]: a local to its slot in the frame a form around it binds, and any other name
+//! to the global frame, so that evaluating it goes straight to its binding rather than
+//! comparing it with the names of each frame around it. the frames are the ones the forms
+//! bind as they are evaluated, see [super::inbuilt]. what the pass does not understand,
+//! such as a call of a value rather than of a name, is left to be looked up by name
+
+use std::rc::Rc;
+
+use super::{
+    env::Address,
+    params::{self, Params},
+    symbol::{self, Symbol},
+    List, Value,
+};
+use crate::ast::Span;
+
+/// resolves the names of a top level form, which is evaluated in the global frame
+pub fn resolve(form: Value) -> Value {
+    let scope = Scope::default();
+    match &form {
+        Value::List(items, span)
+            if matches!(
+                items.first(),
+                Some(Value::Symbol(
+                    symbol::DEFINE | symbol::DEFINE_MACRO | symbol::DEFINE_SYNTAX,
+                    _
+                ))
+            ) =>
+        {
+            match scope.definition(items) {
+                Some(items) => Value::List(List::from(items), span.clone()),
+                None => form.clone(),
+            }
+        }
+        _ => scope.expr(&form),
+    }
+}
+
+/// `expr` with its resolved names written as names again, for where a form is handed on
+/// as a value, such as to a macro called while evaluating
+pub fn unresolve(expr: &Value) -> Value {
+    unresolved(expr).unwrap_or_else(|| expr.clone())
+}
+
+/// [unresolve], or `None` when there are no resolved names in `expr`
+fn unresolved(expr: &Value) -> Option<Value> {
+    match expr {
+        Value::Resolved(name, _, span) => Some(Value::Symbol(*name, span.clone())),
+        Value::List(items, span) => {
+            let changed: Vec<_> = items.iter().map(unresolved).collect();
+            if changed.iter().all(Option::is_none) {
+                return None;
+            }
+            let items = items
+                .iter()
+                .zip(changed)
+                .map(|(item, changed)| changed.unwrap_or_else(|| item.clone()));
+            Some(Value::List(items.collect(), span.clone()))
+        }
+        _ => None,
+    }
+}
+
+/// the names of a frame which a form binds
+#[derive(Clone)]
+struct Frame {
+    names: Rc<[Symbol]>,
+    /// whether a name bound twice refers to its last slot, as in an [super::env::Frame],
+    /// rather than its first, as in [super::env::Values]
+    last: bool,
+}
+
+/// the frames the forms around an expression bind, innermost last
+#[derive(Clone, Default)]
+struct Scope(Vec<Frame>);
+
+impl Scope {
+    fn with(&self, names: impl IntoIterator<Item = Symbol>, last: bool) -> Self {
+        let mut scope = self.clone();
+        scope.0.push(Frame {
+            names: names.into_iter().collect(),
+            last,
+        });
+        scope
+    }
+
+    fn address(&self, name: Symbol) -> Address {
+        self.0
+            .iter()
+            .rev()
+            .enumerate()
+            .find_map(|(depth, frame)| {
+                let mut names = frame.names.iter();
+                let index = match frame.last {
+                    true => names.rposition(|bound| *bound == name),
+                    false => names.position(|bound| *bound == name),
+                };
+                Some(Address::Local {
+                    depth,
+                    index: index?,
+                })
+            })
+            .unwrap_or(Address::Global)
+    }
+
+    fn name(&self, name: Symbol, span: &Span) -> Value {
+        Value::Resolved(name, self.address(name), span.clone())
+    }
+
+    fn expr(&self, expr: &Value) -> Value {
+        match expr {
+            Value::Symbol(_, _) if params::keyword(expr).is_some() => expr.clone(),
+            Value::Symbol(name, span) => self.name(*name, span),
+            Value::List(items, span) => match self.form(items) {
+                Some(items) => Value::List(List::from(items), span.clone()),
+                None => expr.clone(),
+            },
+            _ => expr.clone(),
+        }
+    }
+
+    /// a form with the names it evaluates resolved, or `None` to leave it as it is
+    fn form(&self, items: &[Value]) -> Option<Vec<Value>> {
+        let (first, args) = items.split_first()?;
+        let form = match first {
+            Value::Symbol(name, _) => match self.address(*name) {
+                Address::Global => Some(*name),
+                Address::Local { .. } => None,
+            },
+            Value::List(_, _) => None,
+            // a value in the call itself, such as the binder of an expansion's renames,
+            // may not evaluate its arguments
+            _ => return None,
+        };
+        let mut resolved = vec![self.expr(first)];
+        match (form, args) {
+            (
+                Some(
+                    symbol::QUOTE
+                    | symbol::SYNTAX_RULES
+                    | symbol::DEFINE
+                    | symbol::DEFINE_MACRO
+                    | symbol::DEFINE_SYNTAX,
+                ),
+                _,
+            ) => resolved.extend(args.iter().cloned()),
+            (Some(symbol::QUASIQUOTE), [template]) => resolved.push(self.template(template, 0)),
+            (Some(symbol::LAMBDA), [params @ Value::List(parts, _), body]) => {
+                let names = Params::parse(parts).ok()?;
+                resolved.extend([params.clone(), self.with(names.names(), false).expr(body)]);
+            }
+            (Some(symbol::LAMBDA | symbol::MACRO), [name @ Value::Symbol(bound, _), body]) => {
+                resolved.extend([name.clone(), self.with([*bound], false).expr(body)]);
+            }
+            (Some(symbol::LET | symbol::LET_SYNTAX), [Value::List(bindings, span), body]) => {
+                let names = binding_names(bindings)?;
+                resolved.extend([
+                    self.bindings(bindings, span),
+                    self.with(names, false).expr(body),
+                ]);
+            }
+            // the procedure of a named let is bound in a frame of its own,
+            // which the frame of its arguments is bound on top of
+            (
+                Some(symbol::LET | symbol::LET_SYNTAX),
+                [name @ Value::Symbol(procedure, _), Value::List(bindings, span), body],
+            ) => {
+                let names = binding_names(bindings)?;
+                let scope = self.with([*procedure], true).with(names, false);
+                resolved.extend([
+                    name.clone(),
+                    self.bindings(bindings, span),
+                    scope.expr(body),
+                ]);
+            }
+            (
+                Some(symbol::LETREC | symbol::LETREC_STAR | symbol::LETREC_SYNTAX),
+                [Value::List(bindings, span), body],
+            ) => {
+                let scope = self.with(binding_names(bindings)?, true);
+                resolved.extend([scope.bindings(bindings, span), scope.expr(body)]);
+            }
+            (Some(symbol::BEGIN), [defines @ .., body]) => {
+                let names = defines
+                    .iter()
+                    .map(defined_name)
+                    .collect::<Option<Vec<_>>>()?;
+                let scope = self.with(names, true);
+                for define in defines {
+                    let Value::List(define, span) = define else {
+                        unreachable!("the definitions were matched")
+                    };
+                    let define = scope.definition(define)?;
+                    resolved.push(Value::List(List::from(define), span.clone()));
+                }
+                resolved.push(scope.expr(body));
+            }
+            (Some(symbol::SET), [Value::Symbol(name, span), value]) => {
+                resolved.extend([self.name(*name, span), self.expr(value)]);
+            }
+            (Some(symbol::GUARD), [branches @ .., fail]) => {
+                for branch in branches {
+                    resolved.push(self.clause(branch, |_| true)?);
+                }
+                resolved.push(self.expr(fail));
+            }
+            (Some(symbol::EXCEPTION_GUARD), [Value::List(spec, span), body]) => {
+                let [name @ Value::Symbol(bound, _), clauses @ ..] = spec.as_ref() else {
+                    return None;
+                };
+                let scope = self.with([*bound], false);
+                let mut spec = vec![name.clone()];
+                for clause in clauses {
+                    // `else` is matched as it is written
+                    let cond = |cond: &Value| !matches!(cond, Value::Symbol(symbol::ELSE, _));
+                    spec.push(scope.clause(clause, cond)?);
+                }
+                resolved.extend([Value::List(List::from(spec), span.clone()), self.expr(body)]);
+            }
+            (Some(symbol::HANDLE), [body, clauses @ ..]) => {
+                resolved.push(self.expr(body));
+                for clause in clauses {
+                    let Value::List(parts, span) = clause else {
+                        return None;
+                    };
+                    let [name @ Value::Symbol(_, _), bindings @ Value::List(names, _), body] =
+                        parts.as_ref()
+                    else {
+                        return None;
+                    };
+                    let [Value::Symbol(value, _), Value::Symbol(resume, _)] = names.as_ref() else {
+                        return None;
+                    };
+                    let body = self.with([*value, *resume], false).expr(body);
+                    let clause = List::from([name.clone(), bindings.clone(), body]);
+                    resolved.push(Value::List(clause, span.clone()));
+                }
+            }
+            (Some(symbol::PMATCH), [value, branches @ .., fail]) => {
+                resolved.push(self.expr(value));
+                for branch in branches {
+                    let Value::List(parts, span) = branch else {
+                        return None;
+                    };
+                    let (structure, rest @ ([_] | [_, _])) = parts.split_first()? else {
+                        return None;
+                    };
+                    // the names are bound latest first, see [super::inbuilt::pmatch]
+                    let mut names = Vec::new();
+                    structure_names(structure, &mut names);
+                    let scope = self.with(names.into_iter().rev(), false);
+                    let parts = core::iter::once(structure.clone())
+                        .chain(rest.iter().map(|part| scope.expr(part)));
+                    resolved.push(Value::List(parts.collect(), span.clone()));
+                }
+                resolved.push(self.expr(fail));
+            }
+            (
+                Some(
+                    symbol::QUASIQUOTE
+                    | symbol::LAMBDA
+                    | symbol::MACRO
+                    | symbol::LET
+                    | symbol::LET_SYNTAX
+                    | symbol::LETREC
+                    | symbol::LETREC_STAR
+                    | symbol::LETREC_SYNTAX
+                    | symbol::BEGIN
+                    | symbol::SET
+                    | symbol::GUARD
+                    | symbol::EXCEPTION_GUARD
+                    | symbol::HANDLE
+                    | symbol::PMATCH,
+                ),
+                _,
+            ) => return None,
+            _ => resolved.extend(args.iter().map(|arg| self.expr(arg))),
+        }
+        Some(resolved)
+    }
+
+    /// a `(cond body)` clause, with its cond resolved where `resolve_cond` says
+    fn clause(&self, clause: &Value, resolve_cond: impl Fn(&Value) -> bool) -> Option<Value> {
+        let Value::List(parts, span) = clause else {
+            return None;
+        };
+        let [cond, body] = parts.as_ref() else {
+            return None;
+        };
+        let cond = match resolve_cond(cond) {
+            true => self.expr(cond),
+            false => cond.clone(),
+        };
+        Some(Value::List(
+            List::from([cond, self.expr(body)]),
+            span.clone(),
+        ))
+    }
+
+    /// `((name value)...)`, with each value resolved. the bindings have been matched
+    /// by [binding_names]
+    fn bindings(&self, bindings: &[Value], span: &Span) -> Value {
+        let bindings = bindings.iter().map(|binding| match binding {
+            Value::List(parts, span) => Value::List(
+                List::from([parts[0].clone(), self.expr(&parts[1])]),
+                span.clone(),
+            ),
+            _ => unreachable!("the bindings were matched"),
+        });
+        Value::List(bindings.collect(), span.clone())
+    }
+
+    /// a define form with its value or body resolved, see [super::inbuilt::define_global]
+    fn definition(&self, define: &[Value]) -> Option<Vec<Value>> {
+        match define {
+            [keyword @ Value::Symbol(symbol::DEFINE, _), name @ Value::Symbol(_, _), value] => {
+                Some(vec![keyword.clone(), name.clone(), self.expr(value)])
+            }
+            [keyword @ Value::Symbol(symbol::DEFINE, _), name_params @ Value::List(parts, _), body] =>
+            {
+                let [Value::Symbol(_, _), params @ ..] = parts.as_ref() else {
+                    return None;
+                };
+                let params = Params::parse(params).ok()?;
+                let body = self.with(params.names(), false).expr(body);
+                Some(vec![keyword.clone(), name_params.clone(), body])
+            }
+            [keyword @ Value::Symbol(symbol::DEFINE_MACRO, _), name_binding @ Value::List(parts, _), body] =>
+            {
+                let [Value::Symbol(_, _), Value::Symbol(binding, _)] = parts.as_ref() else {
+                    return None;
+                };
+                let body = self.with([*binding], false).expr(body);
+                Some(vec![keyword.clone(), name_binding.clone(), body])
+            }
+            [Value::Symbol(symbol::DEFINE_SYNTAX, _), Value::Symbol(_, _), _] => {
+                Some(define.to_vec())
+            }
+            _ => None,
+        }
+    }
+
+    /// a quasiquote template, with the expressions unquoted at `depth` 0 resolved,
+    /// see [super::inbuilt::quasiquote]
+    fn template(&self, template: &Value, depth: usize) -> Value {
+        let Value::List(items, span) = template else {
+            return template.clone();
+        };
+        let items = match items.as_ref() {
+            [unquote @ Value::Symbol(symbol::UNQUOTE, _), expr] if depth == 0 => {
+                vec![unquote.clone(), self.expr(expr)]
+            }
+            [Value::Symbol(symbol::UNQUOTE | symbol::UNQUOTE_SPLICING, _), ..] if depth == 0 => {
+                return template.clone()
+            }
+            [unquote @ Value::Symbol(symbol::UNQUOTE | symbol::UNQUOTE_SPLICING, _), expr] => {
+                vec![unquote.clone(), self.template(expr, depth - 1)]
+            }
+            [quasiquote @ Value::Symbol(symbol::QUASIQUOTE, _), expr] => {
+                vec![quasiquote.clone(), self.template(expr, depth + 1)]
+            }
+            _ => items
+                .iter()
+                .map(|item| match item {
+                    Value::List(splice, span) if depth == 0 => match splice.as_ref() {
+                        [splicing @ Value::Symbol(symbol::UNQUOTE_SPLICING, _), list] => {
+                            Value::List(
+                                List::from([splicing.clone(), self.expr(list)]),
+                                span.clone(),
+                            )
+                        }
+                        _ => self.template(item, depth),
+                    },
+                    _ => self.template(item, depth),
+                })
+                .collect(),
+        };
+        Value::List(List::from(items), span.clone())
+    }
+}
+
+/// the names of `((name value)...)`, see [super::inbuilt::bind_let]
+fn binding_names(bindings: &[Value]) -> Option<Vec<Symbol>> {
+    bindings
+        .iter()
+        .map(|binding| match binding {
+            Value::List(parts, _) => match parts.as_ref() {
+                [Value::Symbol(name, _), _] => Some(*name),
+                _ => None,
+            },
+            _ => None,
+        })
+        .collect()
+}
+
+/// the name a define form of a `begin` defines
+fn defined_name(define: &Value) -> Option<Symbol> {
+    let Value::List(define, _) = define else {
+        return None;
+    };
+    match define.as_ref() {
+        [Value::Symbol(symbol::DEFINE | symbol::DEFINE_SYNTAX, _), Value::Symbol(name, _), _] => {
+            Some(*name)
+        }
+        [Value::Symbol(symbol::DEFINE | symbol::DEFINE_MACRO, _), Value::List(name_params, _), _] => {
+            match name_params.first()? {
+                Value::Symbol(name, _) => Some(*name),
+                _ => None,
+            }
+        }
+        _ => None,
+    }
+}
+
+/// the names a pmatch? structure binds, in the order it binds them
+fn structure_names(structure: &Value, names: &mut Vec<Symbol>) {
+    match structure {
+        Value::Symbol(name, _) => names.push(*name),
+        Value::List(parts, _) => match parts.as_ref() {
+            [Value::Symbol(symbol::QUOTE, _), ..] => (),
+            _ => parts.iter().for_each(|part| structure_names(part, names)),
+        },
+        _ => (),
+    }
+}
+
+#[test]
+fn resolution_test() {
+    use super::test::{eval, read};
+    // the addresses of the locals a form refers to, in order
+    fn locals(expr: &Value, addresses: &mut Vec<(usize, usize)>) {
+        match expr {
+            Value::Resolved(_, Address::Local { depth, index }, _) => {
+                addresses.push((*depth, *index))
+            }
+            Value::List(items, _) => items.iter().for_each(|item| locals(item, addresses)),
+            _ => (),
+        }
+    }
+    let Value::List(forms, _) = read(
+        "(lambda (x y) (lambda (y) (list x y (let ((x 3)) x))))
+         (let loop ((n 3)) (pmatch? n (m (loop m)) 'quoted))",
+    ) else {
+        panic!()
+    };
+    // x is the slot of the frame around, the inner y shadows the outer one,
+    // and the let binds a frame of its own
+    let shadowing = resolve(forms[0].clone());
+    let mut addresses = Vec::new();
+    locals(&shadowing, &mut addresses);
+    assert_eq!(vec![(1, 0), (0, 0), (0, 0)], addresses);
+    let Value::List(items, _) = &shadowing else {
+        panic!()
+    };
+    assert!(matches!(
+        items[0],
+        Value::Resolved(symbol::LAMBDA, Address::Global, _)
+    ));
+    // the procedure of a named let is a frame out from its arguments,
+    // and a pmatch? binds the names of a branch in a frame of their own
+    let mut addresses = Vec::new();
+    locals(&resolve(forms[1].clone()), &mut addresses);
+    assert_eq!(vec![(0, 0), (2, 0), (0, 0)], addresses);
+    // names are bound as they are when looked up by name, and forms handed on as values,
+    // such as to a macro called while evaluating, have their names written as names
+    for (program, expected) in [
+        ("(pmatch? '(1 2) ((x x) (list x (eval 'x))) #f)", "(2 2)"),
+        ("(let ((_ 1) (_ 2)) (list _ (eval '_)))", "(1 1)"),
+        (
+            "(define-macro (name-of args) (list 'quote (car args)))
+             (let ((x 1) (m name-of)) (pmatch? (m x) ('x 'name) 'resolved))",
+            "name",
+        ),
+        (
+            "(let ((x 1))
+               (guard (e (#t (pmatch? (error-object-irritants e) (((_ 'x)) 'name) 'resolved)))
+                 (car x)))",
+            "name",
+        ),
+        (
+            "(let loop ((i 0) (acc '()))
+               (if? (= i 2) acc (loop (+ i 1) `(,i ,@acc (,(let ((i 'inner)) i))))))",
+            "(1 0 (inner) (inner))",
+        ),
+        (
+            "(let ((else #f)) (guard (e (else 'caught)) (raise 'x)))",
+            "caught",
+        ),
+    ] {
+        assert_eq!(Some(expected.to_string()), eval(program), "{program}");
+    }
+}
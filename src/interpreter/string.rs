@@ -0,0 +1,313 @@
+use std::rc::Rc;
+
+use super::{
+    env,
+    inbuilt::{arity, function},
+    limits, Env, EvalError, EvalResult, List, Symbol, Value,
+};
+use crate::ast::read_number;
+
+fn string(value: &Value) -> Result<&Rc<str>, EvalError> {
+    match value {
+        Value::String(string) => Ok(string),
+        _ => Err(EvalError::Type(
+            format!("expected a string, got {value}"),
+            value.clone(),
+        )),
+    }
+}
+
+fn index(value: &Value) -> Result<usize, EvalError> {
+    match value {
+        Value::Int(int) if *int >= 0 => Ok(*int as usize),
+        _ => Err(EvalError::Type(
+            format!("expected a non-negative integer, got {value}"),
+            value.clone(),
+        )),
+    }
+}
+
+fn strings(list: &Value) -> Result<Vec<Rc<str>>, EvalError> {
+    match list {
+        Value::List(values, _) => values.iter().map(|v| string(v).cloned()).collect(),
+        _ => Err(EvalError::Type(
+            format!("expected a list of strings, got {list}"),
+            list.clone(),
+        )),
+    }
+}
+
+/// a new string, allocating the cells it takes up
+fn new_string(string: String) -> EvalResult {
+    limits::allocate_bytes(string.len())?;
+    Ok(Value::String(Rc::from(string)))
+}
+
+fn list_of(strings: impl Iterator<Item = String>) -> EvalResult {
+    let values = strings.map(new_string).collect::<Result<Vec<_>, _>>()?;
+    limits::allocate(values.len())?;
+    Ok(Value::List(List::from(values), None))
+}
+
+fn length(args: &[Value]) -> EvalResult {
+    match args {
+        [s] => Ok(Value::Int(string(s)?.chars().count() as i64)),
+        _ => Err(arity("string-length", "1 argument", args)),
+    }
+}
+
+fn substring(args: &[Value]) -> EvalResult {
+    let (s, start, end) = match args {
+        [s, start] => (string(s)?, index(start)?, None),
+        [s, start, end] => (string(s)?, index(start)?, Some(index(end)?)),
+        _ => return Err(arity("substring", "2 or 3 arguments", args)),
+    };
+    let len = s.chars().count();
+    let end = end.unwrap_or(len);
+    if start > end || end > len {
+        return Err(EvalError::Type(
+            format!("range {start} to {end} is out of bounds for a string of length {len}"),
+            Value::List(List::from(args), None),
+        ));
+    }
+    new_string(s.chars().skip(start).take(end - start).collect())
+}
+
+fn append(args: &[Value]) -> EvalResult {
+    let mut appended = String::new();
+    for arg in args {
+        appended.push_str(string(arg)?);
+    }
+    new_string(appended)
+}
+
+fn split(args: &[Value]) -> EvalResult {
+    match args {
+        [s] => list_of(string(s)?.split_whitespace().map(String::from)),
+        [s, separator] => match string(separator)?.as_ref() {
+            "" => Err(EvalError::Type(
+                "cannot split on the empty string".to_string(),
+                separator.clone(),
+            )),
+            separator => list_of(string(s)?.split(separator).map(String::from)),
+        },
+        _ => Err(arity("string-split", "1 or 2 arguments", args)),
+    }
+}
+
+fn join(args: &[Value]) -> EvalResult {
+    let (list, separator) = match args {
+        [list] => (strings(list)?, ""),
+        [list, separator] => (strings(list)?, string(separator)?.as_ref()),
+        _ => return Err(arity("string-join", "1 or 2 arguments", args)),
+    };
+    new_string(list.join(separator))
+}
+
+fn map_string(name: &'static str, f: fn(&str) -> String) -> impl Fn(&[Value]) -> EvalResult {
+    move |args| match args {
+        [s] => new_string(f(string(s)?)),
+        _ => Err(arity(name, "1 argument", args)),
+    }
+}
+
+fn to_list(args: &[Value]) -> EvalResult {
+    match args {
+        [s] => list_of(string(s)?.chars().map(String::from)),
+        _ => Err(arity("string->list", "1 argument", args)),
+    }
+}
+
+fn from_list(args: &[Value]) -> EvalResult {
+    match args {
+        [list] => new_string(strings(list)?.concat()),
+        _ => Err(arity("list->string", "1 argument", args)),
+    }
+}
+
+fn to_symbol(args: &[Value]) -> EvalResult {
+    match args {
+        [s] => Ok(Value::Symbol(Symbol::charged(string(s)?)?, None)),
+        _ => Err(arity("string->symbol", "1 argument", args)),
+    }
+}
+
+/// a symbol distinct from every other, named `prefix` followed by a number
+fn gensym(args: &[Value]) -> EvalResult {
+    match args {
+        [] => Ok(Value::Symbol(Symbol::gensym("g")?, None)),
+        [prefix] => Ok(Value::Symbol(Symbol::gensym(string(prefix)?)?, None)),
+        _ => Err(arity("gensym", "at most 1 argument", args)),
+    }
+}
+
+fn from_symbol(args: &[Value]) -> EvalResult {
+    match args {
+        // the string shares the name of the symbol
+        [Value::Symbol(symbol, _)] => Ok(Value::String(symbol.name())),
+        [value] => Err(EvalError::Type(
+            format!("expected a symbol, got {value}"),
+            value.clone(),
+        )),
+        _ => Err(arity("symbol->string", "1 argument", args)),
+    }
+}
+
+fn radix(value: &Value) -> Result<u32, EvalError> {
+    match value {
+        Value::Int(radix @ (2 | 8 | 10 | 16)) => Ok(*radix as u32),
+        _ => Err(EvalError::Type(
+            format!("expected a radix of 2, 8, 10 or 16, got {value}"),
+            value.clone(),
+        )),
+    }
+}
+
+fn from_number(args: &[Value]) -> EvalResult {
+    let (number, radix) = match args {
+        [number] => (number, 10),
+        [number, r] => (number, radix(r)?),
+        _ => return Err(arity("number->string", "1 or 2 arguments", args)),
+    };
+    let string = match (number, radix) {
+        (Value::Int(_) | Value::Float(_), 10) => number.to_string(),
+        (Value::Int(int), radix) => {
+            let sign = if *int < 0 { "-" } else { "" };
+            let magnitude = int.unsigned_abs();
+            match radix {
+                2 => format!("{sign}{magnitude:b}"),
+                8 => format!("{sign}{magnitude:o}"),
+                _ => format!("{sign}{magnitude:x}"),
+            }
+        }
+        _ => {
+            return Err(EvalError::Type(
+                format!("expected an integer to write in radix {radix}, got {number}"),
+                number.clone(),
+            ))
+        }
+    };
+    new_string(string)
+}
+
+fn to_number(args: &[Value]) -> EvalResult {
+    let (s, radix) = match args {
+        [s] => (string(s)?, 10),
+        [s, r] => (string(s)?, radix(r)?),
+        _ => return Err(arity("string->number", "1 or 2 arguments", args)),
+    };
+    Ok(read_number(s, radix).unwrap_or(Value::Bool(false)))
+}
+
+fn compare(name: &'static str, test: fn(&str, &str) -> bool) -> impl Fn(&[Value]) -> EvalResult {
+    move |args| {
+        if args.is_empty() {
+            return Err(arity(name, "at least 1 argument", args));
+        }
+        let strings = args.iter().map(string).collect::<Result<Vec<_>, _>>()?;
+        Ok(Value::Bool(
+            strings.windows(2).all(|pair| test(pair[0], pair[1])),
+        ))
+    }
+}
+
+fn is_string(args: &[Value]) -> EvalResult {
+    match args {
+        [value] => Ok(Value::Bool(matches!(value, Value::String(_)))),
+        _ => Err(arity("string?", "1 argument", args)),
+    }
+}
+
+pub fn bindings(env: Env) -> Vec<env::Value<&'static str, Value>> {
+    vec![
+        env::Value("string?", function(env.clone(), "value", is_string)),
+        env::Value("string-length", function(env.clone(), "string", length)),
+        env::Value(
+            "substring",
+            function(env.clone(), "string start [end]", substring),
+        ),
+        env::Value("string-append", function(env.clone(), "string...", append)),
+        env::Value(
+            "string-split",
+            function(env.clone(), "string [separator]", split),
+        ),
+        env::Value(
+            "string-join",
+            function(env.clone(), "list [separator]", join),
+        ),
+        env::Value(
+            "string-upcase",
+            function(
+                env.clone(),
+                "string",
+                map_string("string-upcase", str::to_uppercase),
+            ),
+        ),
+        env::Value(
+            "string-downcase",
+            function(
+                env.clone(),
+                "string",
+                map_string("string-downcase", str::to_lowercase),
+            ),
+        ),
+        env::Value("string->list", function(env.clone(), "string", to_list)),
+        env::Value("list->string", function(env.clone(), "list", from_list)),
+        env::Value("string->symbol", function(env.clone(), "string", to_symbol)),
+        env::Value("gensym", function(env.clone(), "[prefix]", gensym)),
+        env::Value(
+            "symbol->string",
+            function(env.clone(), "symbol", from_symbol),
+        ),
+        env::Value(
+            "number->string",
+            function(env.clone(), "number [radix]", from_number),
+        ),
+        env::Value(
+            "string->number",
+            function(env.clone(), "string [radix]", to_number),
+        ),
+        env::Value(
+            "string=?",
+            function(
+                env.clone(),
+                "string string...",
+                compare("string=?", |l, r| l == r),
+            ),
+        ),
+        env::Value(
+            "string<?",
+            function(env, "string string...", compare("string<?", |l, r| l < r)),
+        ),
+    ]
+}
+
+#[test]
+fn strings_test() {
+    use super::test::{eval, interpret_str};
+    assert_eq!(Some("\"a\\nb\"".to_string()), eval("\"a\\nb\""));
+    assert_eq!(Some("5".to_string()), eval("(string-length \"héllo\")"));
+    assert_eq!(Some("\"ell\"".to_string()), eval("(substring \"hello\" 1 4)"));
+    assert_eq!(Some("\"foobar\"".to_string()), eval("(string-append \"foo\" \"bar\")"));
+    assert_eq!(
+        Some("(\"a\" \"b\" \"c\")".to_string()),
+        eval("(string-split \" a  b c \")")
+    );
+    assert_eq!(
+        Some("\"a-b\"".to_string()),
+        eval("(string-join (string-split \"a,b\" \",\") \"-\")")
+    );
+    assert_eq!(Some("\"LOUD\"".to_string()), eval("(string-upcase \"loud\")"));
+    assert_eq!(Some("abc".to_string()), eval("(string->symbol \"abc\")"));
+    assert_eq!(Some("#t".to_string()), eval("(pmatch? (string->symbol \"abc\") ('abc #t) #f)"));
+    assert_eq!(
+        Some("#f".to_string()),
+        eval("(let ((g (gensym)))
+                (eval (list 'pmatch? (list 'quote g) (list (list 'quote (string->symbol (symbol->string g))) #t) #f)))")
+    );
+    assert_eq!(Some("\"ff\"".to_string()), eval("(number->string 255 16)"));
+    assert_eq!(Some("2.5".to_string()), eval("(string->number \"2.5\")"));
+    assert_eq!(Some("#f".to_string()), eval("(string->number \"nope\")"));
+    assert_eq!(Some("#t".to_string()), eval("(string<? \"a\" \"b\" \"c\")"));
+    assert!(matches!(interpret_str("(substring \"abc\" 2 5)"), Err(EvalError::Type(_, _))));
+}
@@ -0,0 +1,133 @@
+use core::fmt;
+use std::{cell::RefCell, collections::HashMap, rc::Rc};
+
+use super::{limits, EvalError};
+
+/// an interned name. symbols with the same name have the same id,
+/// so comparing them, or looking them up in an environment, compares integers
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Symbol(u32);
+
+/// every name which has been interned, and the names of uninterned symbols.
+/// the table owns the names, which are kept for as long as the thread runs,
+/// so the names of symbols made while evaluating are charged to [limits::allocate_bytes]
+struct Table {
+    ids: HashMap<Rc<str>, Symbol>,
+    names: Vec<Rc<str>>,
+}
+
+thread_local! {
+    static TABLE: RefCell<Table> = RefCell::new(Table {
+        ids: KNOWN.iter().enumerate().map(|(id, name)| (Rc::from(*name), Symbol(id as u32))).collect(),
+        names: KNOWN.iter().map(|name| Rc::from(*name)).collect(),
+    });
+}
+
+impl Symbol {
+    /// the symbol named `name`, interning it if it is new
+    pub fn new(name: &str) -> Self {
+        TABLE.with_borrow_mut(|table| match table.ids.get(name) {
+            Some(symbol) => *symbol,
+            None => {
+                let name: Rc<str> = Rc::from(name);
+                let symbol = Symbol(table.names.len() as u32);
+                table.names.push(name.clone());
+                table.ids.insert(name, symbol);
+                symbol
+            }
+        })
+    }
+
+    /// [Symbol::new], charging the name against the limit on memory if it is new
+    pub fn charged(name: &str) -> Result<Self, EvalError> {
+        if !TABLE.with_borrow(|table| table.ids.contains_key(name)) {
+            limits::allocate_bytes(name.len())?;
+        }
+        Ok(Symbol::new(name))
+    }
+
+    /// a symbol which is not equal to any other, even one with the same name.
+    /// it is named `prefix` followed by a number, to tell it apart when displayed.
+    /// its name is charged against the limit on memory
+    pub fn gensym(prefix: &str) -> Result<Self, EvalError> {
+        let name = TABLE.with_borrow(|table| format!("{prefix}{}", table.names.len()));
+        limits::allocate_bytes(name.len())?;
+        Ok(TABLE.with_borrow_mut(|table| {
+            let symbol = Symbol(table.names.len() as u32);
+            table.names.push(Rc::from(name));
+            symbol
+        }))
+    }
+
+    pub fn name(self) -> Rc<str> {
+        TABLE.with_borrow(|table| table.names[self.0 as usize].clone())
+    }
+}
+
+impl fmt::Display for Symbol {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+impl fmt::Debug for Symbol {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?}", self.name())
+    }
+}
+
+/// declares the names the interpreter matches on, with fixed ids in the order given
+macro_rules! known {
+    ($($symbol:ident $name:literal)*) => {
+        const KNOWN: &[&str] = &[$($name),*];
+        known!(@ 0; $($symbol)*);
+    };
+    (@ $id:expr; $symbol:ident $($rest:ident)*) => {
+        pub const $symbol: Symbol = Symbol($id);
+        known!(@ $id + 1; $($rest)*);
+    };
+    (@ $id:expr;) => {};
+}
+
+known! {
+    QUOTE "quote"
+    QUASIQUOTE "quasiquote"
+    UNQUOTE "unquote"
+    UNQUOTE_SPLICING "unquote-splicing"
+    DEFINE "define"
+    DEFINE_MACRO "define-macro"
+    DEFINE_SYNTAX "define-syntax"
+    LAMBDA "lambda"
+    MACRO "macro"
+    BEGIN "begin"
+    LET "let"
+    LETREC "letrec"
+    LETREC_STAR "letrec*"
+    LET_SYNTAX "let-syntax"
+    LETREC_SYNTAX "letrec-syntax"
+    SET "set!"
+    IF "if?"
+    GUARD "guard?"
+    EXCEPTION_GUARD "guard"
+    ELSE "else"
+    HANDLE "handle"
+    PMATCH "pmatch?"
+    EVAL "eval"
+    ERROR "error"
+    SYNTAX_RULES "syntax-rules"
+    DOT "."
+    WILDCARD "_"
+    ELLIPSIS "..."
+    OPTIONAL "#!optional"
+    KEY "#!key"
+    REST "#!rest"
+}
+
+#[test]
+fn interning() {
+    assert_eq!(QUOTE, Symbol::new("quote"));
+    assert_eq!("#!rest", &*REST.name());
+    assert_eq!(Symbol::new("a fresh name"), Symbol::new("a fresh name"));
+    let gensym = Symbol::gensym("g").unwrap();
+    assert_ne!(gensym, Symbol::new(&gensym.name()));
+}
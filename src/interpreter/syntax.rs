@@ -0,0 +1,477 @@
+use core::fmt::Display;
+use std::{
+    collections::HashMap,
+    rc::Rc,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use super::{
+    expand::{ExpandResult, Expansion},
+    symbol::{self, Symbol},
+    EvalError, List, Value,
+};
+
+/// what a pattern variable matched. under an ellipsis, it matches once for each repetition
+#[derive(Clone)]
+enum Match {
+    One(Value),
+    Many(Vec<Match>),
+}
+
+type Bindings = HashMap<Symbol, Match>;
+
+/// names which forms recognise by name rather than by what they are bound to,
+/// so an expansion keeps them as they are written
+fn reserved(name: Symbol) -> bool {
+    matches!(
+        name,
+        symbol::DEFINE
+            | symbol::DEFINE_MACRO
+            | symbol::DEFINE_SYNTAX
+            | symbol::QUOTE
+            | symbol::QUASIQUOTE
+            | symbol::UNQUOTE
+            | symbol::UNQUOTE_SPLICING
+            | symbol::DOT
+            | symbol::WILDCARD
+            | symbol::ELLIPSIS
+            | symbol::OPTIONAL
+            | symbol::KEY
+            | symbol::REST
+    ) || name.name().starts_with("#:")
+}
+
+/// the name an identifier was written with, before any expansions renamed it
+fn unrenamed(mut name: &str) -> &str {
+    while let Some((original, mark)) = name.rsplit_once('#') {
+        if original.is_empty() || mark.is_empty() || !mark.bytes().all(|b| b.is_ascii_digit()) {
+            break;
+        }
+        name = original;
+    }
+    name
+}
+
+/// each expansion marks the names it introduces differently
+static EXPANSIONS: AtomicUsize = AtomicUsize::new(0);
+
+/// the rules of a `syntax-rules` macro,
+/// `(syntax-rules [ellipsis] (literal...) (pattern template)...)`
+pub struct Rules {
+    ellipsis: Symbol,
+    literals: Rc<[Value]>,
+    /// the patterns without the macro keyword they start with, and their templates
+    rules: Vec<(Rc<[Value]>, Value)>,
+}
+
+impl Display for Rules {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "{}", super::DisplayList(self.literals.clone()))?;
+        for (pattern, _) in &self.rules {
+            write!(f, " (_")?;
+            for part in pattern.iter() {
+                write!(f, " {part}")?;
+            }
+            write!(f, ")")?;
+        }
+        Ok(())
+    }
+}
+
+impl Rules {
+    pub fn parse(exprs: &[Value]) -> Result<Self, EvalError> {
+        let (ellipsis, literals, rules) = match exprs {
+            [Value::Symbol(ellipsis, _), Value::List(literals, _), rules @ ..] => {
+                (*ellipsis, literals, rules)
+            }
+            [Value::List(literals, _), rules @ ..] => (symbol::ELLIPSIS, literals, rules),
+            _ => {
+                return Err(EvalError::BadForm(
+                    "expected (syntax-rules [ellipsis] (literal...) (pattern template)...)"
+                        .to_string(),
+                    Value::List(List::from(exprs), None),
+                ))
+            }
+        };
+        if let Some(literal) = literals
+            .iter()
+            .find(|lit| !matches!(lit, Value::Symbol(_, _)))
+        {
+            return Err(EvalError::BadForm(
+                "invalid literal, expected a symbol".to_string(),
+                literal.clone(),
+            ));
+        }
+        let rules = rules
+            .iter()
+            .map(|rule| match rule {
+                Value::List(parts, _) => match parts.as_ref() {
+                    [Value::List(pattern, _), template] if !pattern.is_empty() => {
+                        Ok((Rc::from(&pattern[1..]), template.clone()))
+                    }
+                    _ => Err(rule_form(rule)),
+                },
+                _ => Err(rule_form(rule)),
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(Rules {
+            ellipsis,
+            literals: Rc::from(&**literals),
+            rules,
+        })
+    }
+
+    /// expands the template of the first rule matching `args`
+    pub fn expand(&self, args: &[Value]) -> ExpandResult {
+        for (pattern, template) in &self.rules {
+            let mut bindings = HashMap::new();
+            if self.match_list(pattern, args, &mut bindings) {
+                let mut expansion = Template {
+                    rules: self,
+                    mark: EXPANSIONS.fetch_add(1, Ordering::Relaxed),
+                    renames: Vec::new(),
+                };
+                let value = expansion.template(template, &bindings, Quoted::No, false)?;
+                return Ok(Expansion {
+                    expr: value,
+                    renames: expansion.renames.into(),
+                });
+            }
+        }
+        Err(EvalError::BadForm(
+            format!("did not match any rules of syntax {self}"),
+            Value::List(List::from(args), None),
+        ))
+    }
+
+    fn is_ellipsis(&self, value: &Value) -> bool {
+        matches!(value, Value::Symbol(name, _) if *name == self.ellipsis)
+    }
+
+    fn is_literal(&self, name: Symbol) -> bool {
+        self.literals
+            .iter()
+            .any(|literal| matches!(literal, Value::Symbol(lit, _) if *lit == name))
+    }
+
+    fn match_pattern(&self, pattern: &Value, value: &Value, bindings: &mut Bindings) -> bool {
+        match pattern {
+            Value::Symbol(symbol::WILDCARD, _) => true,
+            Value::Symbol(name, _) if self.is_literal(*name) => {
+                matches!(value, Value::Symbol(symbol, _) if unrenamed(&symbol.name()) == unrenamed(&name.name()))
+            }
+            Value::Symbol(name, _) => {
+                bindings.insert(*name, Match::One(value.clone()));
+                true
+            }
+            Value::List(patterns, _) => match value {
+                Value::List(values, _) => self.match_list(patterns, values, bindings),
+                _ => false,
+            },
+            _ => pattern == value,
+        }
+    }
+
+    /// matches `(before... [repeated ellipsis] after... [. tail])`
+    fn match_list(&self, patterns: &[Value], values: &[Value], bindings: &mut Bindings) -> bool {
+        let (patterns, tail) = match patterns {
+            [init @ .., Value::Symbol(symbol::DOT, _), tail] => (init, Some(tail)),
+            _ => (patterns, None),
+        };
+        let (before, repeated, after) = match patterns.iter().position(|p| self.is_ellipsis(p)) {
+            Some(i) if i > 0 => (
+                &patterns[..i - 1],
+                Some(&patterns[i - 1]),
+                &patterns[i + 1..],
+            ),
+            _ => (patterns, None, &[][..]),
+        };
+        let fixed = before.len() + after.len();
+        if values.len() < fixed || (repeated.is_none() && tail.is_none() && values.len() != fixed) {
+            return false;
+        }
+        let (values_before, rest) = values.split_at(before.len());
+        let repetitions = match repeated {
+            Some(_) => rest.len() - after.len(),
+            None => 0,
+        };
+        let (repeats, rest) = rest.split_at(repetitions);
+        let (values_after, remaining) = rest.split_at(after.len());
+
+        let fixed_match = before
+            .iter()
+            .zip(values_before)
+            .chain(after.iter().zip(values_after))
+            .all(|(pattern, value)| self.match_pattern(pattern, value, bindings));
+        if !fixed_match {
+            return false;
+        }
+        if let Some(repeated) = repeated {
+            let mut vars = Vec::new();
+            self.pattern_vars(repeated, &mut vars);
+            let mut matches: Vec<Vec<Match>> = vars.iter().map(|_| Vec::new()).collect();
+            for value in repeats {
+                let mut repetition = HashMap::new();
+                if !self.match_pattern(repeated, value, &mut repetition) {
+                    return false;
+                }
+                for (var, matches) in vars.iter().zip(matches.iter_mut()) {
+                    matches.push(
+                        repetition
+                            .remove(var)
+                            .expect("the pattern binds its variables"),
+                    );
+                }
+            }
+            for (var, matches) in vars.into_iter().zip(matches) {
+                bindings.insert(var, Match::Many(matches));
+            }
+        }
+        match tail {
+            Some(tail) => {
+                self.match_pattern(tail, &Value::List(List::from(remaining), None), bindings)
+            }
+            None => true,
+        }
+    }
+
+    fn pattern_vars(&self, pattern: &Value, vars: &mut Vec<Symbol>) {
+        match pattern {
+            Value::Symbol(symbol::WILDCARD | symbol::DOT, _) => (),
+            Value::Symbol(name, _) if self.is_literal(*name) || *name == self.ellipsis => (),
+            Value::Symbol(name, _) => vars.push(*name),
+            Value::List(patterns, _) => {
+                for pattern in patterns.iter() {
+                    self.pattern_vars(pattern, vars);
+                }
+            }
+            _ => (),
+        }
+    }
+}
+
+fn rule_form(rule: &Value) -> EvalError {
+    EvalError::BadForm(
+        "invalid rule, expected ((keyword pattern...) template)".to_string(),
+        rule.clone(),
+    )
+}
+
+/// whether the part of a template being filled in is data rather than code,
+/// in which case the names it introduces are kept as they are
+#[derive(Clone, Copy)]
+enum Quoted {
+    No,
+    Yes,
+    /// within this many quasiquotes, where only unquoted parts are code
+    Quasi(usize),
+}
+
+impl Quoted {
+    /// how the items of a list starting with `head` are quoted
+    fn within(self, head: Option<&Value>) -> Self {
+        let Some(Value::Symbol(head, _)) = head else {
+            return self;
+        };
+        match (self, *head) {
+            (Quoted::No, symbol::QUOTE) => Quoted::Yes,
+            (Quoted::No, symbol::QUASIQUOTE) => Quoted::Quasi(1),
+            (Quoted::Quasi(depth), symbol::QUASIQUOTE) => Quoted::Quasi(depth + 1),
+            (Quoted::Quasi(1), symbol::UNQUOTE | symbol::UNQUOTE_SPLICING) => Quoted::No,
+            (Quoted::Quasi(depth), symbol::UNQUOTE | symbol::UNQUOTE_SPLICING) => {
+                Quoted::Quasi(depth - 1)
+            }
+            (quoted, _) => quoted,
+        }
+    }
+}
+
+/// a template being filled in by the variables of the pattern it matched
+struct Template<'a> {
+    rules: &'a Rules,
+    mark: usize,
+    renames: Vec<(Symbol, Symbol)>,
+}
+
+impl<'a> Template<'a> {
+    /// `quoted` templates keep the names they introduce as they are,
+    /// and `escaped` templates, within `(... template)`, treat the ellipsis as a plain symbol
+    fn template(
+        &mut self,
+        template: &Value,
+        bindings: &Bindings,
+        quoted: Quoted,
+        escaped: bool,
+    ) -> Result<Value, EvalError> {
+        match template {
+            Value::Symbol(name, span) => match bindings.get(name) {
+                Some(Match::One(value)) => Ok(value.clone()),
+                Some(Match::Many(_)) => Err(EvalError::BadForm(
+                    format!("pattern variable {name} needs an ellipsis after it"),
+                    template.clone(),
+                )),
+                None if !matches!(quoted, Quoted::No) || reserved(*name) => Ok(template.clone()),
+                None => Ok(Value::Symbol(self.rename(*name)?, span.clone())),
+            },
+            Value::List(items, span) => match items.as_ref() {
+                [ellipsis, escaped_template] if !escaped && self.rules.is_ellipsis(ellipsis) => {
+                    self.template(escaped_template, bindings, quoted, true)
+                }
+                [_, _] => Ok(Value::List(
+                    self.list(items, bindings, quoted.within(items.first()), escaped)?.into(),
+                    span.clone(),
+                )),
+                _ => Ok(Value::List(
+                    self.list(items, bindings, quoted, escaped)?.into(),
+                    span.clone(),
+                )),
+            },
+            _ => Ok(template.clone()),
+        }
+    }
+
+    fn list(
+        &mut self,
+        items: &[Value],
+        bindings: &Bindings,
+        quoted: Quoted,
+        escaped: bool,
+    ) -> Result<Vec<Value>, EvalError> {
+        let mut expanded = Vec::with_capacity(items.len());
+        let mut i = 0;
+        while i < items.len() {
+            let item = &items[i];
+            // a dotted tail which expands to a list is spliced in
+            if let (Value::Symbol(symbol::DOT, _), [tail]) = (item, &items[i + 1..]) {
+                match self.template(tail, bindings, quoted, escaped)? {
+                    Value::List(tail, _) => expanded.extend(tail.iter().cloned()),
+                    tail => expanded.extend([item.clone(), tail]),
+                }
+                break;
+            }
+            let depth = match escaped {
+                true => 0,
+                false => items[i + 1..]
+                    .iter()
+                    .take_while(|next| self.rules.is_ellipsis(next))
+                    .count(),
+            };
+            match depth {
+                0 => expanded.push(self.template(item, bindings, quoted, escaped)?),
+                _ => self.repeat(item, bindings, depth, quoted, &mut expanded)?,
+            }
+            i += 1 + depth;
+        }
+        Ok(expanded)
+    }
+
+    /// expands a template followed by `depth` ellipses once for each repetition
+    /// of the pattern variables within it
+    fn repeat(
+        &mut self,
+        template: &Value,
+        bindings: &Bindings,
+        depth: usize,
+        quoted: Quoted,
+        expanded: &mut Vec<Value>,
+    ) -> Result<(), EvalError> {
+        let mut vars = Vec::new();
+        self.rules.pattern_vars(template, &mut vars);
+        let repeated: Vec<_> = vars
+            .into_iter()
+            .filter_map(|var| match bindings.get(&var) {
+                Some(Match::Many(matches)) => Some((var, matches)),
+                _ => None,
+            })
+            .collect();
+        let Some((_, first)) = repeated.first() else {
+            return Err(EvalError::BadForm(
+                "no pattern variable repeats under this ellipsis".to_string(),
+                template.clone(),
+            ));
+        };
+        if repeated
+            .iter()
+            .any(|(_, matches)| matches.len() != first.len())
+        {
+            return Err(EvalError::BadForm(
+                "pattern variables under one ellipsis matched different numbers of times"
+                    .to_string(),
+                template.clone(),
+            ));
+        }
+        for i in 0..first.len() {
+            let mut repetition = bindings.clone();
+            for (var, matches) in &repeated {
+                repetition.insert(*var, matches[i].clone());
+            }
+            match depth {
+                1 => expanded.push(self.template(template, &repetition, quoted, false)?),
+                _ => self.repeat(template, &repetition, depth - 1, quoted, expanded)?,
+            }
+        }
+        Ok(())
+    }
+
+    /// a name introduced by the template, distinct from every name where the macro is used
+    fn rename(&mut self, name: Symbol) -> Result<Symbol, EvalError> {
+        if let Some((renamed, _)) = self.renames.iter().find(|(_, original)| *original == name) {
+            return Ok(*renamed);
+        }
+        let renamed = Symbol::charged(&format!("{name}#{}", self.mark))?;
+        self.renames.push((renamed, name));
+        Ok(renamed)
+    }
+}
+
+#[test]
+fn syntax_rules_test() {
+    use super::test::{eval, interpret_str};
+    // the introduced `t` does not capture the caller's `t`,
+    // and the introduced `if?` is not captured by the caller's `if?`
+    assert_eq!(
+        Some("(5 7)".to_string()),
+        eval("(begin
+                (define-syntax my-or
+                  (syntax-rules ()
+                    ((_) #f)
+                    ((_ e) e)
+                    ((_ e rest ...) (let ((t e)) (if? t t (my-or rest ...))))))
+                (define t 5)
+                (list (my-or #f t) (let ((if? 7)) (my-or #f if?))))")
+    );
+    assert_eq!(
+        Some("(1 2 3)".to_string()),
+        eval("(begin
+                (define-syntax my-cond
+                  (syntax-rules (else)
+                    ((_ (else e)) e)
+                    ((_ (c e) clause ...) (if? c e (my-cond clause ...)))))
+                (list (my-cond (#t 1)) (my-cond (#f 1) (#t 2)) (my-cond (#f 1) (else 3))))")
+    );
+    assert_eq!(
+        Some("((1 2) (a b))".to_string()),
+        eval("(begin
+                (define-syntax bind
+                  (syntax-rules () ((_ ((name value) ...) body) ((lambda (name ...) body) value ...))))
+                (bind ((a 1) (b 2)) (list (list a b) '(a b))))")
+    );
+    // names in the literal parts of quasiquote are data, as they are in quote,
+    // while the unquoted parts are code
+    assert_eq!(
+        Some("(a (b 3) b)".to_string()),
+        eval("(begin
+                (define-syntax m (syntax-rules () ((_) (list 'a `(b ,(+ 1 2)) `b))))
+                (m))")
+    );
+    assert_eq!(
+        Some("(t 5 t `(t ,(t 6)))".to_string()),
+        eval("(begin
+                (define-syntax n (syntax-rules () ((_ x) (let ((t x)) `(t ,t ,'t `(t ,(t ,(+ 1 t))))))))
+                (let ((t 'outer)) (n 5)))")
+    );
+    assert!(matches!(
+        interpret_str("(begin (define-syntax one (syntax-rules () ((_ x) x))) (one 1 2))"),
+        Err(EvalError::BadForm(_, _))
+    ));
+}
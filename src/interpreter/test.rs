@@ -0,0 +1,24 @@
+//! helpers for the tests of the interpreter
+
+use super::{EvalResult, List, Session, Value};
+
+/// reads `source`, which is expected to be well formed
+pub fn read(source: &str) -> Value {
+    crate::ast::sl(crate::fastpass::View::new(source)).ok().unwrap()
+}
+
+/// runs a whole program, giving the value of its last form
+pub fn interpret(program: Value) -> EvalResult {
+    Session::default()
+        .run(program, |_| ())
+        .map(|last| last.unwrap_or(Value::List(List::from([]), None)))
+}
+
+pub fn interpret_str(source: &str) -> EvalResult {
+    interpret(read(source))
+}
+
+/// the value of the last form of `source` as it is displayed, or None if it failed
+pub fn eval(source: &str) -> Option<String> {
+    interpret_str(source).map(|value| value.to_string()).ok()
+}
@@ -1,59 +1,139 @@
 use core::cmp::PartialEq;
 use core::fmt::Display;
-use std::rc::Rc;
+use std::{cell::RefCell, rc::Rc};
 
-use super::{env, env::Lookup, Env, EvalResult};
+use super::{
+    env::{Address, Lookup},
+    expand::Macro,
+    symbol::{self, Symbol},
+    vm::Closure,
+    Env, EvalError, EvalResult, List, TailResult,
+};
+use crate::ast::Span;
+
+/// applies a procedure, given the environment it was defined in,
+/// the environment of the caller, and the unevaluated arguments of the call
+pub type ProcedureFn = Rc<dyn Fn(&Env, Env, &[Value]) -> TailResult + 'static>;
 
 #[derive(Clone)]
-pub enum Value<'env> {
-    Procedure(
-        Env<'env>,
-        Rc<dyn Fn(Env<'env>, &[Value<'env>]) -> EvalResult<'env> + 'env>,
-        Rc<dyn Display + 'env>,
-    ),
-    Symbol(&'env str),
+pub enum Value {
+    /// the environment the procedure was defined in, how to apply it, and how to display it
+    Procedure(Env, ProcedureFn, Rc<dyn Display>),
+    /// a procedure whose call is replaced by the form it expands to
+    Macro(Rc<Macro>),
+    /// a procedure compiled to run on the [super::vm]
+    Closure(Rc<Closure>),
+    Symbol(Symbol, Span),
+    /// a symbol of an expression, resolved to where it is bound, see [super::resolve]
+    Resolved(Symbol, Address, Span),
     Bool(bool),
-    List(Rc<[Value<'env>]>),
+    Int(i64),
+    Float(f64),
+    String(Rc<str>),
+    List(List, Span),
+    /// a mutable reference to a value
+    Box(Rc<RefCell<Value>>),
+    /// an error object, made by `error` or by the interpreter failing, see [EvalError::condition]
+    Error(Rc<EvalError>),
 }
 
-impl<'env> PartialEq for Value<'env> {
+impl PartialEq for Value {
     fn eq(&self, other: &Self) -> bool {
         match self {
-            Value::Symbol(id) => matches!(other, Value::Symbol(other_id) if id == other_id),
-            Value::List(lst) => matches!(
+            Value::Symbol(id, _) => matches!(other, Value::Symbol(other_id, _) if id == other_id),
+            Value::Resolved(id, address, _) => matches!(
+                    other,
+                    Value::Resolved(other_id, other_address, _)
+                        if id == other_id && address == other_address
+            ),
+            Value::List(lst, _) => matches!(
                     other,
-                    Value::List(other_lst)
-                        if lst.iter().zip(other_lst.iter()).all(|(a, b)| a == b)
+                    Value::List(other_lst, _)
+                        if lst.len() == other_lst.len()
+                            && lst.iter().zip(other_lst.iter()).all(|(a, b)| a == b)
             ),
             Value::Procedure(_, fn_ptr, _) => matches!(
                     other,
                     Value::Procedure(_, other_fn_ptr, _)
-                        if Rc::ptr_eq(&fn_ptr, &other_fn_ptr)
+                        if Rc::ptr_eq(fn_ptr, other_fn_ptr)
             ),
+            Value::Macro(mac) => {
+                matches!(other, Value::Macro(other_mac) if Rc::ptr_eq(mac, other_mac))
+            }
+            Value::Closure(closure) => {
+                matches!(other, Value::Closure(other_closure) if Rc::ptr_eq(closure, other_closure))
+            }
             Value::Bool(bool) => matches!(other, Value::Bool(other_bool) if bool == other_bool),
+            Value::Int(int) => matches!(other, Value::Int(other_int) if int == other_int),
+            Value::String(string) => {
+                matches!(other, Value::String(other_string) if string == other_string)
+            }
+            Value::Float(float) => {
+                matches!(other, Value::Float(other_float) if float == other_float)
+            }
+            Value::Box(cell) => {
+                matches!(other, Value::Box(other_cell) if Rc::ptr_eq(cell, other_cell))
+            }
+            Value::Error(err) => {
+                matches!(other, Value::Error(other_err) if Rc::ptr_eq(err, other_err))
+            }
         }
     }
 }
 
-impl<'env> Value<'env> {
-    pub fn from_env(env: Env<'env>, str: &'env str) -> EvalResult<'env> {
-        Ok(env
-            .clone()
-            .lookup(&str)
-            .expect(&format!(
-                "unable to find value for name \"{str}\"\nenv:\n{:?}\n",
-                env::Debug::new(env)
-            ))
-            .clone())
+impl Value {
+    pub fn from_env(env: Env, symbol: Symbol) -> EvalResult {
+        env.lookup(&symbol)
+            .ok_or_else(|| EvalError::Unbound(Value::Symbol(symbol, None), env.clone()))
+    }
+
+    /// all values except for `#f` and the empty list are truthy
+    pub fn truthy(&self) -> bool {
+        match self {
+            Value::Bool(bool) => *bool,
+            Value::List(values, _) => !values.is_empty(),
+            _ => true,
+        }
+    }
+
+    /// where this value was read from, if it was read from source
+    pub fn span(&self) -> Span {
+        match self {
+            Value::Symbol(_, span) | Value::Resolved(_, _, span) | Value::List(_, span) => {
+                span.clone()
+            }
+            _ => None,
+        }
     }
 }
 
-impl<'env> core::fmt::Display for Value<'env> {
+thread_local! {
+    /// the boxes a value being displayed is within
+    static PRINTING: RefCell<Vec<*const RefCell<Value>>> = const { RefCell::new(Vec::new()) };
+}
+
+impl core::fmt::Display for Value {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             Value::Procedure(_, _, repr) => write!(f, "(procedure {repr})"),
-            Value::Symbol(expression) => write!(f, "{expression}"),
-            Value::List(lst) => {
+            Value::Macro(mac) => write!(f, "(macro {mac})"),
+            Value::Closure(closure) => write!(f, "(procedure {closure})"),
+            Value::Symbol(expression, _) | Value::Resolved(expression, _, _) => {
+                write!(f, "{expression}")
+            }
+            Value::List(lst, _) => {
+                if let [Value::Symbol(name, _), quoted] = lst.as_ref() {
+                    let prefix = match *name {
+                        symbol::QUOTE => Some("'"),
+                        symbol::QUASIQUOTE => Some("`"),
+                        symbol::UNQUOTE => Some(","),
+                        symbol::UNQUOTE_SPLICING => Some(",@"),
+                        _ => None,
+                    };
+                    if let Some(prefix) = prefix {
+                        return write!(f, "{prefix}{quoted}");
+                    }
+                }
                 if !lst.is_empty() {
                     write!(f, "({}", lst[0])?;
                     let _ = &lst[1..]
@@ -68,11 +148,37 @@ impl<'env> core::fmt::Display for Value<'env> {
                 true => write!(f, "#t"),
                 false => write!(f, "#f"),
             },
+            Value::Int(int) => write!(f, "{int}"),
+            Value::Float(float) => match float {
+                float if float.is_nan() => write!(f, "+nan.0"),
+                float if float.is_infinite() && *float > 0.0 => write!(f, "+inf.0"),
+                float if float.is_infinite() => write!(f, "-inf.0"),
+                float => write!(f, "{float:?}"),
+            },
+            Value::String(string) => write!(f, "{string:?}"),
+            Value::Box(cell) => {
+                // a box which contains itself is shown as #&... where it is reached again
+                let ptr = Rc::as_ptr(cell);
+                if PRINTING.with_borrow(|printing| printing.contains(&ptr)) {
+                    return write!(f, "#&...");
+                }
+                PRINTING.with_borrow_mut(|printing| printing.push(ptr));
+                let written = write!(f, "#&{}", cell.borrow());
+                PRINTING.with_borrow_mut(|printing| printing.pop());
+                written
+            }
+            Value::Error(err) => {
+                write!(f, "(error {:?}", err.reason())?;
+                err.irritants()
+                    .iter()
+                    .try_for_each(|irritant| write!(f, " {irritant}"))?;
+                write!(f, ")")
+            }
         }
     }
 }
 
-impl<'env> core::fmt::Debug for Value<'env> {
+impl core::fmt::Debug for Value {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(f, "{self}")
     }
@@ -0,0 +1,564 @@
+use std::rc::Rc;
+
+use super::{Function, Op, Scope};
+use crate::interpreter::{
+    params,
+    params::Params,
+    symbol::{self, Symbol},
+    List, Value,
+};
+
+/// the forms which do not evaluate their arguments, and are compiled as such where the
+/// compiler understands them. other uses of them are left to [Op::Interpret]
+const FORMS: &[Symbol] = &[
+    symbol::LAMBDA,
+    symbol::MACRO,
+    symbol::BEGIN,
+    symbol::LET,
+    symbol::QUOTE,
+    symbol::QUASIQUOTE,
+    symbol::GUARD,
+    symbol::EXCEPTION_GUARD,
+    symbol::HANDLE,
+    symbol::PMATCH,
+    symbol::IF,
+    symbol::EVAL,
+    symbol::ERROR,
+    symbol::SET,
+    symbol::LETREC,
+    symbol::LETREC_STAR,
+    symbol::SYNTAX_RULES,
+    symbol::LET_SYNTAX,
+    symbol::LETREC_SYNTAX,
+    symbol::DEFINE,
+    symbol::DEFINE_MACRO,
+    symbol::DEFINE_SYNTAX,
+];
+
+/// compiles an expression into a function of no arguments which evaluates it
+pub fn compile(expr: &Value) -> Rc<Function> {
+    let mut compiler = Compiler {
+        builders: vec![Builder::default()],
+    };
+    compiler.expr(expr, true);
+    compiler.emit(Op::Return);
+    let builder = compiler
+        .builders
+        .pop()
+        .expect("the top level is being built");
+    let params = Params::parse(&[]).expect("no parameters are valid parameters");
+    Rc::new(builder.finish(params, 0, false))
+}
+
+/// compiles a define form into the name it defines, and a function evaluating its value,
+/// unless it defines something the compiler does not handle, such as a macro
+pub fn compile_definition(define: &[Value]) -> Option<(Symbol, Rc<Function>)> {
+    match define {
+        [Value::Symbol(symbol::DEFINE, _), Value::Symbol(name, _), value] => {
+            Some((*name, compile(value)))
+        }
+        [Value::Symbol(symbol::DEFINE, _), Value::List(name_params, _), body] => {
+            let [Value::Symbol(name, _), params @ ..] = name_params.as_ref() else {
+                return None;
+            };
+            let mut compiler = Compiler {
+                builders: vec![Builder::default()],
+            };
+            let function = compiler.function(params, body)?;
+            compiler.emit(Op::Closure(function));
+            compiler.emit(Op::Return);
+            let builder = compiler
+                .builders
+                .pop()
+                .expect("the top level is being built");
+            let params = Params::parse(&[]).expect("no parameters are valid parameters");
+            Some((*name, Rc::new(builder.finish(params, 0, false))))
+        }
+        _ => None,
+    }
+}
+
+/// a function being compiled
+#[derive(Default)]
+struct Builder {
+    code: Vec<Op>,
+    constants: Vec<Value>,
+    functions: Vec<Rc<Function>>,
+    scopes: Vec<Scope>,
+    /// the locals in scope, innermost last, with their slots
+    locals: Vec<(Symbol, usize)>,
+    /// the name of each slot
+    names: Vec<Symbol>,
+}
+
+impl Builder {
+    fn finish(self, params: Params, required: usize, rest: bool) -> Function {
+        Function {
+            params: Rc::new(params),
+            required,
+            rest,
+            names: Rc::from(self.names),
+            code: self.code,
+            constants: self.constants,
+            functions: self.functions,
+            scopes: self.scopes,
+        }
+    }
+}
+
+/// a local definition, see [Compiler::definitions]
+enum Definition<'a> {
+    Value(&'a Value),
+    Lambda(&'a [Value], &'a Value),
+}
+
+struct Compiler {
+    /// the functions being compiled, innermost last
+    builders: Vec<Builder>,
+}
+
+impl Compiler {
+    fn builder(&mut self) -> &mut Builder {
+        self.builders.last_mut().expect("a function is being built")
+    }
+
+    fn emit(&mut self, op: Op) -> usize {
+        let code = &mut self.builder().code;
+        code.push(op);
+        code.len() - 1
+    }
+
+    /// points the jump at `at` to the next instruction
+    fn patch(&mut self, at: usize) {
+        let to = self.builder().code.len();
+        match &mut self.builder().code[at] {
+            Op::Jump(target) | Op::JumpUnless(target) | Op::Expand { to: target, .. } => {
+                *target = to
+            }
+            op => unreachable!("only jumps are patched, not {op:?}"),
+        }
+    }
+
+    fn constant(&mut self, value: Value) -> usize {
+        let constants = &mut self.builder().constants;
+        constants.push(value);
+        constants.len() - 1
+    }
+
+    /// a new slot for `name` in the frame of the function being compiled.
+    /// slots are not reused, so each is only ever bound to one name
+    fn slot(&mut self, name: Symbol) -> usize {
+        let names = &mut self.builder().names;
+        names.push(name);
+        names.len() - 1
+    }
+
+    fn bind(&mut self, name: Symbol, slot: usize) {
+        self.builder().locals.push((name, slot));
+    }
+
+    /// the depth and index of the local `name` is bound to, if it is a local
+    fn resolve(&self, name: Symbol) -> Option<(usize, usize)> {
+        self.builders
+            .iter()
+            .rev()
+            .enumerate()
+            .find_map(|(depth, builder)| {
+                let (_, index) = builder
+                    .locals
+                    .iter()
+                    .rev()
+                    .find(|(local, _)| *local == name)?;
+                Some((depth, *index))
+            })
+    }
+
+    /// compiles `expr`, leaving its value on the stack.
+    /// calls in tail position replace the function being compiled
+    fn expr(&mut self, expr: &Value, tail: bool) {
+        match expr {
+            Value::Symbol(_, _) if params::keyword(expr).is_some() => {
+                let constant = self.constant(expr.clone());
+                self.emit(Op::Const(constant));
+            }
+            Value::Symbol(name, _) => {
+                let constant = self.constant(expr.clone());
+                match self.resolve(*name) {
+                    Some((depth, index)) => self.emit(Op::Local {
+                        depth,
+                        index,
+                        name: constant,
+                    }),
+                    None => self.emit(Op::Global(constant)),
+                };
+            }
+            Value::List(items, _) if !items.is_empty() => self.form(expr, items, tail),
+            Value::List(_, _) => self.interpret(expr),
+            _ => {
+                let constant = self.constant(expr.clone());
+                self.emit(Op::Const(constant));
+            }
+        }
+    }
+
+    fn form(&mut self, expr: &Value, items: &[Value], tail: bool) {
+        let form = match &items[0] {
+            Value::Symbol(name, _) if FORMS.contains(name) && self.resolve(*name).is_none() => {
+                Some(*name)
+            }
+            _ => None,
+        };
+        let compiled = match (form, &items[1..]) {
+            (None, args) => {
+                self.call(expr, &items[0], args, tail);
+                true
+            }
+            (Some(symbol::QUOTE), [quoted]) => {
+                let constant = self.constant(quoted.clone());
+                self.emit(Op::Const(constant));
+                true
+            }
+            (Some(symbol::IF), [cond, pass, fail]) => {
+                self.expr(cond, false);
+                let unless = self.emit(Op::JumpUnless(0));
+                self.expr(pass, tail);
+                let end = self.emit(Op::Jump(0));
+                self.patch(unless);
+                self.expr(fail, tail);
+                self.patch(end);
+                true
+            }
+            (Some(symbol::LAMBDA), [params @ Value::List(_, _), body]) => {
+                let Value::List(params, _) = params else {
+                    unreachable!()
+                };
+                self.lambda(params, body)
+            }
+            (Some(symbol::LAMBDA), [rest @ Value::Symbol(_, _), body]) => {
+                self.lambda(&[Value::Symbol(symbol::REST, None), rest.clone()], body)
+            }
+            (Some(symbol::LET), [Value::Symbol(name, _), Value::List(bindings, _), body]) => {
+                self.named_let(expr, *name, bindings, body, tail)
+            }
+            (Some(symbol::LET), [Value::List(bindings, _), body]) => {
+                self.bind_let(bindings, body, tail)
+            }
+            (Some(symbol::LETREC), [Value::List(bindings, _), body]) => {
+                self.letrec(bindings, body, false, tail)
+            }
+            (Some(symbol::LETREC_STAR), [Value::List(bindings, _), body]) => {
+                self.letrec(bindings, body, true, tail)
+            }
+            (Some(symbol::BEGIN), [defines @ .., body]) => self.begin(defines, body, tail),
+            (Some(symbol::SET), [Value::Symbol(name, _), value]) => {
+                self.expr(value, false);
+                match self.resolve(*name) {
+                    Some((depth, index)) => self.emit(Op::SetLocal { depth, index }),
+                    None => {
+                        let constant = self.constant(items[1].clone());
+                        self.emit(Op::SetGlobal(constant))
+                    }
+                };
+                let unit = self.constant(Value::List(List::from([]), None));
+                self.emit(Op::Const(unit));
+                true
+            }
+            _ => false,
+        };
+        if !compiled {
+            self.interpret(expr);
+        }
+    }
+
+    /// leaves `expr` to be evaluated as a tree, with the locals in scope bound by name
+    fn interpret(&mut self, expr: &Value) {
+        let scope = self.scope();
+        let expr = self.constant(expr.clone());
+        self.emit(Op::Interpret { expr, scope });
+    }
+
+    /// the locals in scope, as the index of a scope of the function being compiled
+    fn scope(&mut self) -> usize {
+        let scope: Scope = self
+            .builders
+            .iter()
+            .rev()
+            .enumerate()
+            .flat_map(|(depth, builder)| {
+                builder
+                    .locals
+                    .iter()
+                    .rev()
+                    .map(move |(name, index)| (*name, depth, *index))
+            })
+            .collect();
+        let scopes = &mut self.builder().scopes;
+        scopes.push(scope);
+        scopes.len() - 1
+    }
+
+    fn call(&mut self, expr: &Value, procedure: &Value, args: &[Value], tail: bool) {
+        // a procedure in the call itself, such as the binder of an expansion's renames,
+        // may not evaluate its arguments
+        if let Value::Procedure(_, _, _) = procedure {
+            return self.interpret(expr);
+        }
+        self.expr(procedure, false);
+        let call = self.constant(expr.clone());
+        let scope = self.scope();
+        let expand = self.emit(Op::Expand { call, scope, to: 0 });
+        for arg in args {
+            self.expr(arg, false);
+        }
+        let argc = args.len();
+        match tail {
+            true => self.emit(Op::TailCall { argc, call }),
+            false => self.emit(Op::Call { argc, call }),
+        };
+        self.patch(expand);
+    }
+
+    /// compiles a function, giving its index among the functions of the one being compiled.
+    /// parameters which are optional or keys are not compiled
+    fn function(&mut self, params: &[Value], body: &Value) -> Option<usize> {
+        let params = Params::parse(params).ok()?;
+        let (required, rest) = params.positional()?;
+        let (required, rest) = (required.to_vec(), rest);
+        self.builders.push(Builder::default());
+        for name in required.iter().copied().chain(rest) {
+            let slot = self.slot(name);
+            self.bind(name, slot);
+        }
+        self.expr(body, true);
+        self.emit(Op::Return);
+        let builder = self.builders.pop().expect("the function is being built");
+        let function = builder.finish(params, required.len(), rest.is_some());
+        let functions = &mut self.builder().functions;
+        functions.push(Rc::new(function));
+        Some(functions.len() - 1)
+    }
+
+    fn lambda(&mut self, params: &[Value], body: &Value) -> bool {
+        match self.function(params, body) {
+            Some(function) => {
+                self.emit(Op::Closure(function));
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// `((name value)...)`, where names bound more than once are left to the errors of [eval]
+    fn bindings(bindings: &[Value]) -> Option<Vec<(Symbol, &Value)>> {
+        let mut bound = Vec::with_capacity(bindings.len());
+        bindings
+            .iter()
+            .map(|binding| match binding {
+                Value::List(parts, _) => match parts.as_ref() {
+                    [symbol @ Value::Symbol(name, _), value] => {
+                        params::bind_once(&mut bound, *name, symbol).ok()?;
+                        Some((*name, value))
+                    }
+                    _ => None,
+                },
+                _ => None,
+            })
+            .collect()
+    }
+
+    fn bind_let(&mut self, bindings: &[Value], body: &Value, tail: bool) -> bool {
+        let Some(bindings) = Self::bindings(bindings) else {
+            return false;
+        };
+        // the values are evaluated before any of the names are bound
+        let mut slots = Vec::with_capacity(bindings.len());
+        for (name, value) in &bindings {
+            let slot = self.slot(*name);
+            self.expr(value, false);
+            self.emit(Op::SetLocal {
+                depth: 0,
+                index: slot,
+            });
+            slots.push(slot);
+        }
+        let scope = self.builder().locals.len();
+        for ((name, _), slot) in bindings.iter().zip(slots) {
+            self.bind(*name, slot);
+        }
+        self.expr(body, tail);
+        self.builder().locals.truncate(scope);
+        true
+    }
+
+    /// `(let name ((binding value)...) body)`, binding `name` to a procedure of the
+    /// bindings within body, and calling it with the values
+    fn named_let(
+        &mut self,
+        expr: &Value,
+        name: Symbol,
+        bindings: &[Value],
+        body: &Value,
+        tail: bool,
+    ) -> bool {
+        let Some(bindings) = Self::bindings(bindings) else {
+            return false;
+        };
+        let params: Vec<_> = bindings
+            .iter()
+            .map(|(name, _)| Value::Symbol(*name, None))
+            .collect();
+        let slot = self.slot(name);
+        let scope = self.builder().locals.len();
+        self.bind(name, slot);
+        let Some(function) = self.function(&params, body) else {
+            self.builder().locals.truncate(scope);
+            return false;
+        };
+        self.builder().locals.truncate(scope);
+        self.emit(Op::Closure(function));
+        self.emit(Op::SetLocal {
+            depth: 0,
+            index: slot,
+        });
+        let name = self.constant(Value::Symbol(name, None));
+        self.emit(Op::Local {
+            depth: 0,
+            index: slot,
+            name,
+        });
+        for (_, value) in &bindings {
+            self.expr(value, false);
+        }
+        let call = self.constant(expr.clone());
+        let argc = bindings.len();
+        match tail {
+            true => self.emit(Op::TailCall { argc, call }),
+            false => self.emit(Op::Call { argc, call }),
+        };
+        true
+    }
+
+    fn letrec(&mut self, bindings: &[Value], body: &Value, sequential: bool, tail: bool) -> bool {
+        match Self::bindings(bindings) {
+            Some(bindings) => {
+                let definitions = bindings
+                    .into_iter()
+                    .map(|(name, value)| (name, Definition::Value(value)))
+                    .collect();
+                self.definitions(definitions, body, sequential, tail)
+            }
+            None => false,
+        }
+    }
+
+    /// `(begin define-form... body)` where each define form defines a value or a procedure
+    fn begin(&mut self, defines: &[Value], body: &Value, tail: bool) -> bool {
+        let definitions = defines
+            .iter()
+            .map(|define| match define {
+                Value::List(define, _) => match define.as_ref() {
+                    [Value::Symbol(symbol::DEFINE, _), Value::Symbol(name, _), value] => {
+                        Some((*name, Definition::Value(value)))
+                    }
+                    [Value::Symbol(symbol::DEFINE, _), Value::List(name_params, _), body] => {
+                        match name_params.as_ref() {
+                            [Value::Symbol(name, _), params @ ..] => {
+                                Some((*name, Definition::Lambda(params, body)))
+                            }
+                            _ => None,
+                        }
+                    }
+                    _ => None,
+                },
+                _ => None,
+            })
+            .collect();
+        match definitions {
+            Some(definitions) => self.definitions(definitions, body, true, tail),
+            None => false,
+        }
+    }
+
+    /// binds every name before evaluating the values, like [crate::interpreter::inbuilt::letrec].
+    /// with `sequential`, each value is bound as soon as it is evaluated
+    fn definitions(
+        &mut self,
+        definitions: Vec<(Symbol, Definition<'_>)>,
+        body: &Value,
+        sequential: bool,
+        tail: bool,
+    ) -> bool {
+        let scope = self.builder().locals.len();
+        let mut slots = Vec::with_capacity(definitions.len());
+        for (name, _) in &definitions {
+            let slot = self.slot(*name);
+            self.bind(*name, slot);
+            slots.push(slot);
+        }
+        for ((_, definition), slot) in definitions.iter().zip(&slots) {
+            match definition {
+                Definition::Value(value) => self.expr(value, false),
+                Definition::Lambda(params, body) => {
+                    if !self.lambda(params, body) {
+                        self.builder().locals.truncate(scope);
+                        return false;
+                    }
+                }
+            }
+            if sequential {
+                self.emit(Op::SetLocal {
+                    depth: 0,
+                    index: *slot,
+                });
+            }
+        }
+        if !sequential {
+            for slot in slots.iter().rev() {
+                self.emit(Op::SetLocal {
+                    depth: 0,
+                    index: *slot,
+                });
+            }
+        }
+        self.expr(body, tail);
+        self.builder().locals.truncate(scope);
+        true
+    }
+}
+
+#[test]
+fn resolution_test() {
+    use crate::interpreter::test::read;
+    // the addresses of the locals a function reads and sets, in order
+    let addresses = |function: &Function| -> Vec<(usize, usize)> {
+        function
+            .code
+            .iter()
+            .filter_map(|op| match op {
+                Op::Local { depth, index, .. } | Op::SetLocal { depth, index } => {
+                    Some((*depth, *index))
+                }
+                _ => None,
+            })
+            .collect()
+    };
+    let Value::List(forms, _) = read(
+        "(lambda (x y) (lambda (y) (list x y (let ((x 3)) x))))
+         (lambda (n) (lambda () (set! n (+ n 1))))",
+    ) else {
+        panic!()
+    };
+    // x is the slot of the frame around, the inner y shadows the outer one, and the x of
+    // the let shadows the outer x with a slot of its own in the inner frame
+    let shadowing = compile(&forms[0]);
+    assert_eq!(
+        vec![(1, 0), (0, 0), (0, 1), (0, 1)],
+        addresses(&shadowing.functions[0].functions[0])
+    );
+    // n is read and set in the slot of the frame the closure captured
+    let captured = compile(&forms[1]);
+    assert_eq!(
+        vec![(1, 0), (1, 0)],
+        addresses(&captured.functions[0].functions[0])
+    );
+}
@@ -0,0 +1,526 @@
+//! a compiler from expressions to bytecode, and a stack machine which runs it,
+//! as an alternative to evaluating the expressions as trees with [super::eval].
+//! locals are resolved to addresses as they are compiled, the number of frames out
+//! and the slot in that frame, so names are only looked up for globals,
+//! and by the expressions left to [super::eval].
+//! the machine is a step of the run it was called in: what it cannot evaluate itself,
+//! such as a call of a builtin which does not have a value straight away, is handed to
+//! that run with a frame which resumes the machine, see [Machine::suspend]
+
+mod compile;
+
+use core::fmt::{self, Display};
+use std::rc::Rc;
+
+use super::{
+    cps::then, env, env::Lookup, inbuilt, limits, params::Params, run, symbol::Symbol, Env,
+    EvalError, EvalResult, List, Tail, TailResult, Value,
+};
+
+pub use compile::{compile, compile_definition};
+
+/// an instruction of a [Function], which works on the values on top of the stack.
+/// constants, functions and scopes are indices into the tables of the function
+#[derive(Clone, Copy, Debug)]
+pub enum Op {
+    /// pushes a constant
+    Const(usize),
+    /// pushes the local in slot `index` of the frame `depth` functions out from this one,
+    /// `name` being the constant symbol it was written as
+    Local {
+        depth: usize,
+        index: usize,
+        name: usize,
+    },
+    /// pops a value into a local slot
+    SetLocal {
+        depth: usize,
+        index: usize,
+    },
+    /// pushes the value of the global named by a constant symbol
+    Global(usize),
+    /// pops a value into the global named by a constant symbol, which must already be bound
+    SetGlobal(usize),
+    /// pushes a closure of a function over the frame of this one
+    Closure(usize),
+    /// calls the procedure below `argc` arguments, replacing them with its value.
+    /// `call` is the constant expression of the call, for errors
+    Call {
+        argc: usize,
+        call: usize,
+    },
+    /// calls in tail position, so a compiled procedure replaces the function calling it
+    TailCall {
+        argc: usize,
+        call: usize,
+    },
+    /// when the value on top of the stack is a macro, replaces it with the value of the call
+    /// it expands, evaluated with the locals of a scope bound, and jumps past the call
+    Expand {
+        call: usize,
+        scope: usize,
+        to: usize,
+    },
+    Jump(usize),
+    /// pops a value, jumping if it is not truthy
+    JumpUnless(usize),
+    /// pops the value of the function, returning it to its caller
+    Return,
+    /// evaluates a constant expression the compiler does not handle as a tree,
+    /// with the locals of a scope bound
+    Interpret {
+        expr: usize,
+        scope: usize,
+    },
+}
+
+/// locals in scope, innermost first, as names with the depth and index of their slots
+type Scope = Rc<[(Symbol, usize, usize)]>;
+
+/// the code of a lambda, or of a top level form
+pub struct Function {
+    params: Rc<Params>,
+    required: usize,
+    rest: bool,
+    /// the names of the slots in a frame of the function,
+    /// for its parameters and then its other locals
+    names: Rc<[Symbol]>,
+    code: Vec<Op>,
+    constants: Vec<Value>,
+    functions: Vec<Rc<Function>>,
+    scopes: Vec<Scope>,
+}
+
+impl fmt::Debug for Function {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "function {} with slots {:?}", self.params, self.names)?;
+        for (pc, op) in self.code.iter().enumerate() {
+            writeln!(f, "{pc:>4} {op:?}")?;
+        }
+        for (i, function) in self.functions.iter().enumerate() {
+            write!(f, "function {i}: {function:?}")?;
+        }
+        Ok(())
+    }
+}
+
+/// the locals of a call, each in the slot the compiler gave it
+struct Frame {
+    locals: env::Frame<Symbol, Value>,
+    /// the frame of the function the called function was defined in
+    parent: Option<Rc<Frame>>,
+}
+
+impl Frame {
+    fn new(function: &Function, slots: Box<[Option<Value>]>, parent: Option<Rc<Self>>) -> Rc<Self> {
+        Rc::new(Frame {
+            locals: env::Frame::with_slots(function.names.clone(), slots),
+            parent,
+        })
+    }
+
+    /// the frame `depth` functions out from this one
+    fn up(self: &Rc<Self>, depth: usize) -> &Rc<Self> {
+        let mut frame = self;
+        for _ in 0..depth {
+            frame = frame
+                .parent
+                .as_ref()
+                .expect("the compiler counts the frames around a local");
+        }
+        frame
+    }
+}
+
+/// a compiled procedure
+pub struct Closure {
+    function: Rc<Function>,
+    frame: Option<Rc<Frame>>,
+    globals: Env,
+}
+
+impl Display for Closure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.function.params)
+    }
+}
+
+impl Closure {
+    /// a frame for a call with `args`
+    fn enter(&self, args: Vec<Value>) -> Result<Rc<Frame>, EvalError> {
+        let function = &self.function;
+        function.params.arity(args.len(), &args)?;
+        let mut slots = Vec::with_capacity(function.names.len());
+        let mut args = args.into_iter();
+        slots.extend(args.by_ref().take(function.required).map(Some));
+        if function.rest {
+            let rest: Vec<_> = args.collect();
+            limits::allocate(rest.len())?;
+            slots.push(Some(Value::List(List::from(rest), None)));
+        }
+        slots.resize(function.names.len(), None);
+        Ok(Frame::new(function, slots.into(), self.frame.clone()))
+    }
+}
+
+/// the locals of a frame, bound by name for [Op::Interpret]
+struct Locals {
+    scope: Scope,
+    frame: Rc<Frame>,
+}
+
+impl Lookup<Symbol, Value> for Locals {
+    fn lookup(&self, id: &Symbol) -> Option<Value> {
+        let (_, depth, index) = self.scope.iter().find(|(name, _, _)| name == id)?;
+        self.frame.up(*depth).locals.get(*index)
+    }
+
+    fn set(&self, id: &Symbol, value: Value) -> Result<(), Value> {
+        match self.scope.iter().find(|(name, _, _)| name == id) {
+            Some((_, depth, index)) => {
+                self.frame.up(*depth).locals.put(*index, value);
+                Ok(())
+            }
+            None => Err(value),
+        }
+    }
+
+    fn debug(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let last = self.scope.len().saturating_sub(1);
+        self.scope
+            .iter()
+            .enumerate()
+            .try_for_each(|(i, (name, depth, index))| {
+                match self.frame.up(*depth).locals.get(*index) {
+                    Some(value) => write!(f, "{name:#?} -> {value:#?}")?,
+                    None => write!(f, "{name:#?} -> <unset>")?,
+                }
+                if last == i {
+                    Ok(())
+                } else {
+                    writeln!(f)
+                }
+            })
+    }
+}
+
+/// compiles and runs an expression, with `globals` as its top level
+pub fn evaluate(globals: &Env, expr: &Value) -> EvalResult {
+    execute(globals, compile(expr))
+}
+
+/// runs a compiled top level form, with `globals` as its top level
+pub fn execute(globals: &Env, function: Rc<Function>) -> EvalResult {
+    let frame = Frame::new(&function, vec![None; function.names.len()].into(), None);
+    run(Machine::new(globals.clone(), Calls::default()).execute(Active::new(function, frame))?)
+}
+
+/// calls a compiled procedure with arguments which have already been evaluated,
+/// within the run it is called in
+pub fn call(closure: &Closure, args: Vec<Value>) -> TailResult {
+    let frame = closure.enter(args)?;
+    let active = Active::new(closure.function.clone(), frame);
+    Machine::new(closure.globals.clone(), Calls::default()).execute(active)
+}
+
+/// a function being run
+#[derive(Clone)]
+struct Active {
+    function: Rc<Function>,
+    frame: Rc<Frame>,
+    pc: usize,
+    /// the values the function has pushed
+    stack: Vec<Value>,
+}
+
+impl Active {
+    fn new(function: Rc<Function>, frame: Rc<Frame>) -> Self {
+        Active {
+            function,
+            frame,
+            pc: 0,
+            stack: Vec::new(),
+        }
+    }
+
+    fn pop(&mut self) -> Value {
+        self.stack.pop().expect("the compiler balances the stack")
+    }
+}
+
+/// the functions waiting for the calls they made to return, innermost first.
+/// they are shared by the machines resumed from where one was suspended, see [Machine::suspend]
+#[derive(Clone, Default)]
+struct Calls(Option<Rc<Call>>);
+
+#[derive(Clone)]
+struct Call {
+    caller: Active,
+    /// the number of functions waiting, this one included
+    depth: usize,
+    outer: Calls,
+}
+
+impl Calls {
+    fn depth(&self) -> usize {
+        self.0.as_ref().map_or(0, |call| call.depth)
+    }
+}
+
+impl Drop for Calls {
+    fn drop(&mut self) {
+        // the calls are let go of one at a time, rather than recursively
+        let mut calls = self.0.take();
+        while let Some(call) = calls {
+            calls = Rc::try_unwrap(call)
+                .ok()
+                .and_then(|mut call| call.outer.0.take());
+        }
+    }
+}
+
+struct Machine {
+    globals: Env,
+    calls: Calls,
+    /// the calls waiting in the evaluations further out
+    outer: usize,
+}
+
+impl Drop for Machine {
+    fn drop(&mut self) {
+        limits::set_depth(self.outer);
+    }
+}
+
+impl Machine {
+    fn new(globals: Env, calls: Calls) -> Self {
+        Machine {
+            globals,
+            calls,
+            outer: limits::depth(),
+        }
+    }
+
+    /// records the calls waiting on this machine, before starting an evaluation from it
+    fn nest(&self) {
+        limits::set_depth(self.outer + self.calls.depth());
+    }
+
+    /// continues with `active` once `tail` has a value, which it is given on its stack.
+    /// the tail is evaluated by the run the machine was started in, as a frame of it,
+    /// so that continuations captured within it can be resumed, any number of times
+    fn suspend(&mut self, tail: Tail, active: Active) -> TailResult {
+        let (globals, calls) = (self.globals.clone(), self.calls.clone());
+        then(tail, move |value| {
+            let mut active = active.clone();
+            active.stack.push(value);
+            Machine::new(globals.clone(), calls.clone()).execute(active)
+        })
+    }
+
+    fn execute(&mut self, mut active: Active) -> TailResult {
+        loop {
+            let op = active.function.code[active.pc];
+            active.pc += 1;
+            match op {
+                Op::Const(constant) => {
+                    let value = active.function.constants[constant].clone();
+                    active.stack.push(value);
+                }
+                Op::Local { depth, index, name } => {
+                    match active.frame.up(depth).locals.get(index) {
+                        Some(value) => active.stack.push(value),
+                        None => {
+                            return Err(EvalError::Unbound(
+                                active.function.constants[name].clone(),
+                                self.globals.clone(),
+                            ))
+                        }
+                    }
+                }
+                Op::SetLocal { depth, index } => {
+                    let value = active.pop();
+                    active.frame.up(depth).locals.put(index, value);
+                }
+                Op::Global(name) => {
+                    let symbol = &active.function.constants[name];
+                    let Value::Symbol(name, _) = symbol else {
+                        unreachable!("globals are named by symbols")
+                    };
+                    let value = Value::from_env(self.globals.clone(), *name)
+                        .map_err(|err| err.within(symbol))?;
+                    active.stack.push(value);
+                }
+                Op::SetGlobal(name) => {
+                    let value = active.pop();
+                    let symbol = &active.function.constants[name];
+                    let Value::Symbol(name, _) = symbol else {
+                        unreachable!("globals are named by symbols")
+                    };
+                    if self.globals.set(name, value).is_err() {
+                        return Err(EvalError::Unbound(symbol.clone(), self.globals.clone()));
+                    }
+                }
+                Op::Closure(function) => {
+                    let closure = Value::Closure(Rc::new(Closure {
+                        function: active.function.functions[function].clone(),
+                        frame: Some(active.frame.clone()),
+                        globals: self.globals.clone(),
+                    }));
+                    active.stack.push(closure);
+                }
+                Op::Expand { call, scope, to } => {
+                    if let Some(Value::Macro(mac)) = active.stack.last() {
+                        let mac = mac.clone();
+                        active.pop();
+                        let call = &active.function.constants[call];
+                        let Value::List(items, _) = call else {
+                            unreachable!("calls are lists")
+                        };
+                        let env = self.globals.bind(Locals {
+                            scope: active.function.scopes[scope].clone(),
+                            frame: active.frame.clone(),
+                        });
+                        self.nest();
+                        let tail = mac.call(env, items).map_err(|err| err.within(call))?;
+                        // an expanded tail call is followed by a return
+                        active.pc = to;
+                        match tail {
+                            Tail::Value(value) => active.stack.push(value),
+                            tail => return self.suspend(tail, active),
+                        }
+                    }
+                }
+                Op::Jump(to) => active.pc = to,
+                Op::JumpUnless(to) => {
+                    if !active.pop().truthy() {
+                        active.pc = to;
+                    }
+                }
+                Op::Call { argc, call } | Op::TailCall { argc, call } => {
+                    let args = active.stack.split_off(active.stack.len() - argc);
+                    let procedure = active.pop();
+                    let call = &active.function.constants[call];
+                    let within = |err: EvalError| err.within(call);
+                    limits::step(|| call.clone()).map_err(within)?;
+                    match procedure {
+                        Value::Closure(closure) => {
+                            let next = Active::new(
+                                closure.function.clone(),
+                                closure.enter(args).map_err(within)?,
+                            );
+                            match op {
+                                Op::TailCall { .. } => active = next,
+                                _ => {
+                                    let depth = self.calls.depth() + 1;
+                                    if self.outer + depth > limits::max_depth() {
+                                        return Err(limits::too_deep(call.clone()));
+                                    }
+                                    let caller = std::mem::replace(&mut active, next);
+                                    let outer = std::mem::take(&mut self.calls);
+                                    self.calls = Calls(Some(Rc::new(Call {
+                                        caller,
+                                        depth,
+                                        outer,
+                                    })));
+                                }
+                            }
+                        }
+                        // other procedures are called within the run the machine was
+                        // started in, and a tail call of one is followed by a return
+                        procedure => {
+                            self.nest();
+                            match inbuilt::apply(&procedure, &args).map_err(within)? {
+                                Tail::Value(value) => active.stack.push(value),
+                                tail => return self.suspend(tail, active),
+                            }
+                        }
+                    }
+                }
+                Op::Return => {
+                    let value = active.pop();
+                    let Some(call) = self.calls.0.take() else {
+                        return Ok(Tail::Value(value));
+                    };
+                    // the call is copied when a suspended machine shares it
+                    let call = Rc::try_unwrap(call).unwrap_or_else(|call| (*call).clone());
+                    (active, self.calls) = (call.caller, call.outer);
+                    active.stack.push(value);
+                }
+                // the expression is evaluated within the run the machine was started in
+                Op::Interpret { expr, scope } => {
+                    let env = self.globals.bind(Locals {
+                        scope: active.function.scopes[scope].clone(),
+                        frame: active.frame.clone(),
+                    });
+                    let expr = active.function.constants[expr].clone();
+                    return self.suspend(Tail::Eval(env, expr), active);
+                }
+            }
+        }
+    }
+}
+
+#[test]
+fn vm_engine_test() {
+    use crate::interpreter::{test::read, Engine, EvalError, Session};
+    let eval = |engine, source: &'static str| {
+        let mut session = Session::default();
+        session.engine(engine);
+        let program = read(source);
+        session.run(program, |_| ()).map(|last| last.unwrap().to_string()).ok()
+    };
+    for program in [
+        "(define (count n) (let loop ((i 0)) (if? (= i n) i (loop (+ i 1))))) (count 100000)",
+        "(define (adder n) (lambda (x) (+ x n))) (map (adder 2) '(1 2 3))",
+        "(letrec ((even? (lambda (n) (if? (= n 0) #t (odd? (- n 1)))))
+                  (odd? (lambda (n) (if? (= n 0) #f (even? (- n 1))))))
+           (list (even? 10) (odd? 7)))",
+        "(let ((n 1)) (let ((f (lambda () (set! n (+ n 1))))) (begin (define _ (f)) (list (f) n))))",
+        "(define (f #!optional (x 2)) x) (let ((y 3)) (list (f) (f y) `(,y)))",
+        "(define-syntax swap! (syntax-rules () ((_ a b) (let ((t a)) (begin (define _ (set! a b)) (set! b t))))))
+         (let ((x 1) (y 2)) (begin (define _ (swap! x y)) (list x y)))",
+        "((lambda (x . rest) (list x rest)) 1 2 3)",
+        "(define-macro (first args) (car args)) (let ((local first)) (list (local 1) (local 2)))",
+        "(define (f x) (let ((y 2)) (lambda (z) (eval '(list x y z))))) ((f 1) 3)",
+        "(+ 1 (call/cc (lambda (k) (+ 10 (k 5)))))",
+        "(((lambda (x y) (lambda (y) (list x y (let ((x 3)) x)))) 1 2) 4)",
+        "(define (counter) (let ((n 0)) (lambda () (let ((_ (set! n (+ n 1)))) n))))
+         (let ((a (counter)) (b (counter))) (list (a) (a) (b) (a)))",
+        "(define (f x) (+ (raise-continuable x) 1)) (with-exception-handler (lambda (e) (* e 10)) (lambda () (f 4)))",
+        "(guard (e ((error-object? e) (error-object-message e)))
+           (with-exception-handler (lambda (e) 42) (lambda () (+ 1 (raise 'oops)))))",
+        // effects performed, and continuations captured, within compiled procedures
+        // can be resumed, as the calls of the vm are frames of the run it was started in
+        "(define (ask) (perform 'ask 0)) (handle (+ 1 (ask)) (ask (v k) (k 41)))",
+        "(define (yield x) (perform 'yield x))
+         (handle (map (lambda (x) (+ 1 (yield x))) '(1 2 3)) (yield (v k) (k (* v 10))))",
+        "(let ((k (box #f)) (seen (box '())))
+           (begin
+             (define (f) (call/cc (lambda (c) (begin (define _ (set-box! k c)) 2))))
+             (define n (+ 0 (f)))
+             (define _ (set-box! seen (cons n (unbox seen))))
+             (if? (< n 4) ((unbox k) (+ n 1)) (unbox seen))))",
+        // and within the expansion of a macro called through a local, or a form not compiled
+        "(define-macro (twice args) (list 'list (car args) (car args)))
+         (let ((k (box #f)) (n (box 0)) (local twice))
+           (let ((r (local (call/cc (lambda (c) (let ((_ (set-box! k c))) 0))))))
+             (let ((_ (set-box! n (+ (unbox n) 1))))
+               (if? (< (unbox n) 3) ((unbox k) (unbox n)) r))))",
+        "(define k (box #f))
+         (define (f) `(,(call/cc (lambda (c) (begin (define _ (set-box! k c)) 2)))))
+         (let ((seen (box '())))
+           (begin
+             (define n (car (f)))
+             (define _ (set-box! seen (cons n (unbox seen))))
+             (if? (< n 4) ((unbox k) (+ n 1)) (unbox seen))))",
+    ] {
+        let tree = eval(Engine::Tree, program);
+        assert!(tree.is_some(), "{program}");
+        assert_eq!(tree, eval(Engine::Vm, program), "{program}");
+    }
+    let mut session = Session::default();
+    session.engine(Engine::Vm);
+    let program = read("((lambda (x) x) 1 2)");
+    assert!(matches!(session.run(program, |_| ()), Err(EvalError::Arity(_, _))));
+}
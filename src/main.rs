@@ -1,20 +1,117 @@
 mod fastpass;
 mod ast;
+mod cli;
 mod interpreter;
+mod repl;
 
-use std::fs;
+use std::{
+    fs,
+    io::{self, Read},
+    process,
+};
 
 use ast::sl;
+use cli::{Command, Input, Options, Print};
 use fastpass::View;
-use interpreter::interpret;
+use interpreter::Session;
+
+// exit codes, listed in [cli::USAGE]
+const EVAL_ERROR: i32 = 1;
+const USAGE_ERROR: i32 = 2;
+const SYNTAX_ERROR: i32 = 3;
+const INPUT_ERROR: i32 = 4;
 
 fn main() {
     let mut args = std::env::args();
     args.next().unwrap();
-    let file = fs::read_to_string(args.next().unwrap()).unwrap();
-    match sl(View::new(&file)) {
-        Ok(expr) => interpret(expr).unwrap(),
-        Err(err) => println!("something went wrong:\n{}", fastpass::Display(err))
+    // print is None when the inputs are only expanded
+    let (inputs, args, print, options) = match cli::parse(args) {
+        Ok(Command::Repl(options)) => return repl::run(session(options)),
+        Ok(Command::Help) => return println!("{}", cli::USAGE),
+        Ok(Command::Run { inputs, args, print, options }) => (inputs, args, Some(print), options),
+        Ok(Command::Expand { inputs, options }) => (inputs, Vec::new(), None, options),
+        Err(err) => {
+            eprintln!("{err}\n\n{}", cli::USAGE);
+            process::exit(USAGE_ERROR);
+        }
+    };
+
+    // every input is read before any of them are run
+    let mut sources = Vec::with_capacity(inputs.len());
+    for input in &inputs {
+        let source = match input {
+            Input::File(path) => fs::read_to_string(path)
+                .map(|source| (path.as_str(), source))
+                .map_err(|err| format!("unable to read {path}: {err}")),
+            Input::Stdin => {
+                let mut source = String::new();
+                io::stdin()
+                    .read_to_string(&mut source)
+                    .map(|_| ("stdin", source))
+                    .map_err(|err| format!("unable to read stdin: {err}"))
+            }
+            Input::Expr(expr) => Ok(("-e", expr.clone())),
+        };
+        match source {
+            Ok(source) => sources.push(source),
+            Err(err) => {
+                eprintln!("{err}");
+                process::exit(INPUT_ERROR);
+            }
+        }
+    }
+
+    let mut programs = Vec::with_capacity(sources.len());
+    for (name, source) in &sources {
+        match sl(View::named(name, source)) {
+            Ok(program) => programs.push(program),
+            Err(err) => {
+                eprintln!("syntax error:\n{}", fastpass::Display(err));
+                process::exit(SYNTAX_ERROR);
+            }
+        }
+    }
+
+    let mut session = session(options);
+    let script = sources.first().map(|(name, _)| name.to_string());
+    let command_line: Vec<_> = script.into_iter().chain(args).collect();
+    session.command_line(&command_line);
+
+    let Some(print) = print else {
+        for program in programs {
+            if let Err(err) = session.expand(program, |form| println!("{form}")) {
+                eprintln!("error while expanding:\n{err}");
+                process::exit(EVAL_ERROR);
+            }
+        }
+        return;
     };
+
+    let mut last = None;
+    for program in programs {
+        let each = |value: &_| {
+            if print == Print::All {
+                println!("{value}");
+            }
+        };
+        match session.run(program, each) {
+            Ok(value) => last = value,
+            Err(err) => {
+                eprintln!("error while evaluating:\n{err}");
+                process::exit(EVAL_ERROR);
+            }
+        }
+    }
+    match last {
+        Some(value) if print == Print::Last => println!("{value}"),
+        _ => (),
+    }
 }
 
+/// a session set up as the command line asks
+fn session(options: Options) -> Session {
+    let mut session = Session::new(options.capabilities);
+    session.engine(options.engine);
+    session.limits(options.limits);
+    session
+}
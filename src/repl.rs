@@ -0,0 +1,158 @@
+mod line;
+
+use std::{
+    env,
+    fs::{self, File, OpenOptions},
+    io::Write,
+    path::PathBuf,
+};
+
+use crate::ast::{sl, Malformed, UnexpectedToken};
+use crate::fastpass::{self, Either, View};
+use crate::interpreter::Session;
+use line::Input;
+
+/// inputs read before this session and during it,
+/// one line per entry, appended to the history file as they are read
+struct History {
+    entries: Vec<String>,
+    file: Option<File>,
+}
+
+impl History {
+    /// the history file is `$SL_HISTORY`, or `~/.sl_history`
+    fn load() -> Self {
+        let path = env::var_os("SL_HISTORY")
+            .map(PathBuf::from)
+            .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".sl_history")));
+        let entries = path
+            .as_ref()
+            .and_then(|path| fs::read_to_string(path).ok())
+            .map(|history| history.lines().map(String::from).collect())
+            .unwrap_or_default();
+        let file =
+            path.and_then(|path| OpenOptions::new().create(true).append(true).open(path).ok());
+        History { entries, file }
+    }
+
+    fn push(&mut self, line: &str) {
+        if line.trim().is_empty() {
+            return;
+        }
+        // the repl is still usable without a history file, so failing to write to it is ignored
+        if let Some(file) = &mut self.file {
+            let _ = writeln!(file, "{line}");
+        }
+        self.entries.push(line.to_string());
+    }
+
+    /// the entry a line recalls, `!!` for the last and `!n` for entry n of `:history`,
+    /// or None if the line is not a recall
+    fn recall(&self, line: &str) -> Option<Result<&str, String>> {
+        let recalled = match line.trim().strip_prefix('!')? {
+            "!" => self.entries.last(),
+            n => match n.parse::<usize>() {
+                Ok(n) => self.entries.get(n),
+                Err(_) => return Some(Err(format!("expected !! or !n to recall, got {line}"))),
+            },
+        };
+        Some(recalled.map(String::as_str).ok_or_else(|| format!("no history entry for {line}")))
+    }
+}
+
+#[test]
+fn recall_test() {
+    let history = History {
+        entries: vec!["(define x 1)".to_string(), "(+ x 1)".to_string()],
+        file: None,
+    };
+    assert_eq!(Some(Ok("(+ x 1)")), history.recall("!!"));
+    assert_eq!(Some(Ok("(define x 1)")), history.recall("!0"));
+    assert!(matches!(history.recall("!2"), Some(Err(_))));
+    assert!(matches!(history.recall("!x"), Some(Err(_))));
+    assert_eq!(None, history.recall("(+ x 1)"));
+}
+
+/// whether more input could complete the source, such as an unclosed s expression
+fn unclosed(err: &Either<Malformed, UnexpectedToken>) -> bool {
+    matches!(err, Either::L(Either::L(_) | Either::R(Either::L(_))))
+}
+
+#[test]
+fn unclosed_test() {
+    let unclosed = |source| match sl(View::new(source)) {
+        Err(err) => unclosed(&err),
+        Ok(_) => false,
+    };
+    assert!(unclosed("(define (f x)"));
+    assert!(unclosed("(a (b c)\n  (d"));
+    assert!(unclosed("(display \"abc"));
+    assert!(!unclosed("(a b)"));
+    assert!(!unclosed("(a b))"));
+}
+
+/// reads forms from stdin, evaluating each in `session` and printing its value,
+/// until the end of input or `:quit`
+pub fn run(mut session: Session) {
+    let mut history = History::load();
+    let mut input = String::new();
+    loop {
+        let prompt = if input.is_empty() { "sl> " } else { "... " };
+        let mut line = match line::read(prompt, &history.entries) {
+            Ok(Input::Line(line)) => line,
+            // the unfinished expression is abandoned along with the line
+            Ok(Input::Cleared) => {
+                input.clear();
+                continue;
+            }
+            Ok(Input::End) | Err(_) => {
+                if !input.trim().is_empty() {
+                    eprintln!("\nend of input within an unfinished expression");
+                }
+                println!();
+                return;
+            }
+        };
+        if input.is_empty() {
+            match line.trim() {
+                ":quit" => return,
+                ":history" => {
+                    for (i, entry) in history.entries.iter().enumerate() {
+                        println!("{i:>5}  {entry}");
+                    }
+                    continue;
+                }
+                _ => (),
+            }
+        }
+        match history.recall(&line) {
+            None => (),
+            Some(Ok(entry)) => {
+                line = entry.to_string();
+                println!("{line}");
+            }
+            Some(Err(err)) => {
+                eprintln!("{err}");
+                continue;
+            }
+        }
+        history.push(&line);
+        input.push_str(&line);
+        input.push('\n');
+
+        // values own the source they were read from, so the input can be reused once read
+        let read = match sl(View::named("repl", &input)) {
+            Err(err) if unclosed(&err) => continue,
+            read => read.map_err(|err| fastpass::Display(err).to_string()),
+        };
+        input.clear();
+        match read {
+            Err(err) => eprintln!("syntax error:\n{err}"),
+            Ok(program) => {
+                if let Err(err) = session.run(program, |value| println!("{value}")) {
+                    eprintln!("error while evaluating:\n{err}");
+                }
+            }
+        }
+    }
+}
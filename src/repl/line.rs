@@ -0,0 +1,219 @@
+//! line editing for the repl. when stdin is a terminal, the cursor moves with the arrow
+//! keys and up and down recall the entries of the history. otherwise lines are read as is
+
+use std::{
+    io::{self, BufRead, IsTerminal, Read, Write},
+    process::{Command, Stdio},
+};
+
+/// the terminal, switched to reading a key at a time while a line is edited,
+/// and switched back when dropped
+struct Raw {
+    saved: String,
+}
+
+impl Raw {
+    /// switches the terminal with `stty`, unless stdin is not a terminal or it cannot be
+    fn enable() -> Option<Raw> {
+        if !io::stdin().is_terminal() {
+            return None;
+        }
+        let saved = stty(&["-g"])?.trim().to_string();
+        stty(&["-icanon", "-echo", "-isig", "min", "1"])?;
+        Some(Raw { saved })
+    }
+}
+
+impl Drop for Raw {
+    fn drop(&mut self) {
+        stty(&[&self.saved]);
+    }
+}
+
+fn stty(args: &[&str]) -> Option<String> {
+    let output = Command::new("stty")
+        .args(args)
+        .stdin(Stdio::inherit())
+        .output()
+        .ok()?;
+    output
+        .status
+        .success()
+        .then(|| String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+#[derive(Debug, PartialEq)]
+enum Key {
+    Char(char),
+    Enter,
+    Backspace,
+    Delete,
+    Left,
+    Right,
+    Up,
+    Down,
+    Home,
+    End,
+    /// ctrl-c, which abandons the line and the unfinished expression it continues
+    Clear,
+    /// ctrl-d, which ends the input when the line is empty
+    EndOfInput,
+    Other,
+}
+
+fn byte(input: &mut impl Read) -> io::Result<Option<u8>> {
+    let mut byte = [0];
+    match input.read(&mut byte)? {
+        0 => Ok(None),
+        _ => Ok(Some(byte[0])),
+    }
+}
+
+/// reads the next key pressed, or None at the end of input
+fn key(input: &mut impl Read) -> io::Result<Option<Key>> {
+    let Some(first) = byte(input)? else {
+        return Ok(None);
+    };
+    let key = match first {
+        b'\r' | b'\n' => Key::Enter,
+        0x7f | 0x08 => Key::Backspace,
+        0x01 => Key::Home,
+        0x05 => Key::End,
+        0x03 => Key::Clear,
+        0x04 => Key::EndOfInput,
+        0x1b => match (byte(input)?, byte(input)?) {
+            (Some(b'[' | b'O'), Some(b'A')) => Key::Up,
+            (Some(b'[' | b'O'), Some(b'B')) => Key::Down,
+            (Some(b'[' | b'O'), Some(b'C')) => Key::Right,
+            (Some(b'[' | b'O'), Some(b'D')) => Key::Left,
+            (Some(b'[' | b'O'), Some(b'H')) => Key::Home,
+            (Some(b'[' | b'O'), Some(b'F')) => Key::End,
+            // followed by a ~
+            (Some(b'['), Some(b'3')) => {
+                byte(input)?;
+                Key::Delete
+            }
+            _ => Key::Other,
+        },
+        first if first < 0x20 => Key::Other,
+        first => {
+            // the bytes following the first of a utf-8 sequence
+            let following = first.leading_ones().saturating_sub(1) as usize;
+            let mut bytes = vec![first];
+            for _ in 0..following {
+                bytes.extend(byte(input)?);
+            }
+            match core::str::from_utf8(&bytes).ok().and_then(|s| s.chars().next()) {
+                Some(c) => Key::Char(c),
+                None => Key::Other,
+            }
+        }
+    };
+    Ok(Some(key))
+}
+
+/// what a call to [read] read
+pub enum Input {
+    /// a line, without its line ending
+    Line(String),
+    /// ctrl-c was pressed while the line was edited
+    Cleared,
+    End,
+}
+
+/// reads a line after printing `prompt`
+pub fn read(prompt: &str, history: &[String]) -> io::Result<Input> {
+    let mut stdout = io::stdout().lock();
+    write!(stdout, "{prompt}")?;
+    stdout.flush()?;
+    let Some(_raw) = Raw::enable() else {
+        let mut line = String::new();
+        return match io::stdin().lock().read_line(&mut line)? {
+            0 => Ok(Input::End),
+            _ => Ok(Input::Line(line.trim_end_matches(['\n', '\r']).to_string())),
+        };
+    };
+
+    let mut stdin = io::stdin().lock();
+    let mut line: Vec<char> = Vec::new();
+    let mut cursor = 0;
+    // the entry of the history shown, where the end of the history is the line being written,
+    // which is kept while an entry is shown
+    let mut shown = history.len();
+    let mut written = Vec::new();
+    loop {
+        let Some(key) = key(&mut stdin)? else {
+            return Ok(Input::End);
+        };
+        match key {
+            Key::Char(c) => {
+                line.insert(cursor, c);
+                cursor += 1;
+            }
+            Key::Enter => {
+                writeln!(stdout)?;
+                return Ok(Input::Line(line.into_iter().collect()));
+            }
+            Key::Backspace if cursor > 0 => {
+                cursor -= 1;
+                line.remove(cursor);
+            }
+            Key::Delete if cursor < line.len() => {
+                line.remove(cursor);
+            }
+            Key::Left => cursor = cursor.saturating_sub(1),
+            Key::Right => cursor = (cursor + 1).min(line.len()),
+            Key::Home => cursor = 0,
+            Key::End => cursor = line.len(),
+            Key::Up if shown > 0 => {
+                if shown == history.len() {
+                    written = line;
+                }
+                shown -= 1;
+                line = history[shown].chars().collect();
+                cursor = line.len();
+            }
+            Key::Down if shown < history.len() => {
+                shown += 1;
+                line = match history.get(shown) {
+                    Some(entry) => entry.chars().collect(),
+                    None => written.clone(),
+                };
+                cursor = line.len();
+            }
+            Key::Clear => {
+                writeln!(stdout, "^C")?;
+                return Ok(Input::Cleared);
+            }
+            Key::EndOfInput if line.is_empty() => {
+                return Ok(Input::End);
+            }
+            _ => (),
+        }
+        let text: String = line.iter().collect();
+        write!(stdout, "\r{prompt}{text}\x1b[K")?;
+        if cursor < line.len() {
+            write!(stdout, "\x1b[{}D", line.len() - cursor)?;
+        }
+        stdout.flush()?;
+    }
+}
+
+#[test]
+fn key_test() {
+    let keys = |mut input: &[u8]| {
+        let mut keys = Vec::new();
+        while let Some(key) = key(&mut input).unwrap() {
+            keys.push(key);
+        }
+        keys
+    };
+    assert_eq!(
+        vec![Key::Up, Key::Down, Key::Left, Key::Right, Key::Delete, Key::Enter],
+        keys(b"\x1b[A\x1b[B\x1b[D\x1b[C\x1b[3~\r")
+    );
+    assert_eq!(
+        vec![Key::Char('a'), Key::Char('λ'), Key::Backspace, Key::EndOfInput],
+        keys("aλ\x7f\x04".as_bytes())
+    );
+}